@@ -0,0 +1,117 @@
+// benches/gp_operators.rs
+//
+// Criterion benchmarks for the GP operators that run outside the EVM, on random
+// trees of a few representative sizes. Gives a baseline for spotting which
+// operator dominates and for checking that complexity fixes pay off.
+//
+// Usage: cargo bench --bench gp_operators
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+use offchain::compiler::ast::{Push3Ast, UntypedAst};
+use offchain::gp::generate_spec::{random_code_with_size, InstructionSet};
+use offchain::gp::mutation::{enum_nodes_dfs, size_aware_crossover};
+use offchain::gp::population_management::{calculate_population_stats, structural_distance, Individual};
+use offchain::helpers::rng::seeded_rng;
+
+/// Program sizes (in points) for the per-tree benchmarks.
+const TREE_SIZES: [usize; 3] = [10, 50, 200];
+
+/// Population sizes for `calculate_population_stats`.
+const POP_SIZES: [usize; 2] = [100, 400];
+
+fn random_tree(seed: u64, points: usize) -> UntypedAst {
+    random_code_with_size(&mut seeded_rng(seed), &InstructionSet::new_default(), points)
+}
+
+fn random_population(seed: u64, pop_size: usize, points: usize) -> Vec<Individual> {
+    let mut rng = seeded_rng(seed);
+    let set = InstructionSet::new_default();
+    (0..pop_size)
+        .map(|i| Individual::new(random_code_with_size(&mut rng, &set, points), i as f64))
+        .collect()
+}
+
+fn bench_structural_distance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("structural_distance");
+    for points in TREE_SIZES {
+        let pair = (random_tree(1, points), random_tree(2, points));
+        group.bench_with_input(BenchmarkId::from_parameter(points), &pair, |b, (x, y)| {
+            b.iter(|| structural_distance(black_box(x), black_box(y)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_enum_nodes_dfs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("enum_nodes_dfs");
+    for points in TREE_SIZES {
+        let tree = random_tree(3, points);
+        group.bench_with_input(BenchmarkId::from_parameter(points), &tree, |b, tree| {
+            b.iter(|| enum_nodes_dfs(black_box(tree)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_size_aware_crossover(c: &mut Criterion) {
+    let mut group = c.benchmark_group("size_aware_crossover");
+    for points in TREE_SIZES {
+        let parents = (random_tree(4, points), random_tree(5, points));
+        let mut rng = seeded_rng(6);
+        group.bench_with_input(BenchmarkId::from_parameter(points), &parents, |b, (p1, p2)| {
+            b.iter(|| size_aware_crossover(black_box(p1), black_box(p2), &mut rng, None))
+        });
+    }
+    group.finish();
+}
+
+fn bench_population_stats(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calculate_population_stats");
+    // All pairs are compared, so keep the sample count down for the larger population
+    group.sample_size(10);
+    for pop_size in POP_SIZES {
+        let population = random_population(7, pop_size, 30);
+        group.bench_with_input(BenchmarkId::from_parameter(pop_size), &population, |b, population| {
+            b.iter(|| calculate_population_stats(black_box(population)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_to_bytecode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("to_bytecode");
+    for points in TREE_SIZES {
+        let tree = random_tree(8, points);
+        group.bench_with_input(BenchmarkId::from_parameter(points), &tree, |b, tree| {
+            b.iter(|| black_box(tree).to_bytecode())
+        });
+    }
+    group.finish();
+}
+
+fn bench_crossover_fresh_parents(c: &mut Criterion) {
+    // Crossover on a new pair every iteration, so one lucky cut point does not dominate
+    let mut seed = 100;
+    c.bench_function("size_aware_crossover/fresh_pair_50", |b| {
+        b.iter_batched(
+            || {
+                seed += 2;
+                (random_tree(seed, 50), random_tree(seed + 1, 50), seeded_rng(seed))
+            },
+            |(p1, p2, mut rng)| size_aware_crossover(&p1, &p2, &mut rng, None),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_structural_distance,
+    bench_enum_nodes_dfs,
+    bench_size_aware_crossover,
+    bench_crossover_fresh_parents,
+    bench_population_stats,
+    bench_to_bytecode,
+);
+criterion_main!(benches);
@@ -0,0 +1,23 @@
+// benches/runner_pool.rs
+//
+// Getting a ready-to-run `EvmRunner`: a fresh deployment (`EvmRunner::new`)
+// against a copy of one deployment (`EvmRunnerPool::runner`). Needs the
+// interpreter artifact (`forge build` in onchain/, or PUSH3_ARTIFACT).
+//
+// Usage: cargo bench --bench runner_pool
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use offchain::helpers::artifact::{cached_creation_code, locate_interpreter_artifact};
+use offchain::runner::revm_runner::{EvmRunner, EvmRunnerPool};
+
+fn bench_runner_setup(c: &mut Criterion) {
+    let creation_bytes = cached_creation_code(locate_interpreter_artifact().unwrap()).unwrap();
+    let pool = EvmRunnerPool::new(creation_bytes.as_slice()).unwrap();
+
+    c.bench_function("runner_setup/deploy", |b| b.iter(|| EvmRunner::new(creation_bytes.as_slice()).unwrap()));
+    c.bench_function("runner_setup/pool", |b| b.iter(|| pool.runner()));
+}
+
+criterion_group!(benches, bench_runner_setup);
+criterion_main!(benches);
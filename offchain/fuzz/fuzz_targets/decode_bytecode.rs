@@ -0,0 +1,20 @@
+// fuzz/fuzz_targets/decode_bytecode.rs
+//
+// Feed arbitrary bytes to `bytecode_to_ast`. It must never panic, and anything
+// it decodes must encode back to exactly the same bytes (so the 0x02 / 0x04
+// framing and the sublist lengths are read the way `to_bytecode` writes them).
+//
+// Usage (from offchain/): cargo +nightly fuzz run decode_bytecode
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use offchain::compiler::ast::{bytecode_to_ast, Push3Ast};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(ast) = bytecode_to_ast(data) {
+        assert_eq!(ast.bytecode_len(), data.len());
+        assert_eq!(ast.to_bytecode(), data, "re-encoding {}", ast);
+    }
+});
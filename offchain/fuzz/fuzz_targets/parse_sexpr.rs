@@ -0,0 +1,37 @@
+// fuzz/fuzz_targets/parse_sexpr.rs
+//
+// Feed arbitrary strings to the S-expression parser. It must never panic, and
+// whatever it accepts must survive a print / re-parse round trip.
+//
+// Usage (from offchain/): cargo +nightly fuzz run parse_sexpr
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use offchain::compiler::ast::{parse_string_to_sexpr, sexpr_to_untyped, tokenize};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    // Tokens never contain whitespace-only or empty strings
+    for token in tokenize(text) {
+        assert!(!token.trim().is_empty(), "empty token from {:?}", text);
+    }
+
+    let Ok(sexpr) = parse_string_to_sexpr(text) else {
+        return;
+    };
+    let Ok(ast) = sexpr_to_untyped(&sexpr) else {
+        return;
+    };
+
+    // Printing and re-parsing gives back the same program
+    let printed = ast.to_string();
+    let reparsed = parse_string_to_sexpr(&printed)
+        .and_then(|sexpr| sexpr_to_untyped(&sexpr))
+        .unwrap_or_else(|e| panic!("{:?} printed as {:?} does not parse: {}", text, printed, e));
+    assert_eq!(ast, reparsed, "round trip of {:?} via {:?}", text, printed);
+});
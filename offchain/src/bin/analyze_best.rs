@@ -2,21 +2,22 @@
 // Quick analysis of best evolved solution
 
 use anyhow::Result;
+use std::io;
 use rand::{thread_rng, Rng};
 
 // Our GP + compiler modules 
-use offchain::compiler::ast::{UntypedAst, Push3Ast};
-use offchain::helpers::artifact::get_creation_code;
-use offchain::runner::revm_runner::{
-    EvmRunner, 
-    Push3InterpreterInputs,
+use offchain::compiler::ast::UntypedAst;
+use offchain::helpers::artifact::{get_creation_code, locate_interpreter_artifact};
+use offchain::runner::revm_runner::EvmRunner;
+use offchain::gp::analysis::{
+    describe_ast, print_sample_table, sample_rows, stack_rows, write_stack_table, DescribeOptions, ErrorSummary,
 };
-use offchain::compiler::push3_describtor::make_sublist_descriptor;
+use offchain::gp::fitness::{abs_error, evaluate_ast_on_x, ParsimonyPressure};
 
 // Enhanced GP operators
 use offchain::gp::generate_spec::ranmdom_code_fixed;
 use offchain::gp::mutation::{
-    point_mutate, size_aware_crossover, size_limited_mutate, get_subtree_size
+    point_mutate, size_aware_crossover, size_limited_mutate
 };
 
 // Advanced population management
@@ -24,11 +25,11 @@ use offchain::gp::population_management::{
     Individual, calculate_population_stats,
     diverse_elitism, apply_fitness_sharing, age_population,
     diverse_tournament_selection, calculate_novelty_score,
-    enforce_minimum_diversity
+    enforce_minimum_diversity, sort_population
 };
 
 /// Generate target function samples
-fn generate_samples() -> Vec<(i32, i32)> {
+fn generate_samples() -> Vec<(i128, i128)> {
     let mut samples = Vec::new();
     for x in -10..=10 {
         let y = x * x * x - 2 * x * x + 3 * x + 5;
@@ -37,41 +38,11 @@ fn generate_samples() -> Vec<(i32, i32)> {
     samples
 }
 
-/// Evaluate AST on single input
-fn evaluate_ast_on_x(
-    runner: &mut EvmRunner,
-    ast: &UntypedAst,
-    x: i32,
-) -> i32 {
-    let code_bytes = ast.to_bytecode();
-    let code_len = code_bytes.len() as u32;
-    let descriptor = make_sublist_descriptor(0, code_len);
-
-    let inputs = Push3InterpreterInputs {
-        code: code_bytes,
-        init_code_stack: Vec::new(),
-        init_exec_stack: vec![descriptor],
-        init_int_stack: vec![x as i128],
-        init_bool_stack: Vec::new(),
-    };
-
-    let outputs = match runner.run_interpreter(&inputs) {
-        Ok(o) => o,
-        Err(_) => return i32::MAX,
-    };
-
-    if outputs.final_int_stack.is_empty() {
-        i32::MAX
-    } else {
-        *outputs.final_int_stack.last().unwrap() as i32
-    }
-}
-
 /// Enhanced fitness function for expanded instruction set
 fn evaluate_fitness(
     runner: &mut EvmRunner,
     ast: &UntypedAst,
-    samples: &[(i32, i32)]
+    samples: &[(i128, i128)]
 ) -> f64 {
     let mut total_fitness = 0.0;
     let mut successful_evaluations = 0;
@@ -79,11 +50,9 @@ fn evaluate_fitness(
     for &(x, target_y) in samples {
         let predicted = evaluate_ast_on_x(runner, ast, x);
         
-        if predicted == i32::MAX {
-            total_fitness += 0.1;
-        } else {
+        if let Some(predicted) = predicted {
             successful_evaluations += 1;
-            let diff = (predicted - target_y).abs() as f64;
+            let diff = abs_error(predicted, target_y) as f64;
             
             let sample_fitness = if diff == 0.0 {
                 2000.0
@@ -100,6 +69,8 @@ fn evaluate_fitness(
             };
             
             total_fitness += sample_fitness;
+        } else {
+            total_fitness += 0.1;
         }
     }
     
@@ -111,15 +82,13 @@ fn evaluate_fitness(
     }
     
     // Enhanced parsimony pressure for larger search space
-    let size_penalty = match get_subtree_size(ast) {
-        s if s > 50 => 0.7,
-        s if s > 35 => 0.8,
-        s if s > 25 => 0.9,
-        s if s > 15 => 0.95,
-        _ => 1.0,
-    };
-    
-    (total_fitness / samples.len() as f64) * size_penalty
+    let parsimony = ParsimonyPressure::Banded(vec![
+        (50, 0.7),
+        (35, 0.8),
+        (25, 0.9),
+        (15, 0.95),
+    ]);
+    parsimony.apply(total_fitness / samples.len() as f64, ast)
 }
 
 fn main() -> Result<()> {
@@ -128,8 +97,7 @@ fn main() -> Result<()> {
     let samples = generate_samples();
     println!("Target function: f(x) = x³ - 2x² + 3x + 5");
     
-    let creation_hex_filename = "../onchain/out/Push3Interpreter.sol/Push3Interpreter.json";
-    let creation_bytes = get_creation_code(creation_hex_filename)?;
+    let creation_bytes = get_creation_code(locate_interpreter_artifact()?)?;
     let mut runner = EvmRunner::new(creation_bytes)?;
 
     // Run quick evolution
@@ -150,7 +118,7 @@ fn main() -> Result<()> {
     let mut best_overall: Option<Individual> = None;
 
     for gen in 0..generations {
-        population.sort_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap());
+        sort_population(&mut population);
         
         if best_overall.is_none() || population[0].fitness > best_overall.as_ref().unwrap().fitness {
             best_overall = Some(population[0].clone());
@@ -173,7 +141,7 @@ fn main() -> Result<()> {
                 // Crossover
                 let p1 = rng.gen_range(0..elite_count.min(50));
                 let p2 = rng.gen_range(0..elite_count.min(50));
-                let (child, _) = size_aware_crossover(&population[p1].ast, &population[p2].ast, &mut rng);
+                let (child, _) = size_aware_crossover(&population[p1].ast, &population[p2].ast, &mut rng, Some(max_size));
                 let fitness = evaluate_fitness(&mut runner, &child, &samples);
                 new_pop.push(Individual::new(child, fitness));
             } else {
@@ -198,104 +166,20 @@ fn main() -> Result<()> {
         
         println!("\n=== DETAILED ANALYSIS ===");
         println!("Target: f(x) = x³ - 2x² + 3x + 5");
-        println!("x\tTarget\tPredicted\tError\tStatus");
-        println!("─────────────────────────────────────");
-        
-        let mut perfect = 0;
-        let mut close = 0;
-        let mut failures = 0;
-        let mut total_error = 0.0;
-        
-        for &(x, target) in &samples {
-            let predicted = evaluate_ast_on_x(&mut runner, &best.ast, x);
-            let status = if predicted == i32::MAX {
-                failures += 1;
-                "FAIL".to_string()
-            } else {
-                let error = (predicted - target).abs();
-                total_error += error as f64;
-                if error == 0 {
-                    perfect += 1;
-                    "PERFECT".to_string()
-                } else if error <= 5 {
-                    close += 1;
-                    format!("CLOSE({})", error)
-                } else {
-                    format!("OFF({})", error)
-                }
-            };
-            
-            println!("{}\t{}\t{}\t{}\t{}", 
-                     x, 
-                     target, 
-                     if predicted == i32::MAX { "FAIL".to_string() } else { predicted.to_string() },
-                     if predicted == i32::MAX { "∞".to_string() } else { (predicted - target).abs().to_string() },
-                     status);
-        }
-        
-        let avg_error = if failures < samples.len() {
-            total_error / (samples.len() - failures) as f64
-        } else {
-            f64::INFINITY
-        };
+        let rows = sample_rows(&mut runner, &best.ast, &samples);
+        print_sample_table(&rows);
         
         println!("\nSUMMARY:");
-        println!("Perfect matches: {}/{}", perfect, samples.len());
-        println!("Close matches (≤5): {}/{}", close, samples.len());
-        println!("Failures: {}/{}", failures, samples.len());
-        println!("Average error: {:.1}", avg_error);
-        println!("Success rate: {:.1}%", (samples.len() - failures) as f64 / samples.len() as f64 * 100.0);
+        ErrorSummary::from_rows(&rows).print();
+
+        println!("\n=== FINAL STACKS ===");
+        let xs: Vec<i128> = samples.iter().map(|&(x, _)| x).collect();
+        write_stack_table(&stack_rows(&mut runner, &best.ast, &xs), &mut io::stdout())?;
         
         // Try to interpret the program structure
         println!("\n=== PROGRAM INTERPRETATION ===");
-        analyze_ast_structure(&best.ast, 0);
+        describe_ast(&best.ast, &mut io::stdout(), DescribeOptions::default())?;
     }
     
     Ok(())
 }
-
-fn analyze_ast_structure(ast: &UntypedAst, depth: usize) {
-    let indent = "  ".repeat(depth);
-    
-    match ast {
-        UntypedAst::IntLiteral(val) => {
-            println!("{}Constant: {}", indent, val);
-        }
-        UntypedAst::Instruction(op) => {
-            let description = match op {
-                offchain::compiler::ast::OpCode::Plus => "Add top two values",
-                offchain::compiler::ast::OpCode::Minus => "Subtract second from top",
-                offchain::compiler::ast::OpCode::Mult => "Multiply top two values",
-                offchain::compiler::ast::OpCode::Dup => "Duplicate top value",
-                offchain::compiler::ast::OpCode::Pop => "Remove top value",
-                offchain::compiler::ast::OpCode::GreaterThan => "Push (second > top) to bool stack",
-                offchain::compiler::ast::OpCode::LessThan => "Push (second < top) to bool stack",
-                offchain::compiler::ast::OpCode::Equal => "Push (second == top) to bool stack",
-                offchain::compiler::ast::OpCode::NotEqual => "Push (second != top) to bool stack",
-                offchain::compiler::ast::OpCode::GreaterEqual => "Push (second >= top) to bool stack",
-                offchain::compiler::ast::OpCode::LessEqual => "Push (second <= top) to bool stack",
-                offchain::compiler::ast::OpCode::Sin => "Sine of top value",
-                offchain::compiler::ast::OpCode::Cos => "Cosine of top value",
-                offchain::compiler::ast::OpCode::Sqrt => "Square root of top value",
-                offchain::compiler::ast::OpCode::Abs => "Absolute value of top value",
-                offchain::compiler::ast::OpCode::Mod => "second % top",
-                offchain::compiler::ast::OpCode::Pow => "second ^ top",
-                offchain::compiler::ast::OpCode::ConstPi => "Push π (3141)",
-                offchain::compiler::ast::OpCode::ConstE => "Push e (2718)",
-                offchain::compiler::ast::OpCode::ConstRand => "Push random value [0,999]",
-                offchain::compiler::ast::OpCode::BoolToInt => "Convert bool to int (0/1)",
-                offchain::compiler::ast::OpCode::IntToBool => "Convert int to bool (0=false)",
-                offchain::compiler::ast::OpCode::IfThen => "Execute next if true",
-                offchain::compiler::ast::OpCode::IfElse => "Execute then/else based on condition",
-                _ => "Other operation",
-            };
-            println!("{}Operation: {:?} - {}", indent, op, description);
-        }
-        UntypedAst::Sublist(children) => {
-            println!("{}Block with {} operations:", indent, children.len());
-            for child in children {
-                analyze_ast_structure(child, depth + 1);
-            }
-        }
-    }
-}
\ No newline at end of file
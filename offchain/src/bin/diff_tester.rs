@@ -0,0 +1,67 @@
+// src/bin/diff_tester.rs
+//
+// Feed random ASTs to both the pure-Rust interpreter and the on-chain interpreter,
+// printing every program where they disagree.
+//
+// Usage: cargo run --bin diff_tester -- [num_programs] [seed]
+
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::env;
+
+use offchain::gp::generate_spec::ranmdom_code_fixed;
+use offchain::gp::validation::{diff_test, DiffResult};
+use offchain::helpers::artifact::{get_creation_code, locate_interpreter_artifact};
+use offchain::helpers::rng::seeded_rng;
+use offchain::runner::revm_runner::EvmRunner;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let num_programs: usize = match args.get(1) {
+        Some(arg) => arg.parse()?,
+        None => 200,
+    };
+    let mut rng: StdRng = match args.get(2) {
+        Some(arg) => seeded_rng(arg.parse()?),
+        None => StdRng::from_entropy(),
+    };
+
+    // 1) Deploy the interpreter
+    let creation_bytes = get_creation_code(locate_interpreter_artifact()?)?;
+    let mut runner = EvmRunner::new(creation_bytes)?;
+    println!("Deployed interpreter at: 0x{:x}", runner.interpreter_addr);
+
+    // 2) Compare both interpreters on random programs and inputs
+    let max_points = 12;
+    let mut agreed = 0;
+    let mut skipped = 0;
+    let mut both_failed = 0;
+    let mut divergences = 0;
+
+    for i in 0..num_programs {
+        let ast = ranmdom_code_fixed(&mut rng, max_points);
+        let x: i128 = rng.gen_range(-50..=50);
+
+        let result = diff_test(&ast, &[x], &mut runner);
+        match &result {
+            DiffResult::Agree => agreed += 1,
+            DiffResult::Skipped(_) => skipped += 1,
+            DiffResult::BothFailed => both_failed += 1,
+            _ => {
+                divergences += 1;
+                println!("\n=== Divergence in program #{} (x = {}) ===", i, x);
+                println!("AST: {:?}", ast);
+                println!("Result: {:?}", result);
+            }
+        }
+    }
+
+    // 3) Summary
+    println!("\n=== Summary over {} programs ===", num_programs);
+    println!("Agree:       {}", agreed);
+    println!("Both failed: {}", both_failed);
+    println!("Skipped:     {}", skipped);
+    println!("Divergences: {}", divergences);
+    Ok(())
+}
@@ -0,0 +1,75 @@
+// src/bin/diversity_bench.rs
+//
+// Time the bucketed `enforce_minimum_diversity` against the original all-pairs
+// version on random populations, and check that both remove the same individuals.
+//
+// Usage: cargo run --release --bin diversity_bench -- [pop_size] [min_distance] [seed]
+
+use anyhow::{bail, Result};
+use rand::Rng;
+use std::env;
+use std::time::Instant;
+
+use offchain::gp::generate_spec::ranmdom_code_fixed;
+use offchain::gp::population_management::{
+    enforce_minimum_diversity, enforce_minimum_diversity_all_pairs, Individual,
+};
+use offchain::helpers::rng::seeded_rng;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let pop_size: usize = match args.get(1) {
+        Some(arg) => arg.parse()?,
+        None => 400,
+    };
+    let min_distance: f64 = match args.get(2) {
+        Some(arg) => arg.parse()?,
+        None => 0.15,
+    };
+    let seed: u64 = match args.get(3) {
+        Some(arg) => arg.parse()?,
+        None => 42,
+    };
+
+    // 1) Random population; distinct fitness values so no tie is broken at
+    //    random and both versions must agree exactly
+    let mut rng = seeded_rng(seed);
+    let population: Vec<Individual> = (0..pop_size)
+        .map(|i| {
+            let ast = ranmdom_code_fixed(&mut rng, 20);
+            Individual::new(ast, i as f64 + rng.gen::<f64>() * 0.5)
+        })
+        .collect();
+    println!("Population: {}, min_distance: {}", pop_size, min_distance);
+
+    // 2) Time both versions on the same input
+    let mut all_pairs = population.clone();
+    let start = Instant::now();
+    enforce_minimum_diversity_all_pairs(&mut all_pairs, min_distance, &mut seeded_rng(seed));
+    let all_pairs_time = start.elapsed();
+
+    let mut bucketed = population.clone();
+    let start = Instant::now();
+    enforce_minimum_diversity(&mut bucketed, min_distance, &mut seeded_rng(seed));
+    let bucketed_time = start.elapsed();
+
+    println!("all pairs: {:>10.2?}  kept {}", all_pairs_time, all_pairs.len());
+    println!("bucketed:  {:>10.2?}  kept {}", bucketed_time, bucketed.len());
+    println!(
+        "speedup:   {:.1}x",
+        all_pairs_time.as_secs_f64() / bucketed_time.as_secs_f64().max(1e-9)
+    );
+
+    // 3) Both must keep exactly the same individuals (in the same order)
+    let same = all_pairs.len() == bucketed.len()
+        && all_pairs
+            .iter()
+            .zip(&bucketed)
+            .all(|(a, b)| a.fitness == b.fitness && a.ast == b.ast);
+    if !same {
+        bail!("bucketed and all-pairs versions kept different individuals");
+    }
+    println!("Both versions kept the same individuals.");
+
+    Ok(())
+}
@@ -1,14 +1,24 @@
 use anyhow::Result;
-use rand::thread_rng;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::env;
 
 // Suppose you have these in your library:
 use offchain::gp::generate_spec::ranmdom_code_fixed;
 use offchain::gp::mutation::mutate_by_index;
-use offchain::compiler::ast::UntypedAst;
+use offchain::helpers::rng::seeded_rng;
 
 fn main() -> Result<()> {
-    let mut rng = thread_rng();
+    // Optional first argument: a u64 seed, so a run can be reproduced exactly.
+    // e.g. `cargo run --bin gp_spec_tester -- 42`
+    let mut rng: StdRng = match env::args().nth(1) {
+        Some(arg) => {
+            let seed: u64 = arg.parse()?;
+            println!("Using seed {}", seed);
+            seeded_rng(seed)
+        }
+        None => StdRng::from_entropy(),
+    };
 
     // We'll produce, say, 3 random ASTs ("subjects").
     let num_subjects = 3;
@@ -4,7 +4,7 @@ use anyhow::{anyhow, bail, Result};
 use rand::{thread_rng, Rng};
 
 // 1) We'll use the helper that reads creation code from a JSON artifact
-use offchain::helpers::artifact::get_creation_code;
+use offchain::helpers::artifact::{get_creation_code, locate_interpreter_artifact};
 
 // 2) EvmRunner to deploy & run the interpreter
 use offchain::runner::revm_runner::EvmRunner;
@@ -18,11 +18,11 @@ fn main() -> Result<()> {
     // ----------------------------------------------------------------------
     // 1) Read the creation code (Push3Interpreter) using our helper function
     // ----------------------------------------------------------------------
-    let creation_hex_filename = "../onchain/out/Push3Interpreter.sol/Push3Interpreter.json";
-    let creation_bytes = get_creation_code(creation_hex_filename)?;
+    let creation_hex_filename = locate_interpreter_artifact()?;
+    let creation_bytes = get_creation_code(&creation_hex_filename)?;
     println!(
         "Loaded creation code from {} ({} bytes)",
-        creation_hex_filename,
+        creation_hex_filename.display(),
         creation_bytes.len()
     );
 
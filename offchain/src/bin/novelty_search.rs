@@ -0,0 +1,125 @@
+// src/bin/novelty_search.rs
+//
+// Novelty search: select purely on behavioral novelty (how different a program's
+// outputs are from its neighbors'), never on fitness, and collect every distinct
+// program that happens to compute the target along the way.
+//
+// Usage: cargo run --bin novelty_search -- [generations] [seed]
+
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+use std::env;
+
+use offchain::compiler::ast::{canonicalize, UntypedAst};
+use offchain::gp::generate_spec::ranmdom_code_fixed;
+use offchain::gp::mutation::{get_subtree_size, mutate_by_index, point_mutate, size_aware_crossover};
+use offchain::gp::population_management::{
+    cache_behaviors, calculate_behavioral_novelty, structural_distance, tournament_selection,
+    Individual,
+};
+use offchain::helpers::artifact::{get_creation_code, locate_interpreter_artifact};
+use offchain::helpers::rng::seeded_rng;
+use offchain::runner::revm_runner::EvmRunner;
+
+/// Target: f(x) = 2x, which has many structurally different solutions
+/// (`(DUP +)`, `(2 *)`, `(DUP 1 * +)`, ...).
+fn target(x: i128) -> i128 {
+    2 * x
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let generations: usize = match args.get(1) {
+        Some(arg) => arg.parse()?,
+        None => 30,
+    };
+    let mut rng: StdRng = match args.get(2) {
+        Some(arg) => seeded_rng(arg.parse()?),
+        None => StdRng::from_entropy(),
+    };
+
+    let creation_bytes = get_creation_code(locate_interpreter_artifact()?)?;
+    let mut runner = EvmRunner::new(creation_bytes)?;
+
+    // 1) Probe set and the behavior a solver must show on it
+    let probes: Vec<Vec<i128>> = (-5..=5).map(|x| vec![x]).collect();
+    let target_behavior: Vec<f64> = probes.iter().map(|p| target(p[0]) as f64).collect();
+
+    let pop_size = 150;
+    let max_points = 10;
+    let mut population: Vec<Individual> = (0..pop_size)
+        .map(|_| Individual::new(ranmdom_code_fixed(&mut rng, max_points), 0.0))
+        .collect();
+
+    // Distinct solvers, keyed by canonical form
+    let mut seen: HashSet<UntypedAst> = HashSet::new();
+    let mut solvers: Vec<UntypedAst> = Vec::new();
+
+    for gen in 0..generations {
+        // (a) Behaviors are computed once per program and cached on it
+        cache_behaviors(&mut population, &mut runner, &probes);
+
+        let scores: Vec<f64> = population
+            .iter()
+            .map(|ind| calculate_behavioral_novelty(ind, &population, &mut runner, &probes))
+            .collect();
+        for (individual, score) in population.iter_mut().zip(scores) {
+            individual.novelty_score = score;
+            individual.fitness = score; // selection below only looks at novelty
+        }
+
+        // (b) Archive every new solver
+        for individual in &population {
+            if individual.behavior.as_deref() == Some(target_behavior.as_slice())
+                && seen.insert(canonicalize(&individual.ast))
+            {
+                solvers.push(individual.ast.clone());
+            }
+        }
+
+        let avg_novelty =
+            population.iter().map(|ind| ind.novelty_score).sum::<f64>() / population.len() as f64;
+        println!("Gen {:3}: avg novelty={:.3}, distinct solvers={}", gen, avg_novelty, solvers.len());
+
+        // (c) Next generation, parents chosen by novelty tournament
+        let mut next = Vec::with_capacity(pop_size);
+        while next.len() < pop_size {
+            let parent1 = tournament_selection(&population, 5, &mut rng);
+            if rng.gen::<f64>() < 0.5 {
+                let parent2 = tournament_selection(&population, 5, &mut rng);
+                let (child1, child2) = size_aware_crossover(&parent1.ast, &parent2.ast, &mut rng, None);
+                next.push(Individual::new(child1, 0.0));
+                next.push(Individual::new(child2, 0.0));
+            } else if rng.gen::<bool>() {
+                next.push(Individual::new(point_mutate(&parent1.ast, &mut rng, 0.2), 0.0));
+            } else {
+                next.push(Individual::new(mutate_by_index(&parent1.ast, &mut rng, max_points), 0.0));
+            }
+        }
+        next.truncate(pop_size);
+        population = next;
+    }
+
+    // 2) Report the solvers, smallest first, and how different they are
+    println!("\n=== {} distinct solvers of f(x) = 2x ===", solvers.len());
+    solvers.sort_by_key(get_subtree_size);
+    for (i, ast) in solvers.iter().take(10).enumerate() {
+        println!("#{}: {:?}", i + 1, ast);
+    }
+
+    if solvers.len() >= 2 {
+        let mut total = 0.0;
+        let mut pairs = 0;
+        for i in 0..solvers.len() {
+            for j in (i + 1)..solvers.len() {
+                total += structural_distance(&solvers[i], &solvers[j]);
+                pairs += 1;
+            }
+        }
+        println!("Average structural distance between solvers: {:.3}", total / pairs as f64);
+    }
+
+    Ok(())
+}
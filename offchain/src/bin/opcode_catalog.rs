@@ -0,0 +1,70 @@
+// src/bin/opcode_catalog.rs
+//
+// Print every `OpCode` with its byte in `DefaultOpCodeMapping`, its S-expression
+// symbol, its stack effect and its description, all taken from the library
+// tables. Mapping mismatches (a duplicated byte, a byte or symbol that does not
+// map back to the same opcode) are listed at the end and make the command fail.
+//
+// Usage: cargo run --bin opcode_catalog
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+use offchain::compiler::ast::{
+    parse_string_to_sexpr, sexpr_to_untyped, OpCode, OpCodeMapping, UntypedAst, DEFAULT_OP_MAPPING,
+};
+use offchain::compiler::stack_effect::opcode_effect;
+
+fn main() -> Result<()> {
+    let mut problems = Vec::new();
+    let mut by_byte: BTreeMap<u8, Vec<&OpCode>> = BTreeMap::new();
+
+    println!("{:<6} {:<13} {:<12} {:<9} {:<9} Description", "Byte", "OpCode", "Symbol", "Int", "Bool");
+    println!("{}", "-".repeat(100));
+
+    for op in OpCode::ALL.iter() {
+        let byte = DEFAULT_OP_MAPPING.opcode_byte(op);
+        let symbol = op.mnemonic();
+        let effect = opcode_effect(op);
+        println!(
+            "0x{:02x}   {:<13} {:<12} {:<9} {:<9} {}",
+            byte,
+            format!("{:?}", op),
+            symbol,
+            format!("-{} {:+}", effect.int_needed, effect.int_delta),
+            format!("-{} {:+}", effect.bool_needed, effect.bool_delta),
+            op.description()
+        );
+
+        by_byte.entry(byte).or_default().push(op);
+        if DEFAULT_OP_MAPPING.opcode_from_byte(byte).as_ref() != Some(op) {
+            problems.push(format!("byte 0x{:02x} does not decode back to {:?}", byte, op));
+        }
+        let parsed = parse_string_to_sexpr(symbol).and_then(|sexpr| sexpr_to_untyped(&sexpr));
+        if parsed != Ok(UntypedAst::Instruction(op.clone())) {
+            problems.push(format!("symbol {:?} does not parse back to {:?} (got {:?})", symbol, op, parsed));
+        }
+    }
+
+    for (byte, ops) in &by_byte {
+        if ops.len() > 1 {
+            problems.push(format!("byte 0x{:02x} is shared by {:?}", byte, ops));
+        }
+        if *byte == DEFAULT_OP_MAPPING.int_literal_tag() || *byte == DEFAULT_OP_MAPPING.sublist_tag() {
+            problems.push(format!("byte 0x{:02x} of {:?} collides with a literal/sublist tag", byte, ops));
+        }
+    }
+
+    println!("\n{} opcodes. Int/Bool: operands needed, then net change.", OpCode::ALL.len());
+    if !problems.is_empty() {
+        println!("\nMapping problems:");
+        for problem in &problems {
+            println!("- {}", problem);
+        }
+        bail!("{} mapping problem(s)", problems.len());
+    }
+    println!("No mapping problems.");
+
+    Ok(())
+}
@@ -2,21 +2,20 @@
 // Quick analysis of a specific evolved solution
 
 use anyhow::Result;
+use std::io;
 use rand::thread_rng;
 
 // Our GP + compiler modules 
-use offchain::compiler::ast::{UntypedAst, Push3Ast, OpCode};
-use offchain::helpers::artifact::get_creation_code;
-use offchain::runner::revm_runner::{
-    EvmRunner, 
-    Push3InterpreterInputs,
-};
-use offchain::compiler::push3_describtor::make_sublist_descriptor;
+use offchain::compiler::ast::UntypedAst;
+use offchain::gp::analysis::{describe_ast, stack_rows, write_stack_table, DescribeOptions};
+use offchain::helpers::artifact::{get_creation_code, locate_interpreter_artifact};
+use offchain::runner::revm_runner::EvmRunner;
+use offchain::gp::fitness::{abs_error, evaluate_ast_on_x};
 use offchain::gp::generate_spec::ranmdom_code_fixed;
 use offchain::gp::mutation::get_subtree_size;
 
 /// Generate target function samples
-fn generate_samples() -> Vec<(i32, i32)> {
+fn generate_samples() -> Vec<(i128, i128)> {
     let mut samples = Vec::new();
     for x in -10..=10 {
         let y = x * x * x - 2 * x * x + 3 * x + 5;
@@ -25,40 +24,13 @@ fn generate_samples() -> Vec<(i32, i32)> {
     samples
 }
 
-/// Evaluate AST on single input
-fn evaluate_ast_on_x(runner: &mut EvmRunner, ast: &UntypedAst, x: i32) -> i32 {
-    let code_bytes = ast.to_bytecode();
-    let code_len = code_bytes.len() as u32;
-    let descriptor = make_sublist_descriptor(0, code_len);
-
-    let inputs = Push3InterpreterInputs {
-        code: code_bytes,
-        init_code_stack: Vec::new(),
-        init_exec_stack: vec![descriptor],
-        init_int_stack: vec![x as i128],
-        init_bool_stack: Vec::new(),
-    };
-
-    match runner.run_interpreter(&inputs) {
-        Ok(outputs) => {
-            if outputs.final_int_stack.is_empty() {
-                i32::MAX
-            } else {
-                *outputs.final_int_stack.last().unwrap() as i32
-            }
-        }
-        Err(_) => i32::MAX,
-    }
-}
-
 fn main() -> Result<()> {
     println!("=== Analyzing Hand-Picked Best Solution ===");
     
     let samples = generate_samples();
     println!("Target function: f(x) = x³ - 2x² + 3x + 5");
     
-    let creation_hex_filename = "../onchain/out/Push3Interpreter.sol/Push3Interpreter.json";
-    let creation_bytes = get_creation_code(creation_hex_filename)?;
+    let creation_bytes = get_creation_code(locate_interpreter_artifact()?)?;
     let mut runner = EvmRunner::new(creation_bytes)?;
 
     // Generate a few candidates and analyze the best performing one
@@ -76,11 +48,11 @@ fn main() -> Result<()> {
         
         for &(x, target) in &samples {
             let predicted = evaluate_ast_on_x(&mut runner, &ast, x);
-            if predicted == i32::MAX {
+            if let Some(predicted) = predicted {
+                total_error += abs_error(predicted, target) as f64;
+            } else {
                 failures += 1;
                 total_error += 1000.0; // Heavy penalty for failures
-            } else {
-                total_error += (predicted - target).abs() as f64;
             }
         }
         
@@ -120,11 +92,8 @@ fn main() -> Result<()> {
         
         for &(x, target) in &samples {
             let predicted = evaluate_ast_on_x(&mut runner, &ast, x);
-            let status = if predicted == i32::MAX {
-                failures += 1;
-                "FAIL".to_string()
-            } else {
-                let error = (predicted - target).abs();
+            let status = if let Some(predicted) = predicted {
+                let error = abs_error(predicted, target);
                 total_error += error as f64;
                 if error == 0 {
                     perfect += 1;
@@ -137,13 +106,16 @@ fn main() -> Result<()> {
                 } else {
                     format!("OFF({})", error)
                 }
+            } else {
+                failures += 1;
+                "FAIL".to_string()
             };
             
             println!("{}\t{}\t{}\t{}\t{}", 
                      x, 
                      target, 
-                     if predicted == i32::MAX { "FAIL".to_string() } else { predicted.to_string() },
-                     if predicted == i32::MAX { "∞".to_string() } else { (predicted - target).abs().to_string() },
+                     predicted.map_or("FAIL".to_string(), |p| p.to_string()),
+                     predicted.map_or("∞".to_string(), |p| abs_error(p, target).to_string()),
                      status);
         }
         
@@ -159,71 +131,30 @@ fn main() -> Result<()> {
         println!("Good matches (≤20): {}/{}", 
                  samples.iter().map(|&(x, target)| {
                      let predicted = evaluate_ast_on_x(&mut runner, &ast, x);
-                     if predicted != i32::MAX && (predicted - target).abs() <= 20 { 1 } else { 0 }
+                     if predicted.is_some_and(|p| abs_error(p, target) <= 20) { 1 } else { 0 }
                  }).sum::<i32>(), samples.len());
         println!("Failures: {}/{}", failures, samples.len());
         println!("Average error: {:.1}", avg_error);
         println!("Success rate: {:.1}%", (samples.len() - failures) as f64 / samples.len() as f64 * 100.0);
         
         println!("\n=== PROGRAM INTERPRETATION ===");
-        analyze_ast_structure(&ast, 0);
+        describe_ast(&ast, &mut io::stdout(), DescribeOptions { verbose: true, emoji: true })?;
         
         // Show what the program computes for a few key points
         println!("\n=== FUNCTION BEHAVIOR ===");
         for x in [-5, -1, 0, 1, 3, 5] {
             let target = x * x * x - 2 * x * x + 3 * x + 5;
             let predicted = evaluate_ast_on_x(&mut runner, &ast, x);
-            if predicted != i32::MAX {
-                println!("f({}) = {} (target: {}, error: {})", x, predicted, target, (predicted - target).abs());
+            if let Some(predicted) = predicted {
+                println!("f({}) = {} (target: {}, error: {})", x, predicted, target, abs_error(predicted, target));
             }
         }
+
+        println!("\n=== FINAL STACKS ===");
+        write_stack_table(&stack_rows(&mut runner, &ast, &[-5, -1, 0, 1, 3, 5]), &mut io::stdout())?;
     } else {
         println!("No good solution found in random search");
     }
     
     Ok(())
 }
-
-fn analyze_ast_structure(ast: &UntypedAst, depth: usize) {
-    let indent = "  ".repeat(depth);
-    
-    match ast {
-        UntypedAst::IntLiteral(val) => {
-            println!("{}📊 Constant: {}", indent, val);
-        }
-        UntypedAst::Instruction(op) => {
-            let (symbol, description) = match op {
-                OpCode::Plus => ("➕", "Add top two values"),
-                OpCode::Minus => ("➖", "Subtract second from top"),
-                OpCode::Mult => ("✖️", "Multiply top two values"),
-                OpCode::Dup => ("📋", "Duplicate top value"),
-                OpCode::Pop => ("🗑️", "Remove top value"),
-                OpCode::GreaterThan => ("🔍>", "Push (second > top) to bool stack"),
-                OpCode::LessThan => ("🔍<", "Push (second < top) to bool stack"),
-                OpCode::Equal => ("🔍=", "Push (second == top) to bool stack"),
-                OpCode::NotEqual => ("🔍≠", "Push (second != top) to bool stack"),
-                OpCode::Abs => ("📏", "Absolute value of top"),
-                OpCode::Sqrt => ("√", "Square root of top"),
-                OpCode::Pow => ("^", "second raised to power of top"),
-                OpCode::Mod => ("%", "second modulo top"),
-                OpCode::Sin => ("sin", "Sine of top value"),
-                OpCode::Cos => ("cos", "Cosine of top value"),
-                OpCode::ConstPi => ("π", "Push π (3141)"),
-                OpCode::ConstE => ("e", "Push e (2718)"),
-                OpCode::ConstRand => ("🎲", "Push random [0,999]"),
-                OpCode::BoolToInt => ("bool→int", "Convert bool to 0/1"),
-                OpCode::IntToBool => ("int→bool", "Convert int to bool"),
-                OpCode::IfThen => ("if", "Execute next if true"),
-                OpCode::IfElse => ("if-else", "Branch execution"),
-                _ => ("?", "Other operation"),
-            };
-            println!("{}{} {} - {}", indent, symbol, format!("{:?}", op), description);
-        }
-        UntypedAst::Sublist(children) => {
-            println!("{}📦 Block with {} operations:", indent, children.len());
-            for child in children {
-                analyze_ast_structure(child, depth + 1);
-            }
-        }
-    }
-}
\ No newline at end of file
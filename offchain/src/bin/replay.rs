@@ -0,0 +1,85 @@
+// src/bin/replay.rs
+//
+// Run a saved program against a target over a new sample range, without
+// re-evolving, e.g. to check how it generalizes outside its training range.
+//
+// Usage: cargo run --bin replay -- <program.json> "<expr>" [min] [max]
+//
+//   cargo run --bin replay -- best.json "x^3 - 2*x^2 + 3*x + 5" 10 50
+//
+// The JSON is either a program saved with `UntypedAst::save`, or a report from
+// `EvolutionResult::report_json` (e.g. `symreg_cli --json`), whose best program
+// is used. The target uses `x` only (see `helpers::expr` for the syntax);
+// points where it is undefined are skipped.
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::io;
+
+use offchain::compiler::ast::UntypedAst;
+use offchain::gp::analysis::{describe_ast, print_sample_table, sample_rows, DescribeOptions, ErrorSummary};
+use offchain::gp::mutation::get_subtree_size;
+use offchain::helpers::artifact::{get_creation_code, locate_interpreter_artifact};
+use offchain::helpers::expr::parse_expr;
+use offchain::runner::revm_runner::EvmRunner;
+
+/// A bare program, or the `best.ast` of a JSON report.
+fn load_program(path: &str) -> Result<UntypedAst> {
+    let json = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+    let value: Value = serde_json::from_str(&json).map_err(|e| anyhow!("Failed to parse {}: {}", path, e))?;
+    let program = match value.get("best") {
+        Some(best) => best
+            .get("ast")
+            .cloned()
+            .ok_or_else(|| anyhow!("{} looks like a report, but its best program has no 'ast'", path))?,
+        None => value,
+    };
+    serde_json::from_value(program).map_err(|e| anyhow!("{} does not hold a program: {}", path, e))
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let (Some(path), Some(source)) = (args.get(1), args.get(2)) else {
+        bail!("usage: replay <program.json> \"<expr>\" [min] [max]");
+    };
+    let min: i128 = match args.get(3) {
+        Some(arg) => arg.parse()?,
+        None => -10,
+    };
+    let max: i128 = match args.get(4) {
+        Some(arg) => arg.parse()?,
+        None => 10,
+    };
+    if min > max {
+        bail!("empty sample range [{}, {}]", min, max);
+    }
+
+    let ast = load_program(path)?;
+    let target = parse_expr(source, &["x"])?;
+    let samples: Vec<(i128, i128)> =
+        (min..=max).filter_map(|x| target.eval(&[x]).map(|y| (x, y))).collect();
+    if samples.is_empty() {
+        bail!("target is undefined on the whole sample range");
+    }
+
+    println!("Program (size={}): {}", get_subtree_size(&ast), ast);
+    println!("Target: f(x) = {}", source);
+    println!("Samples: {} over [{}, {}]\n", samples.len(), min, max);
+
+    let creation_bytes = get_creation_code(locate_interpreter_artifact()?)?;
+    let mut runner = EvmRunner::new(creation_bytes)?;
+    runner.self_test()?;
+
+    let rows = sample_rows(&mut runner, &ast, &samples);
+    print_sample_table(&rows);
+
+    println!("\nSUMMARY:");
+    ErrorSummary::from_rows(&rows).print();
+
+    println!("\n=== PROGRAM INTERPRETATION ===");
+    describe_ast(&ast, &mut io::stdout(), DescribeOptions::default())?;
+
+    Ok(())
+}
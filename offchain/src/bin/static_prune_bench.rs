@@ -0,0 +1,78 @@
+// src/bin/static_prune_bench.rs
+//
+// Count how many EVM calls `EvalOptions::static_prune` saves over one random
+// generation, and check every pruned program really fails on every sample when
+// run, so pruning never changes a fitness.
+//
+// Usage: cargo run --release --bin static_prune_bench -- [pop_size] [max_points] [--seed N]
+
+use anyhow::{bail, Result};
+use std::env;
+use std::time::Instant;
+
+use offchain::gp::fitness::{evaluate_errors_with_options, EvalOptions};
+use offchain::gp::generate_spec::ranmdom_code_fixed;
+use offchain::helpers::artifact::{get_creation_code, locate_interpreter_artifact};
+use offchain::helpers::rng::{entropy_seed, seeded_rng, take_seed_flag};
+use offchain::runner::revm_runner::EvmRunner;
+
+fn main() -> Result<()> {
+    let mut args: Vec<String> = env::args().collect();
+    let seed = take_seed_flag(&mut args)?.unwrap_or_else(entropy_seed);
+    let pop_size: usize = match args.get(1) {
+        Some(arg) => arg.parse()?,
+        None => 500,
+    };
+    let max_points: usize = match args.get(2) {
+        Some(arg) => arg.parse()?,
+        None => 15,
+    };
+
+    let creation_bytes = get_creation_code(locate_interpreter_artifact()?)?;
+    let mut runner = EvmRunner::new(creation_bytes)?;
+
+    let samples: Vec<(i128, i128)> = (-5..=5).map(|x| (x, x * x + 1)).collect();
+    let full = EvalOptions::default();
+    let pruning = EvalOptions { static_prune: true, ..EvalOptions::default() };
+
+    // One random generation
+    let mut rng = seeded_rng(seed);
+    let population: Vec<_> = (0..pop_size).map(|_| ranmdom_code_fixed(&mut rng, max_points)).collect();
+
+    let start = Instant::now();
+    let mut pruned_count = 0;
+    for ast in &population {
+        if pruning.provably_no_answer(ast) {
+            pruned_count += 1;
+        }
+        evaluate_errors_with_options(&mut runner, ast, &samples, &pruning);
+    }
+    let pruned_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut mismatches = 0;
+    for ast in &population {
+        let ran = evaluate_errors_with_options(&mut runner, ast, &samples, &full);
+        if pruning.provably_no_answer(ast) && ran.iter().any(Option::is_some) {
+            println!("  pruned but answered: {} => {:?}", ast, ran);
+            mismatches += 1;
+        }
+    }
+    let full_time = start.elapsed();
+
+    println!("seed {}: {} programs, {} samples each", seed, pop_size, samples.len());
+    println!(
+        "pruned {} programs ({:.1}%), saving {} of {} EVM calls",
+        pruned_count,
+        pruned_count as f64 / pop_size as f64 * 100.0,
+        pruned_count * samples.len(),
+        pop_size * samples.len()
+    );
+    println!("with pruning: {:?}, without: {:?}", pruned_time, full_time);
+    if mismatches > 0 {
+        bail!("{} pruned program(s) actually produced an answer", mismatches);
+    }
+    println!("OK");
+
+    Ok(())
+}
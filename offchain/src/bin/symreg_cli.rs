@@ -0,0 +1,138 @@
+// src/bin/symreg_cli.rs
+//
+// Symbolic regression against a target given on the command line.
+//
+// Usage: cargo run --bin symreg_cli -- [--json] [--seed N] "<expr>" [min] [max] [generations] [pop_size] [seed]
+//
+//   cargo run --bin symreg_cli -- "x^3 - 2*x^2 + 3*x + 5" -10 10
+//   cargo run --bin symreg_cli -- "x*y + x" -4 4 40
+//
+// The expression may use `x`, and optionally `y` (see `helpers::expr` for the
+// syntax). Samples are taken at every integer in [min, max], or on the
+// [min, max]² grid if `y` is used; points where the target is undefined
+// (e.g. division by zero) are skipped.
+//
+// With `--json`, nothing but a single JSON report (per-generation stats and the
+// top programs, see `EvolutionResult::report_json`) is written to stdout.
+//
+// The seed (from `--seed`, the last positional argument, or entropy) is printed
+// at startup and included in the JSON report; pass it back to replay the run.
+
+use anyhow::{bail, Result};
+use std::env;
+
+use offchain::gp::engine::{Evolution, EvolutionConfig};
+use offchain::gp::fitness::{
+    evaluate_ast_on_inputs, evaluate_errors_multi, graded_fitness_multi, MultiSample,
+};
+use offchain::gp::minimize::minimize;
+use offchain::gp::mutation::get_subtree_size;
+use offchain::helpers::artifact::{get_creation_code, locate_interpreter_artifact};
+use offchain::helpers::expr::parse_expr;
+use offchain::helpers::rng::take_seed_flag;
+use offchain::runner::revm_runner::EvmRunner;
+
+fn main() -> Result<()> {
+    let json = env::args().any(|arg| arg == "--json");
+    let mut args: Vec<String> = env::args().filter(|arg| arg != "--json").collect();
+    let seed_flag = take_seed_flag(&mut args)?;
+    let Some(source) = args.get(1) else {
+        bail!("usage: symreg_cli [--json] [--seed N] \"<expr>\" [min] [max] [generations] [pop_size] [seed]");
+    };
+    let min: i128 = match args.get(2) {
+        Some(arg) => arg.parse()?,
+        None => -5,
+    };
+    let max: i128 = match args.get(3) {
+        Some(arg) => arg.parse()?,
+        None => 5,
+    };
+    let generations: usize = match args.get(4) {
+        Some(arg) => arg.parse()?,
+        None => 30,
+    };
+    let pop_size: usize = match args.get(5) {
+        Some(arg) => arg.parse()?,
+        None => 200,
+    };
+    let seed: Option<u64> = match args.get(6) {
+        Some(arg) => Some(arg.parse()?),
+        None => seed_flag,
+    };
+    if min > max {
+        bail!("empty sample range [{}, {}]", min, max);
+    }
+
+    // 1) Parse the target and sample it
+    let target = parse_expr(source, &["x", "y"])?;
+    let two_vars = target.uses_var(1);
+    let mut samples: Vec<MultiSample> = Vec::new();
+    for x in min..=max {
+        if two_vars {
+            for y in min..=max {
+                if let Some(z) = target.eval(&[x, y]) {
+                    samples.push((vec![x, y], z));
+                }
+            }
+        } else if let Some(y) = target.eval(&[x]) {
+            samples.push((vec![x], y));
+        }
+    }
+    if samples.is_empty() {
+        bail!("target is undefined on the whole sample range");
+    }
+    if !json {
+        println!("Target: f({}) = {}", if two_vars { "x, y" } else { "x" }, source);
+        println!("Samples: {} over [{}, {}]", samples.len(), min, max);
+    }
+
+    // 2) Evolve
+    let creation_bytes = get_creation_code(locate_interpreter_artifact()?)?;
+    let mut runner = EvmRunner::new(creation_bytes)?;
+    runner.self_test()?;
+
+    let config = EvolutionConfig {
+        pop_size,
+        generations,
+        max_points: 15,
+        seed,
+        verbose: !json,
+        ..EvolutionConfig::default()
+    };
+    let result = Evolution::new(config)?
+        .stop_when(|_, best| best.fitness > 1000.0) // Max is 1200: perfect on every sample
+        .evolve_on_errors(&mut runner, |runner, ast| evaluate_errors_multi(runner, ast, &samples));
+
+    // 3) Report
+    if json {
+        println!("{}", result.report_json(10));
+        return Ok(());
+    }
+
+    let best = &result.best;
+    println!(
+        "\n=== Best program after {} generations (fitness={:.2}, size={}) ===",
+        result.generations_run, best.fitness, best.size
+    );
+    println!("{}", best.ast);
+
+    let mut exact = 0;
+    for (inputs, expected) in &samples {
+        if evaluate_ast_on_inputs(&mut runner, &best.ast, inputs) == Some(*expected) {
+            exact += 1;
+        }
+    }
+    println!("Exact on {}/{} samples", exact, samples.len());
+
+    let minimized = minimize(
+        &best.ast,
+        &mut runner,
+        |runner, ast| graded_fitness_multi(runner, ast, &samples),
+        best.fitness,
+    );
+    if minimized != best.ast {
+        println!("Minimized (size={}): {}", get_subtree_size(&minimized), minimized);
+    }
+
+    Ok(())
+}
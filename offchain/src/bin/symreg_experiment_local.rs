@@ -4,21 +4,17 @@ use anyhow::Result;
 use rand::{thread_rng, Rng};
 
 // Our GP + compiler modules (adjust paths if needed)
-use offchain::compiler::ast::{UntypedAst, Push3Ast};
-use offchain::helpers::artifact::get_creation_code;
-use offchain::runner::revm_runner::{EvmRunner, Push3InterpreterInputs};
-use offchain::compiler::push3_describtor::make_sublist_descriptor;
+use offchain::compiler::ast::UntypedAst;
+use offchain::helpers::artifact::{get_creation_code, locate_interpreter_artifact};
+use offchain::runner::revm_runner::EvmRunner;
+use offchain::gp::fitness::{abs_error, safe_eval, EvalOutcome};
 
 // Our random code and local mutation
 use offchain::gp::generate_spec::ranmdom_code_fixed;
 use offchain::gp::local_mutation::local_mutation_fixed;
 
-// Ethers + ABI
-use ethers::types::U256;
-use ethers::abi::{encode, Token};
-
 /// 1) Generate (x, y) samples for f(x) = 3x^2 + x + 3
-fn generate_samples() -> Vec<(i32, i32)> {
+fn generate_samples() -> Vec<(i128, i128)> {
     let mut samples = Vec::new();
     for x in -5..=5 {
         let y = 3 * x * x + x + 3;
@@ -27,60 +23,23 @@ fn generate_samples() -> Vec<(i32, i32)> {
     samples
 }
 
-/// 2) Evaluate a single AST on a single input `x`, returning the final top of the int stack.
-fn evaluate_ast_on_x(
-    runner: &mut EvmRunner,
-    ast: &UntypedAst,
-    x: i32,
-) -> i32 {
-    // Convert AST => push3 bytecode
-    let code_bytes = ast.to_bytecode();
-    let code_len = code_bytes.len() as u32;
-
-    // Build a sublist descriptor
-    let descriptor = make_sublist_descriptor(0, code_len);
-
-    // Provide x in `init_int_stack`
-    let inputs = Push3InterpreterInputs {
-        code: code_bytes,
-        init_code_stack: Vec::new(),
-        init_exec_stack: vec![descriptor],
-        init_int_stack: vec![x as i128],
-    };
-
-    // Run
-    let outputs = match runner.run_interpreter(&inputs) {
-        Ok(o) => o,
-        Err(_) => {
-            // If revert => 0
-            return 0;
-        }
-    };
+/// Error charged for a sample the program fails on (revert or empty stack).
+const FAILURE_ERROR: f64 = i32::MAX as f64;
 
-    if outputs.final_int_stack.is_empty() {
-        i32::MAX  // or 0, or i32::MAX to strongly penalize weird runs
-    } else {
-        *outputs.final_int_stack.last().unwrap() as i32
-    }
-}
-
-/// 3) Evaluate an AST on all samples => compute MSE
+/// 2) Evaluate an AST on all samples => compute MSE
 fn evaluate_fitness(
     runner: &mut EvmRunner,
     ast: &UntypedAst,
-    samples: &[(i32, i32)]
+    samples: &[(i128, i128)]
 ) -> f64 {
     let mut error_sum = 0.0;
-    for &(x, target_y32) in samples {
-        // cast to i64
-        let target_y = target_y32 as i64;
-        
-        // evaluate in i64
-        let predicted_i64 = evaluate_ast_on_x(runner, ast, x) as i64;
-        
-        // wrapping_sub to avoid panic
-        let diff = predicted_i64.wrapping_sub(target_y);
-        error_sum += (diff as f64) * (diff as f64);
+    for &(x, target_y) in samples {
+        // evaluate in i128; a failed run counts as a huge error
+        let diff = match safe_eval(runner, ast, &[x]) {
+            EvalOutcome::Value(predicted) => abs_error(predicted, target_y) as f64,
+            EvalOutcome::Reverted | EvalOutcome::OutOfGas | EvalOutcome::EmptyStack => FAILURE_ERROR,
+        };
+        error_sum += diff * diff;
     }
     error_sum / samples.len() as f64
 }
@@ -90,8 +49,7 @@ fn main() -> Result<()> {
     let samples = generate_samples();
 
     // 2) Setup ephemeral EVM runner
-    let creation_hex_filename = "../onchain/out/Push3Interpreter.sol/Push3Interpreter.json";
-    let creation_bytes = get_creation_code(creation_hex_filename)?;
+    let creation_bytes = get_creation_code(locate_interpreter_artifact()?)?;
     let mut runner = EvmRunner::new(creation_bytes)?;
 
     // 3) GP parameters
@@ -121,8 +79,8 @@ fn main() -> Result<()> {
             })
             .collect();
         
-        // b) Sort ascending by error
-        scored.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        // b) Sort ascending by error (a NaN error sorts last)
+        scored.sort_by(|a, b| a.1.total_cmp(&b.1));
 
         let best_err = scored[0].1;
         println!("\n=== Generation {gen} ===");
@@ -190,7 +148,7 @@ fn main() -> Result<()> {
             (ast, err)
         })
         .collect();
-    final_scored.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    final_scored.sort_by(|a, b| a.1.total_cmp(&b.1));
 
     let top_n = 10.min(final_scored.len());
     for i in 0..top_n {
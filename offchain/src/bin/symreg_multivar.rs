@@ -0,0 +1,75 @@
+// src/bin/symreg_multivar.rs
+// Symbolic regression on a function of two variables: f(x, y) = x*y + x,
+// or on the rows of a CSV file given as the argument (see `gp::dataset`).
+//
+// Usage: cargo run --bin symreg_multivar -- [--seed N] [data.csv]
+
+use anyhow::Result;
+
+use offchain::gp::dataset::load_samples_csv;
+use offchain::gp::engine::{Evolution, EvolutionConfig};
+use offchain::gp::fitness::{evaluate_ast_on_inputs, evaluate_errors_multi, MultiSample};
+use offchain::helpers::artifact::{get_creation_code, locate_interpreter_artifact};
+use offchain::helpers::rng::take_seed_flag;
+use offchain::runner::revm_runner::EvmRunner;
+
+/// (x, y) grid over [-4, 4]²; `y` ends up on top of the int stack.
+fn generate_samples() -> Vec<MultiSample> {
+    let mut samples = Vec::new();
+    for x in -4..=4 {
+        for y in -4..=4 {
+            samples.push((vec![x, y], x * y + x));
+        }
+    }
+    samples
+}
+
+fn main() -> Result<()> {
+    let mut args: Vec<String> = std::env::args().collect();
+    let seed = take_seed_flag(&mut args)?;
+    let samples = match args.get(1) {
+        Some(path) => {
+            let samples = load_samples_csv(path)?;
+            println!("Target: {} ({} samples)", path, samples.len());
+            samples
+        }
+        None => {
+            let samples = generate_samples();
+            println!("Target function: f(x, y) = x*y + x ({} samples)", samples.len());
+            samples
+        }
+    };
+
+    let creation_bytes = get_creation_code(locate_interpreter_artifact()?)?;
+    let mut runner = EvmRunner::new(creation_bytes)?;
+
+    let config = EvolutionConfig {
+        pop_size: 200,
+        generations: 30,
+        max_points: 12,
+        seed,
+        ..EvolutionConfig::default()
+    };
+
+    let result = Evolution::new(config)?
+        .stop_when(|_, best| best.fitness > 1000.0) // Max is 1200: perfect on every sample
+        .evolve_on_errors(&mut runner, |runner, ast| evaluate_errors_multi(runner, ast, &samples));
+
+    let best = &result.best;
+    println!("\n=== Best program (fitness={:.2}, size={}) ===", best.fitness, best.size);
+    println!("{:#?}", best.ast);
+
+    println!("\nSpot checks:");
+    for (inputs, target) in samples.iter().step_by(10) {
+        let predicted = evaluate_ast_on_inputs(&mut runner, &best.ast, inputs);
+        let args: Vec<String> = inputs.iter().map(|v| format!("{:2}", v)).collect();
+        println!(
+            "  f({}) = {:>6} (target: {})",
+            args.join(", "),
+            predicted.map_or("FAIL".to_string(), |p| p.to_string()),
+            target
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+// src/bin/symreg_vector.rs
+// Evolve a program with two outputs: for input x, leave [x, x+1] on top of the
+// int stack (x+1 on top), scored with `gp::fitness::vector_mse`.
+
+use anyhow::Result;
+
+use offchain::gp::engine::{Evolution, EvolutionConfig};
+use offchain::gp::fitness::{evaluate_vector_output, vector_mse, VectorSample};
+use offchain::helpers::artifact::{get_creation_code, locate_interpreter_artifact};
+use offchain::helpers::rng::take_seed_flag;
+use offchain::runner::revm_runner::EvmRunner;
+
+fn generate_samples() -> Vec<VectorSample> {
+    (-5..=5).map(|x| (vec![x], vec![x, x + 1])).collect()
+}
+
+fn main() -> Result<()> {
+    let samples = generate_samples();
+    println!("Target: x => [x, x+1] ({} samples)", samples.len());
+
+    let creation_bytes = get_creation_code(locate_interpreter_artifact()?)?;
+    let mut runner = EvmRunner::new(creation_bytes)?;
+
+    let config = EvolutionConfig {
+        pop_size: 200,
+        generations: 30,
+        max_points: 8,
+        seed: take_seed_flag(&mut std::env::args().collect())?,
+        ..EvolutionConfig::default()
+    };
+
+    // Fitness in (0, 1]: 1 means every output is exact
+    let result = Evolution::new(config)?
+        .stop_when(|_, best| best.fitness >= 1.0)
+        .evolve(&mut runner, |runner, ast| 1.0 / (1.0 + vector_mse(runner, ast, &samples)));
+
+    let best = &result.best;
+    println!("\n=== Best program (fitness={:.4}, size={}) ===", best.fitness, best.size);
+    println!("{}", best.ast);
+    println!("MSE: {}", vector_mse(&mut runner, &best.ast, &samples));
+
+    println!("\nSpot checks:");
+    for (inputs, expected) in samples.iter().step_by(2) {
+        let predicted = evaluate_vector_output(&mut runner, &best.ast, inputs, expected.len());
+        println!("  x={:2}: {:?} (target: {:?})", inputs[0], predicted, expected);
+    }
+
+    Ok(())
+}
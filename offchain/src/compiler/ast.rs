@@ -6,6 +6,16 @@
 //! opcodes to bytes. This allows us to expand or change instructions easily
 //! (e.g., when the on-chain interpreter adds new opcodes or changes their IDs).
 
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::compiler::push3_describtor::{TAG_INT_LITERAL, TAG_SUBLIST};
+use crate::compiler::stack_effect::stack_effect;
+
 /// A trait describing how to convert an AST into Push3 bytecode.
 ///
 /// This is deliberately minimal for now. In the future, we could add more methods
@@ -26,16 +36,83 @@ pub trait OpCodeMapping {
     /// that the interpreter expects.
     fn opcode_byte(&self, op: &OpCode) -> u8;
 
-    // If you need reverse-lookup (byte => OpCode), you could add a method like:
-    // fn from_byte(&self, b: u8) -> Option<OpCode>;
-    // for now, we omit it since we only do forward mapping in this file.
+    /// Reverse lookup (byte => `OpCode`), used by [`bytecode_to_ast`]. The
+    /// default searches [`OpCode::ALL`] through `opcode_byte`.
+    fn opcode_from_byte(&self, b: u8) -> Option<OpCode> {
+        OpCode::ALL.iter().find(|op| self.opcode_byte(op) == b).cloned()
+    }
+
+    /// The byte that starts an int literal (followed by 4 big-endian bytes).
+    /// Defaults to the interpreter's `INT_LITERAL` tag, [`TAG_INT_LITERAL`].
+    fn int_literal_tag(&self) -> u8 {
+        TAG_INT_LITERAL
+    }
+
+    /// The byte that starts a sublist (followed by a 2-byte big-endian length
+    /// and the children). Defaults to the interpreter's `SUBLIST` tag,
+    /// [`TAG_SUBLIST`]: its 0x03 is `BOOL_LITERAL`.
+    ///
+    /// Both tags are used by the encoder and [`bytecode_to_ast_with_mapping`],
+    /// so a mapping can target an interpreter with different framing:
+    ///
+    /// ```
+    /// use offchain::compiler::ast::{
+    ///     bytecode_to_ast, bytecode_to_ast_with_mapping, OpCode, OpCodeMapping, DEFAULT_OP_MAPPING,
+    /// };
+    /// use offchain::push3;
+    ///
+    /// /// The default opcodes, framed with 0xF0 / 0xF1.
+    /// struct Reframed;
+    ///
+    /// impl OpCodeMapping for Reframed {
+    ///     fn opcode_byte(&self, op: &OpCode) -> u8 {
+    ///         DEFAULT_OP_MAPPING.opcode_byte(op)
+    ///     }
+    ///     fn int_literal_tag(&self) -> u8 {
+    ///         0xF0
+    ///     }
+    ///     fn sublist_tag(&self) -> u8 {
+    ///         0xF1
+    ///     }
+    /// }
+    ///
+    /// let ast = push3!((3 (DUP) +));
+    /// let code = ast.to_bytecode_with_mapping(&Reframed);
+    /// assert_eq!(code, [0xF1, 0, 10, 0xF0, 0, 0, 0, 3, 0xF1, 0, 1, 0x08, 0x05]);
+    /// assert_eq!(bytecode_to_ast_with_mapping(&code, &Reframed), Ok(ast));
+    /// assert!(bytecode_to_ast(&code).is_err());
+    /// ```
+    fn sublist_tag(&self) -> u8 {
+        TAG_SUBLIST
+    }
 }
 
 /// Our untyped AST node:
 /// - `IntLiteral(i32)` holds a literal integer,
 /// - `Instruction(OpCode)` holds one opcode,
 /// - `Sublist(Vec<UntypedAst>)` holds a collection of nested AST nodes.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// `Eq` and `Hash` are derived, so ASTs (and opcodes) can be used as map keys.
+/// The derived `Hash` of a sublist covers its children in order and their
+/// count, matching `==`:
+///
+/// ```
+/// use std::collections::HashSet;
+/// use offchain::compiler::ast::{OpCode, UntypedAst};
+///
+/// let list = |items: Vec<UntypedAst>| UntypedAst::Sublist(items);
+/// let one = UntypedAst::IntLiteral(1);
+/// let plus = UntypedAst::Instruction(OpCode::Plus);
+///
+/// let mut set = HashSet::new();
+/// assert!(set.insert(list(vec![one.clone(), plus.clone()])));
+/// assert!(!set.insert(list(vec![one.clone(), plus.clone()]))); // equal => same entry
+/// assert!(set.insert(list(vec![plus.clone(), one.clone()])));  // order matters
+/// assert!(set.insert(list(vec![one.clone()])));                // count matters
+/// assert!(set.insert(list(vec![list(vec![one]), plus])));      // nesting matters
+/// assert_eq!(set.len(), 4);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum UntypedAst {
     IntLiteral(i32),
     Instruction(OpCode),
@@ -44,7 +121,7 @@ pub enum UntypedAst {
 
 /// An **abstract** set of opcodes. We do *not* assign numeric values here.
 /// Instead, see [`OpCodeMapping::opcode_byte`] for how we convert them to bytes.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum OpCode {
     // Basic operations
     Noop,
@@ -91,34 +168,173 @@ impl UntypedAst {
     /// *any* mapping if needed. The method used by the trait’s `to_bytecode()`
     /// relies on the global `DEFAULT_OP_MAPPING`.
     pub fn to_bytecode_with_mapping<M: OpCodeMapping>(&self, mapping: &M) -> Vec<u8> {
+        // The encoded size is known up front, so the whole program goes into
+        // one buffer instead of one allocation per sublist.
+        let mut bytes = Vec::with_capacity(self.bytecode_len());
+        self.write_bytecode(mapping, &mut bytes);
+        bytes
+    }
+
+    /// Length of the encoding produced by `to_bytecode()`, computed without
+    /// building it: 1 byte per instruction, 5 per int literal, and 3 plus the
+    /// children for a sublist. Handy for sizing `max_points` before a run.
+    ///
+    /// ```
+    /// use offchain::compiler::ast::Push3Ast;
+    /// use offchain::push3;
+    ///
+    /// for ast in [push3!(DUP), push3!(7), push3!(()), push3!((3 (5 DUP) + (()) -))] {
+    ///     assert_eq!(ast.bytecode_len(), ast.to_bytecode().len());
+    /// }
+    /// assert_eq!(push3!((3 (5 DUP) +)).bytecode_len(), 3 + 5 + (3 + 5 + 1) + 1);
+    /// ```
+    pub fn bytecode_len(&self) -> usize {
+        match self {
+            UntypedAst::IntLiteral(_) => 1 + 4,
+            UntypedAst::Instruction(_) => 1,
+            UntypedAst::Sublist(children) => {
+                3 + children.iter().map(UntypedAst::bytecode_len).sum::<usize>()
+            }
+        }
+    }
+
+    /// Write this program as JSON (its serde form), e.g. to replay it later
+    /// with the `replay` bin.
+    ///
+    /// ```
+    /// use offchain::compiler::ast::UntypedAst;
+    /// use offchain::push3;
+    ///
+    /// let path = std::env::temp_dir().join("push3_saved_program.json");
+    /// let ast = push3!((DUP (2 *) -7 +));
+    /// ast.save(&path).unwrap();
+    /// assert_eq!(UntypedAst::load(&path).unwrap(), ast);
+    /// ```
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+            .map_err(|e| anyhow!("Failed to write program {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read program {}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| anyhow!("Failed to parse program: {}", e))
+    }
+
+    fn write_bytecode<M: OpCodeMapping>(&self, mapping: &M, bytes: &mut Vec<u8>) {
         match self {
-            // For an integer literal, we write the “tag byte” for int-literal, then 4 bytes (LE).
+            // For an integer literal, we write the mapping's int-literal tag, then 4 bytes (BE).
             UntypedAst::IntLiteral(val) => {
-                // Hardcode 0x02 => INT_LITERAL. 
-                // You *could* put that in the mapping if you want to make that flexible, too.
-                let mut bytes = Vec::with_capacity(1 + 4);
-                bytes.push(0x02);
+                bytes.push(mapping.int_literal_tag());
                 bytes.extend_from_slice(&val.to_be_bytes());
-                bytes
             }
             UntypedAst::Instruction(op) => {
                 // Use the mapping to find the correct opcode byte:
-                let b = mapping.opcode_byte(op);
-                vec![b]
+                bytes.push(mapping.opcode_byte(op));
             }
             UntypedAst::Sublist(children) => {
-                // Hardcode 0x03 => SUBLIST, then big-endian length, then child payload
-                let mut payload = Vec::new();
+                // The sublist tag, then big-endian length, then child payload.
+                // The length is patched in once the children are written.
+                bytes.push(mapping.sublist_tag());
+                let len_at = bytes.len();
+                bytes.extend_from_slice(&[0, 0]);
                 for child in children {
-                    let child_bytes = child.to_bytecode_with_mapping(mapping);
-                    payload.extend(child_bytes);
+                    child.write_bytecode(mapping, bytes);
+                }
+                let sub_len = (bytes.len() - len_at - 2) as u16;
+                bytes[len_at..len_at + 2].copy_from_slice(&sub_len.to_be_bytes()); // big-endian length
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Traversal
+// ----------------------------------------------------------------------------
+
+impl UntypedAst {
+    /// Every node with its path (child indices from the root, as in
+    /// `gp::mutation::Path`), parents before their children, children left to right.
+    ///
+    /// ```
+    /// use offchain::gp::mutation::get_subtree;
+    /// use offchain::push3;
+    ///
+    /// let ast = push3!((1 (2 3) +));
+    /// let nodes: Vec<String> = ast.iter_preorder().map(|(node, _)| node.to_string()).collect();
+    /// assert_eq!(nodes, ["(1 (2 3) +)", "1", "(2 3)", "2", "3", "+"]);
+    ///
+    /// let paths: Vec<Vec<usize>> = ast.iter_preorder().map(|(_, path)| path).collect();
+    /// assert_eq!(paths, [vec![], vec![0], vec![1], vec![1, 0], vec![1, 1], vec![2]]);
+    /// for (node, path) in ast.iter_preorder() {
+    ///     assert_eq!(&get_subtree(&ast, &path), node);
+    /// }
+    /// ```
+    pub fn iter_preorder(&self) -> Preorder<'_> {
+        Preorder { stack: vec![(self, Vec::new())] }
+    }
+
+    /// Every node with its path, children (left to right) before their parent.
+    ///
+    /// ```
+    /// use offchain::push3;
+    ///
+    /// let ast = push3!((1 (2 3) +));
+    /// let nodes: Vec<String> = ast.iter_postorder().map(|(node, _)| node.to_string()).collect();
+    /// assert_eq!(nodes, ["1", "2", "3", "(2 3)", "+", "(1 (2 3) +)"]);
+    /// ```
+    pub fn iter_postorder(&self) -> Postorder<'_> {
+        Postorder { stack: vec![(self, Vec::new(), false)] }
+    }
+}
+
+/// Iterator returned by [`UntypedAst::iter_preorder`].
+pub struct Preorder<'a> {
+    stack: Vec<(&'a UntypedAst, Vec<usize>)>,
+}
+
+impl<'a> Iterator for Preorder<'a> {
+    type Item = (&'a UntypedAst, Vec<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, path) = self.stack.pop()?;
+        if let UntypedAst::Sublist(children) = node {
+            // Pushed in reverse so the first child comes out next
+            for (i, child) in children.iter().enumerate().rev() {
+                let mut child_path = path.clone();
+                child_path.push(i);
+                self.stack.push((child, child_path));
+            }
+        }
+        Some((node, path))
+    }
+}
+
+/// Iterator returned by [`UntypedAst::iter_postorder`].
+pub struct Postorder<'a> {
+    /// `(node, path, children_pushed)`
+    stack: Vec<(&'a UntypedAst, Vec<usize>, bool)>,
+}
+
+impl<'a> Iterator for Postorder<'a> {
+    type Item = (&'a UntypedAst, Vec<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, path, children_pushed) = self.stack.pop()?;
+            match node {
+                UntypedAst::Sublist(children) if !children_pushed => {
+                    self.stack.push((node, path.clone(), true));
+                    for (i, child) in children.iter().enumerate().rev() {
+                        let mut child_path = path.clone();
+                        child_path.push(i);
+                        self.stack.push((child, child_path, false));
+                    }
                 }
-                let sub_len = payload.len() as u16;
-                let mut bytes = Vec::with_capacity(1 + 2 + payload.len());
-                bytes.push(0x03);
-                bytes.extend_from_slice(&sub_len.to_be_bytes()); // big-endian length
-                bytes.extend(payload);
-                bytes
+                _ => return Some((node, path)),
             }
         }
     }
@@ -183,6 +399,160 @@ impl OpCodeMapping for DefaultOpCodeMapping {
 /// A convenient global `const` or `static` for quick usage.
 pub const DEFAULT_OP_MAPPING: DefaultOpCodeMapping = DefaultOpCodeMapping;
 
+// ----------------------------------------------------------------------------
+// Decoding (bytecode => AST)
+// ----------------------------------------------------------------------------
+
+/// Why [`bytecode_to_ast`] rejected its input. Offsets are byte positions in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// A node (or the 4 bytes of a literal, or a sublist header) is cut off.
+    UnexpectedEnd { offset: usize },
+    /// The byte is neither a literal / sublist tag nor a known opcode.
+    UnknownOpcode { offset: usize, byte: u8 },
+    /// A sublist claims more bytes than its parent (or the input) has left.
+    SublistOverrun { offset: usize, length: usize, available: usize },
+    /// Bytes left over after the top-level node.
+    TrailingBytes { offset: usize },
+    /// Sublists nested deeper than [`MAX_PARSE_DEPTH`].
+    TooDeep { offset: usize },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd { offset } => write!(f, "unexpected end of bytecode at {}", offset),
+            DecodeError::UnknownOpcode { offset, byte } => {
+                write!(f, "unknown opcode 0x{:02x} at {}", byte, offset)
+            }
+            DecodeError::SublistOverrun { offset, length, available } => write!(
+                f,
+                "sublist at {} has length {} but only {} bytes are available",
+                offset, length, available
+            ),
+            DecodeError::TrailingBytes { offset } => write!(f, "trailing bytes from {}", offset),
+            DecodeError::TooDeep { offset } => {
+                write!(f, "sublists nested deeper than {} at {}", MAX_PARSE_DEPTH, offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// The inverse of `to_bytecode()`: decode exactly one node spanning all of `code`.
+///
+/// ```
+/// use offchain::compiler::ast::{bytecode_to_ast, DecodeError, Push3Ast};
+/// use offchain::gp::generate::random_ast;
+/// use offchain::gp::generate_spec::ranmdom_code_fixed;
+/// use offchain::helpers::rng::seeded_rng;
+/// use offchain::push3;
+///
+/// let round_trips = |ast: &offchain::compiler::ast::UntypedAst| {
+///     assert_eq!(bytecode_to_ast(&ast.to_bytecode()).as_ref(), Ok(ast));
+/// };
+///
+/// // Negative and extreme literals, empty sublists, deep nesting
+/// round_trips(&push3!((-1 2147483647 -2147483648 0 +)));
+/// round_trips(&push3!((() (()) ((3) ()))));
+/// round_trips(&push3!(DUP));
+/// round_trips(&push3!(-7));
+/// let mut deep = push3!((1 2 +));
+/// for _ in 0..200 {
+///     deep = offchain::compiler::ast::UntypedAst::Sublist(vec![deep, push3!(DUP)]);
+/// }
+/// round_trips(&deep);
+///
+/// // Random programs from both generators
+/// for seed in 0..200 {
+///     let mut rng = seeded_rng(seed);
+///     round_trips(&ranmdom_code_fixed(&mut rng, 40));
+///     round_trips(&random_ast(&mut rng, 0, 6));
+/// }
+///
+/// // Malformed input
+/// assert_eq!(bytecode_to_ast(&[]), Err(DecodeError::UnexpectedEnd { offset: 0 }));
+/// assert_eq!(bytecode_to_ast(&[0x02, 0, 0]), Err(DecodeError::UnexpectedEnd { offset: 0 }));
+/// assert_eq!(bytecode_to_ast(&[0xff]), Err(DecodeError::UnknownOpcode { offset: 0, byte: 0xff }));
+/// assert_eq!(
+///     bytecode_to_ast(&[0x04, 0x00, 0x02, 0x08]),
+///     Err(DecodeError::SublistOverrun { offset: 0, length: 2, available: 1 })
+/// );
+/// assert_eq!(bytecode_to_ast(&[0x08, 0x08]), Err(DecodeError::TrailingBytes { offset: 1 }));
+/// ```
+pub fn bytecode_to_ast(code: &[u8]) -> Result<UntypedAst, DecodeError> {
+    bytecode_to_ast_with_mapping(code, &DEFAULT_OP_MAPPING)
+}
+
+/// [`bytecode_to_ast`] with a custom [`OpCodeMapping`].
+pub fn bytecode_to_ast_with_mapping<M: OpCodeMapping>(
+    code: &[u8],
+    mapping: &M,
+) -> Result<UntypedAst, DecodeError> {
+    let mut pos = 0;
+    let ast = decode_node(code, &mut pos, code.len(), 0, mapping)?;
+    if pos < code.len() {
+        return Err(DecodeError::TrailingBytes { offset: pos });
+    }
+    Ok(ast)
+}
+
+/// Decode one node starting at `pos`, not reading past `end`.
+fn decode_node<M: OpCodeMapping>(
+    code: &[u8],
+    pos: &mut usize,
+    end: usize,
+    depth: usize,
+    mapping: &M,
+) -> Result<UntypedAst, DecodeError> {
+    let offset = *pos;
+    let Some(&tag) = code.get(offset).filter(|_| offset < end) else {
+        return Err(DecodeError::UnexpectedEnd { offset });
+    };
+
+    match tag {
+        tag if tag == mapping.int_literal_tag() => {
+            if offset + 5 > end {
+                return Err(DecodeError::UnexpectedEnd { offset });
+            }
+            let mut be = [0u8; 4];
+            be.copy_from_slice(&code[offset + 1..offset + 5]);
+            *pos = offset + 5;
+            Ok(UntypedAst::IntLiteral(i32::from_be_bytes(be)))
+        }
+        tag if tag == mapping.sublist_tag() => {
+            if depth >= MAX_PARSE_DEPTH {
+                return Err(DecodeError::TooDeep { offset });
+            }
+            if offset + 3 > end {
+                return Err(DecodeError::UnexpectedEnd { offset });
+            }
+            let length = u16::from_be_bytes([code[offset + 1], code[offset + 2]]) as usize;
+            let body_start = offset + 3;
+            let available = end - body_start;
+            if length > available {
+                return Err(DecodeError::SublistOverrun { offset, length, available });
+            }
+
+            *pos = body_start;
+            let body_end = body_start + length;
+            let mut children = Vec::new();
+            while *pos < body_end {
+                children.push(decode_node(code, pos, body_end, depth + 1, mapping)?);
+            }
+            Ok(UntypedAst::Sublist(children))
+        }
+        byte => match mapping.opcode_from_byte(byte) {
+            Some(op) => {
+                *pos = offset + 1;
+                Ok(UntypedAst::Instruction(op))
+            }
+            None => Err(DecodeError::UnknownOpcode { offset, byte }),
+        },
+    }
+}
+
 // ----------------------------------------------------------------------------
 // S-Expression Parsing Helpers
 // ----------------------------------------------------------------------------
@@ -227,8 +597,12 @@ pub fn tokenize(s: &str) -> Vec<String> {
     tokens
 }
 
+/// Deepest list nesting `parse_string_to_sexpr` accepts, so hostile input
+/// fails with an error instead of overflowing the stack.
+pub const MAX_PARSE_DEPTH: usize = 512;
+
 /// Recursive helper to parse a single S-expression from a list of tokens, advancing `pos`.
-fn parse_sexpr_internal(tokens: &[String], pos: &mut usize) -> Result<SExpr, String> {
+fn parse_sexpr_internal(tokens: &[String], pos: &mut usize, depth: usize) -> Result<SExpr, String> {
     if *pos >= tokens.len() {
         return Err("Unexpected end of tokens".to_string());
     }
@@ -236,19 +610,23 @@ fn parse_sexpr_internal(tokens: &[String], pos: &mut usize) -> Result<SExpr, Str
     let token = &tokens[*pos];
     match token.as_str() {
         "(" => {
+            if depth >= MAX_PARSE_DEPTH {
+                return Err(format!("Lists nested deeper than {} levels", MAX_PARSE_DEPTH));
+            }
+            let open = *pos;
             *pos += 1; // consume '('
             let mut items = Vec::new();
             while *pos < tokens.len() && tokens[*pos] != ")" {
-                let expr = parse_sexpr_internal(tokens, pos)?;
+                let expr = parse_sexpr_internal(tokens, pos, depth + 1)?;
                 items.push(expr);
             }
             if *pos >= tokens.len() {
-                return Err("Missing closing parenthesis".to_string());
+                return Err(format!("Missing closing parenthesis for '(' at token {}", open));
             }
             *pos += 1; // consume ')'
             Ok(SExpr::List(items))
         }
-        ")" => Err("Unexpected ')'".to_string()),
+        ")" => Err(format!("Unexpected ')' at token {} with no matching '('", *pos)),
         _ => {
             let atom_str = token.clone();
             *pos += 1;
@@ -258,13 +636,41 @@ fn parse_sexpr_internal(tokens: &[String], pos: &mut usize) -> Result<SExpr, Str
 }
 
 /// Parse a full string into a single top-level `SExpr`, assuming balanced parentheses.
+///
+/// ```
+/// use offchain::compiler::ast::{parse_string_to_sexpr, SExpr};
+///
+/// assert!(matches!(parse_string_to_sexpr("(1 (2 3) +)"), Ok(SExpr::List(_))));
+/// assert_eq!(parse_string_to_sexpr("DUP"), Ok(SExpr::Atom("DUP".to_string())));
+///
+/// assert_eq!(parse_string_to_sexpr("  "), Err("Empty input".to_string()));
+/// assert_eq!(
+///     parse_string_to_sexpr("(1 (2 +)"),
+///     Err("Missing closing parenthesis for '(' at token 0".to_string())
+/// );
+/// assert_eq!(
+///     parse_string_to_sexpr("(1 2))"),
+///     Err("Unexpected ')' at token 4 with no matching '('".to_string())
+/// );
+/// assert_eq!(
+///     parse_string_to_sexpr("(1 2) DUP"),
+///     Err("Extra tokens after parse: [\"DUP\"] (wrap several expressions in one list)".to_string())
+/// );
+/// assert!(parse_string_to_sexpr(&"(".repeat(100_000)).is_err());
+/// ```
 pub fn parse_string_to_sexpr(s: &str) -> Result<SExpr, String> {
     let tokens = tokenize(s);
+    if tokens.is_empty() {
+        return Err("Empty input".to_string());
+    }
     let mut pos = 0;
-    let expr = parse_sexpr_internal(&tokens, &mut pos)?;
+    let expr = parse_sexpr_internal(&tokens, &mut pos, 0)?;
     if pos < tokens.len() {
+        if tokens[pos] == ")" {
+            return Err(format!("Unexpected ')' at token {} with no matching '('", pos));
+        }
         Err(format!(
-            "Extra tokens after parse: {:?}",
+            "Extra tokens after parse: {:?} (wrap several expressions in one list)",
             &tokens[pos..]
         ))
     } else {
@@ -273,6 +679,23 @@ pub fn parse_string_to_sexpr(s: &str) -> Result<SExpr, String> {
 }
 
 /// Convert an S-expression to an **untyped** AST node.
+///
+/// Opcode names are case-insensitive, and a few common notations are accepted
+/// as aliases: `%` for `MOD`, `^` for `POW` and `=` for `==`. Printing always
+/// uses the canonical symbol.
+///
+/// ```
+/// use offchain::compiler::ast::{parse_string_to_sexpr, sexpr_to_untyped, OpCode, UntypedAst};
+///
+/// let parse = |s| sexpr_to_untyped(&parse_string_to_sexpr(s).unwrap()).unwrap();
+/// for (alias, op) in [("%", OpCode::Mod), ("^", OpCode::Pow), ("=", OpCode::Equal)] {
+///     assert_eq!(parse(alias), UntypedAst::Instruction(op));
+/// }
+/// assert_eq!(parse("(7 3 %)"), parse("(7 3 MOD)"));
+/// assert_eq!(parse("(2 10 ^)").to_string(), "(2 10 POW)");
+/// assert_eq!(parse("(1 1 =)").to_string(), "(1 1 ==)");
+/// assert_eq!(parse("sqrt"), parse("SQRT"));
+/// ```
 pub fn sexpr_to_untyped(expr: &SExpr) -> Result<UntypedAst, String> {
     match expr {
         SExpr::Atom(text) => {
@@ -292,7 +715,7 @@ pub fn sexpr_to_untyped(expr: &SExpr) -> Result<UntypedAst, String> {
                     // Comparison operations
                     ">" => Ok(UntypedAst::Instruction(OpCode::GreaterThan)),
                     "<" => Ok(UntypedAst::Instruction(OpCode::LessThan)),
-                    "==" => Ok(UntypedAst::Instruction(OpCode::Equal)),
+                    "==" | "=" => Ok(UntypedAst::Instruction(OpCode::Equal)),
                     "!=" => Ok(UntypedAst::Instruction(OpCode::NotEqual)),
                     ">=" => Ok(UntypedAst::Instruction(OpCode::GreaterEqual)),
                     "<=" => Ok(UntypedAst::Instruction(OpCode::LessEqual)),
@@ -302,8 +725,8 @@ pub fn sexpr_to_untyped(expr: &SExpr) -> Result<UntypedAst, String> {
                     "COS" => Ok(UntypedAst::Instruction(OpCode::Cos)),
                     "SQRT" => Ok(UntypedAst::Instruction(OpCode::Sqrt)),
                     "ABS" => Ok(UntypedAst::Instruction(OpCode::Abs)),
-                    "MOD" => Ok(UntypedAst::Instruction(OpCode::Mod)),
-                    "POW" => Ok(UntypedAst::Instruction(OpCode::Pow)),
+                    "MOD" | "%" => Ok(UntypedAst::Instruction(OpCode::Mod)),
+                    "POW" | "^" => Ok(UntypedAst::Instruction(OpCode::Pow)),
                     
                     // Constants  
                     "PI" => Ok(UntypedAst::Instruction(OpCode::ConstPi)),
@@ -333,6 +756,443 @@ pub fn sexpr_to_untyped(expr: &SExpr) -> Result<UntypedAst, String> {
     }
 }
 
+/// Build an `UntypedAst` from S-expression syntax written inline, mostly for tests
+/// and examples:
+///
+/// ```
+/// use offchain::compiler::ast::{OpCode, UntypedAst};
+/// use offchain::push3;
+///
+/// assert_eq!(
+///     push3!((3 5 +)),
+///     UntypedAst::Sublist(vec![
+///         UntypedAst::IntLiteral(3),
+///         UntypedAst::IntLiteral(5),
+///         UntypedAst::Instruction(OpCode::Plus),
+///     ])
+/// );
+/// assert_eq!(push3!((DUP (2 *) -1 IF_ELSE)).to_string(), "(DUP (2 *) -1 IF_ELSE)");
+/// assert_eq!(push3!(7), UntypedAst::IntLiteral(7));
+/// ```
+///
+/// The tokens are turned back into text with `stringify!` and run through
+/// [`parse_string_to_sexpr`] + [`sexpr_to_untyped`], so the syntax is exactly
+/// theirs. Write negative literals without a space (`-1`): `- 1` is the `-`
+/// instruction followed by `1`. Panics if the input does not parse.
+#[macro_export]
+macro_rules! push3 {
+    ($($tokens:tt)+) => {{
+        let source = stringify!($($tokens)+);
+        let sexpr = $crate::compiler::ast::parse_string_to_sexpr(source)
+            .unwrap_or_else(|e| panic!("push3!: cannot parse `{}`: {}", source, e));
+        $crate::compiler::ast::sexpr_to_untyped(&sexpr)
+            .unwrap_or_else(|e| panic!("push3!: cannot convert `{}`: {}", source, e))
+    }};
+}
+
+// ----------------------------------------------------------------------------
+// Pretty-printing (AST => S-expression text)
+// ----------------------------------------------------------------------------
+
+impl OpCode {
+    /// Every opcode, in declaration order.
+    pub const ALL: [OpCode; 25] = [
+        OpCode::Noop,
+        OpCode::Plus,
+        OpCode::Minus,
+        OpCode::Mult,
+        OpCode::Dup,
+        OpCode::Pop,
+        OpCode::GreaterThan,
+        OpCode::LessThan,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::GreaterEqual,
+        OpCode::LessEqual,
+        OpCode::Sin,
+        OpCode::Cos,
+        OpCode::Sqrt,
+        OpCode::Abs,
+        OpCode::Mod,
+        OpCode::Pow,
+        OpCode::ConstPi,
+        OpCode::ConstE,
+        OpCode::ConstRand,
+        OpCode::BoolToInt,
+        OpCode::IntToBool,
+        OpCode::IfThen,
+        OpCode::IfElse,
+    ];
+
+    /// The token [`sexpr_to_untyped`] parses back into this opcode.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            OpCode::Noop => "NOOP",
+            OpCode::Plus => "+",
+            OpCode::Minus => "-",
+            OpCode::Mult => "*",
+            OpCode::Dup => "DUP",
+            OpCode::Pop => "POP",
+            OpCode::GreaterThan => ">",
+            OpCode::LessThan => "<",
+            OpCode::Equal => "==",
+            OpCode::NotEqual => "!=",
+            OpCode::GreaterEqual => ">=",
+            OpCode::LessEqual => "<=",
+            OpCode::Sin => "SIN",
+            OpCode::Cos => "COS",
+            OpCode::Sqrt => "SQRT",
+            OpCode::Abs => "ABS",
+            OpCode::Mod => "MOD",
+            OpCode::Pow => "POW",
+            OpCode::ConstPi => "PI",
+            OpCode::ConstE => "E",
+            OpCode::ConstRand => "RAND",
+            OpCode::BoolToInt => "BOOL_TO_INT",
+            OpCode::IntToBool => "INT_TO_BOOL",
+            OpCode::IfThen => "IF_THEN",
+            OpCode::IfElse => "IF_ELSE",
+        }
+    }
+
+    /// Rough on-chain cost relative to `Plus` (1.0), for cost-weighted
+    /// parsimony. `Sqrt` and `Pow` loop in the interpreter, `ConstRand`
+    /// hashes, and `Sin`/`Cos` branch through a piecewise approximation.
+    pub fn weight(&self) -> f64 {
+        match self {
+            OpCode::Sqrt | OpCode::Pow => 4.0,
+            OpCode::ConstRand => 3.0,
+            OpCode::Sin | OpCode::Cos => 2.0,
+            _ => 1.0,
+        }
+    }
+
+    /// One-line description of what the interpreter does for this opcode.
+    /// Binary ops take `b` below `a` (the top) and compute `b OP a`.
+    pub fn description(&self) -> &'static str {
+        match self {
+            OpCode::Noop => "do nothing",
+            OpCode::Plus => "pop a, b; push b + a",
+            OpCode::Minus => "pop a, b; push b - a",
+            OpCode::Mult => "pop a, b; push b * a",
+            OpCode::Dup => "push a copy of the top int",
+            OpCode::Pop => "drop the top int",
+            OpCode::GreaterThan => "pop a, b; push bool b > a",
+            OpCode::LessThan => "pop a, b; push bool b < a",
+            OpCode::Equal => "pop a, b; push bool b == a",
+            OpCode::NotEqual => "pop a, b; push bool b != a",
+            OpCode::GreaterEqual => "pop a, b; push bool b >= a",
+            OpCode::LessEqual => "pop a, b; push bool b <= a",
+            OpCode::Sin => "replace the top (degrees * 10) by its sine * 1000",
+            OpCode::Cos => "replace the top (degrees * 10) by its cosine * 1000",
+            OpCode::Sqrt => "replace the top by its integer square root (0 if negative)",
+            OpCode::Abs => "replace the top by its absolute value",
+            OpCode::Mod => "pop a, b; push b % a (0 if a == 0)",
+            OpCode::Pow => "pop a, b; push b ^ a",
+            OpCode::ConstPi => "push 3141 (pi * 1000)",
+            OpCode::ConstE => "push 2718 (e * 1000)",
+            OpCode::ConstRand => "push a pseudo-random value in [0, 1000)",
+            OpCode::BoolToInt => "pop a bool; push it as 0 or 1",
+            OpCode::IntToBool => "pop an int; push bool int != 0",
+            OpCode::IfThen => "pop a bool; if false, skip the next exec item",
+            OpCode::IfElse => "pop a bool; run the next exec item if true, else the one after",
+        }
+    }
+}
+
+/// Prints the S-expression form, which parses back to the same AST:
+///
+/// ```
+/// use offchain::compiler::ast::{parse_string_to_sexpr, sexpr_to_untyped};
+///
+/// let ast = sexpr_to_untyped(&parse_string_to_sexpr("(3 (DUP *) -2 IF_THEN)").unwrap()).unwrap();
+/// let text = ast.to_string();
+/// assert_eq!(text, "(3 (DUP *) -2 IF_THEN)");
+/// assert_eq!(sexpr_to_untyped(&parse_string_to_sexpr(&text).unwrap()).unwrap(), ast);
+/// ```
+impl fmt::Display for UntypedAst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UntypedAst::IntLiteral(val) => write!(f, "{}", val),
+            UntypedAst::Instruction(op) => f.write_str(op.mnemonic()),
+            UntypedAst::Sublist(children) => {
+                f.write_str("(")?;
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{}", child)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Simplification (dead-code removal)
+// ----------------------------------------------------------------------------
+
+/// Return a smaller AST that behaves the same under the interpreter's stack model.
+///
+/// The pass works bottom-up and:
+/// - removes `Noop` instructions and empty sublists,
+/// - drops a constant push (`IntLiteral`, `PI`, `E`) immediately followed by `POP`,
+/// - flattens singleton sublists, i.e. `(X)` becomes `X`.
+///
+/// `IF_THEN` / `IF_ELSE` skip the *next* exec item, so removing any item can change
+/// which one gets skipped. If the tree contains a conditional, only the
+/// singleton flattening is applied (it keeps the number of exec items unchanged).
+///
+/// ```
+/// use offchain::compiler::ast::{parse_string_to_sexpr, sexpr_to_untyped, simplify};
+///
+/// let parse = |s| sexpr_to_untyped(&parse_string_to_sexpr(s).unwrap()).unwrap();
+/// assert_eq!(simplify(&parse("(3 NOOP +)")), parse("(3 +)"));
+/// assert_eq!(simplify(&parse("((7 POP) (2))")), parse("2"));
+/// ```
+pub fn simplify(ast: &UntypedAst) -> UntypedAst {
+    let allow_removal = !contains_conditional(ast);
+    simplify_node(ast, allow_removal)
+}
+
+/// Recursive helper for [`simplify`].
+fn simplify_node(ast: &UntypedAst, allow_removal: bool) -> UntypedAst {
+    let children = match ast {
+        UntypedAst::Sublist(children) => children,
+        UntypedAst::IntLiteral(_) | UntypedAst::Instruction(_) => return ast.clone(),
+    };
+
+    let mut out: Vec<UntypedAst> = Vec::with_capacity(children.len());
+    for child in children {
+        let child = simplify_node(child, allow_removal);
+        if allow_removal {
+            match &child {
+                UntypedAst::Instruction(OpCode::Noop) => continue,
+                UntypedAst::Sublist(items) if items.is_empty() => continue,
+                UntypedAst::Instruction(OpCode::Pop)
+                    if out.last().is_some_and(pushes_int_constant) =>
+                {
+                    // `<const> POP` leaves every stack as it was
+                    out.pop();
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        out.push(child);
+    }
+
+    if out.len() == 1 {
+        return out.pop().unwrap();
+    }
+    UntypedAst::Sublist(out)
+}
+
+/// True if this node only pushes a constant onto the int stack.
+fn pushes_int_constant(ast: &UntypedAst) -> bool {
+    matches!(
+        ast,
+        UntypedAst::IntLiteral(_)
+            | UntypedAst::Instruction(OpCode::ConstPi)
+            | UntypedAst::Instruction(OpCode::ConstE)
+    )
+}
+
+/// True if `IF_THEN` or `IF_ELSE` appears anywhere in the tree.
+fn contains_conditional(ast: &UntypedAst) -> bool {
+    match ast {
+        UntypedAst::Instruction(op) => matches!(op, OpCode::IfThen | OpCode::IfElse),
+        UntypedAst::IntLiteral(_) => false,
+        UntypedAst::Sublist(children) => children.iter().any(contains_conditional),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Validation and repair
+// ----------------------------------------------------------------------------
+
+/// A structural problem found by [`validate`]. Paths are child indices from the
+/// root, as in `gp::mutation::Path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// An empty sublist, which encodes to a zero-length sublist.
+    EmptySublist { path: Vec<usize> },
+    /// Sublists nested deeper than allowed (a lone atom has depth 0).
+    TooDeep { depth: usize, max_depth: usize },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::EmptySublist { path } => write!(f, "empty sublist at {:?}", path),
+            ValidationError::TooDeep { depth, max_depth } => {
+                write!(f, "nesting depth {} exceeds {}", depth, max_depth)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Check `ast` for empty sublists, reporting the first one in pre-order.
+///
+/// ```
+/// use offchain::compiler::ast::{repair, validate, ValidationError};
+/// use offchain::push3;
+///
+/// let ast = push3!((1 (2 ()) +));
+/// assert_eq!(validate(&ast), Err(ValidationError::EmptySublist { path: vec![1, 1] }));
+/// assert_eq!(repair(&ast), push3!((1 (2) +)));
+/// assert_eq!(validate(&repair(&ast)), Ok(()));
+/// ```
+pub fn validate(ast: &UntypedAst) -> Result<(), ValidationError> {
+    match ast
+        .iter_preorder()
+        .find(|(node, _)| matches!(node, UntypedAst::Sublist(children) if children.is_empty()))
+    {
+        Some((_, path)) => Err(ValidationError::EmptySublist { path }),
+        None => Ok(()),
+    }
+}
+
+/// [`validate`], additionally rejecting programs nested deeper than `max_depth`.
+pub fn validate_with_max_depth(ast: &UntypedAst, max_depth: usize) -> Result<(), ValidationError> {
+    validate(ast)?;
+    let depth = nesting_depth(ast);
+    if depth > max_depth {
+        return Err(ValidationError::TooDeep { depth, max_depth });
+    }
+    Ok(())
+}
+
+/// Remove empty sublists (including ones that only become empty once their own
+/// empty children are gone).
+///
+/// If the tree contains `IF_THEN` / `IF_ELSE`, empty sublists are replaced by
+/// `NOOP` instead, so the number of exec items - and thus what a conditional
+/// skips - stays the same (see [`simplify`]). An empty root also becomes `NOOP`.
+pub fn repair(ast: &UntypedAst) -> UntypedAst {
+    let keep_slots = contains_conditional(ast);
+    repair_node(ast, keep_slots).unwrap_or(UntypedAst::Instruction(OpCode::Noop))
+}
+
+/// Recursive helper for [`repair`]; `None` means "drop this node".
+fn repair_node(ast: &UntypedAst, keep_slots: bool) -> Option<UntypedAst> {
+    let UntypedAst::Sublist(children) = ast else {
+        return Some(ast.clone());
+    };
+    let repaired: Vec<UntypedAst> = children
+        .iter()
+        .filter_map(|child| match repair_node(child, keep_slots) {
+            None if keep_slots => Some(UntypedAst::Instruction(OpCode::Noop)),
+            other => other,
+        })
+        .collect();
+    if repaired.is_empty() {
+        None
+    } else {
+        Some(UntypedAst::Sublist(repaired))
+    }
+}
+
+/// A lone atom has depth 0; a sublist is one deeper than its deepest child.
+fn nesting_depth(ast: &UntypedAst) -> usize {
+    match ast {
+        UntypedAst::IntLiteral(_) | UntypedAst::Instruction(_) => 0,
+        UntypedAst::Sublist(children) => 1 + children.iter().map(nesting_depth).max().unwrap_or(0),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Canonical form
+// ----------------------------------------------------------------------------
+
+/// Rewrite `ast` so that programs differing only in the order of the operands
+/// of a commutative operation look the same.
+///
+/// Sublists run their children in order, so reordering is only safe in one
+/// shape: `(A B op)` where `op` is `+`, `*`, `==` or `!=`, and `A` and `B`
+/// are each self-contained (see [`StackEffect::is_self_contained`]) and push
+/// exactly one int and nothing else. Then `op` always sees the two values
+/// `A` and `B` produced, and the order doesn't change its result. In that
+/// shape the two operands are sorted by the derived `Ord` of [`UntypedAst`];
+/// every other sublist keeps its order. Works bottom-up.
+///
+/// [`StackEffect::is_self_contained`]: crate::compiler::stack_effect::StackEffect::is_self_contained
+///
+/// ```
+/// use offchain::compiler::ast::{canonicalize, parse_string_to_sexpr, sexpr_to_untyped};
+///
+/// let parse = |s| sexpr_to_untyped(&parse_string_to_sexpr(s).unwrap()).unwrap();
+///
+/// // Operands of a commutative op are put in a fixed order...
+/// assert_eq!(canonicalize(&parse("((2 3 *) 7 +)")), canonicalize(&parse("(7 (3 2 *) +)")));
+/// // ...but not those of `-`, nor operands that read the stack (`DUP`).
+/// assert_ne!(canonicalize(&parse("(7 2 -)")), canonicalize(&parse("(2 7 -)")));
+/// assert_ne!(canonicalize(&parse("((DUP) 2 +)")), canonicalize(&parse("(2 (DUP) +)")));
+/// ```
+pub fn canonicalize(ast: &UntypedAst) -> UntypedAst {
+    match ast {
+        UntypedAst::IntLiteral(_) | UntypedAst::Instruction(_) => ast.clone(),
+        UntypedAst::Sublist(children) => {
+            let mut children: Vec<UntypedAst> = children.iter().map(canonicalize).collect();
+            if let [a, b, UntypedAst::Instruction(op)] = children.as_slice() {
+                let commutative = matches!(op, OpCode::Plus | OpCode::Mult | OpCode::Equal | OpCode::NotEqual);
+                if commutative && pushes_one_int(a) && pushes_one_int(b) && a > b {
+                    children.swap(0, 1);
+                }
+            }
+            UntypedAst::Sublist(children)
+        }
+    }
+}
+
+/// True if `ast` computes a single int from nothing, the same way wherever it runs.
+fn pushes_one_int(ast: &UntypedAst) -> bool {
+    let effect = stack_effect(ast);
+    effect.is_self_contained() && effect.int_delta == 1 && effect.bool_delta == 0
+}
+
+/// True if `opcode` appears anywhere in `ast`.
+///
+/// ```
+/// use offchain::compiler::ast::{ast_contains, OpCode};
+/// use offchain::push3;
+///
+/// assert!(ast_contains(&push3!((1 (DUP (RAND)) +)), &OpCode::ConstRand));
+/// assert!(!ast_contains(&push3!((1 (DUP (3)) +)), &OpCode::ConstRand));
+/// assert!(ast_contains(&push3!(RAND), &OpCode::ConstRand));
+/// ```
+pub fn ast_contains(ast: &UntypedAst, opcode: &OpCode) -> bool {
+    match ast {
+        UntypedAst::IntLiteral(_) => false,
+        UntypedAst::Instruction(op) => op == opcode,
+        UntypedAst::Sublist(children) => children.iter().any(|child| ast_contains(child, opcode)),
+    }
+}
+
+/// A short fingerprint of `ast` for logs and result databases: the first 8 bytes
+/// of the keccak-256 of the canonical S-expression (`canonicalize(ast).to_string()`),
+/// as 16 hex digits.
+///
+/// It only depends on the program text, not on memory layout, hasher seeds or
+/// the opcode byte mapping, so it is stable across runs, platforms and builds.
+/// Programs with the same canonical form share a fingerprint.
+///
+/// ```
+/// use offchain::compiler::ast::ast_fingerprint;
+/// use offchain::push3;
+///
+/// assert_eq!(ast_fingerprint(&push3!((3 5 +))), "c26b1c20d0af0ace");
+/// assert_eq!(ast_fingerprint(&push3!((5 3 +))), ast_fingerprint(&push3!((3 5 +))));
+/// assert_ne!(ast_fingerprint(&push3!((5 3 -))), ast_fingerprint(&push3!((3 5 -))));
+/// ```
+pub fn ast_fingerprint(ast: &UntypedAst) -> String {
+    let digest = ethers::utils::keccak256(canonicalize(ast).to_string().as_bytes());
+    hex::encode(&digest[..8])
+}
+
 // ----------------------------------------------------------------------------
 // (Optional) Placeholder for a future typed AST
 // ----------------------------------------------------------------------------
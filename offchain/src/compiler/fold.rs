@@ -0,0 +1,53 @@
+//! src/compiler/fold.rs
+//!
+//! Constant folding: replace self-contained arithmetic subtrees such as `(3 5 +)`
+//! by the single `IntLiteral` they compute. Each candidate is evaluated once on
+//! the EVM, so the folded value is exactly what the interpreter would produce.
+
+use crate::compiler::ast::UntypedAst;
+use crate::compiler::stack_effect::stack_effect;
+use crate::runner::revm_runner::EvmRunner;
+
+/// Fold every maximal constant subtree of `ast` into an `IntLiteral`.
+///
+/// A subtree is folded only if:
+/// - it never reads the input stacks (see [`StackEffect::is_self_contained`]),
+///   so it contains no `RAND` and no conditionals,
+/// - it leaves exactly one new int and no new bools behind,
+/// - running it on the EVM succeeds and the result fits in an `i32`.
+///
+/// Anything else (reverts, overflow, runner errors) leaves the subtree untouched.
+///
+/// [`StackEffect::is_self_contained`]: crate::compiler::stack_effect::StackEffect::is_self_contained
+pub fn const_fold(ast: &UntypedAst, runner: &mut EvmRunner) -> UntypedAst {
+    let children = match ast {
+        UntypedAst::Sublist(children) => children,
+        // Leaves are already as small as they get
+        UntypedAst::IntLiteral(_) | UntypedAst::Instruction(_) => return ast.clone(),
+    };
+
+    if let Some(val) = try_fold(ast, runner) {
+        return UntypedAst::IntLiteral(val);
+    }
+
+    UntypedAst::Sublist(
+        children
+            .iter()
+            .map(|child| const_fold(child, runner))
+            .collect(),
+    )
+}
+
+/// Evaluate `ast` on chain if it is a foldable constant, returning its value.
+fn try_fold(ast: &UntypedAst, runner: &mut EvmRunner) -> Option<i32> {
+    let effect = stack_effect(ast);
+    if !effect.is_self_contained() || effect.int_delta != 1 || effect.bool_delta != 0 {
+        return None;
+    }
+
+    let outputs = runner.run_ast(ast).ok()?;
+    if outputs.final_int_stack.len() != 1 || !outputs.final_bool_stack.is_empty() {
+        return None;
+    }
+    i32::try_from(outputs.final_int_stack[0]).ok()
+}
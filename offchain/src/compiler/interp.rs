@@ -0,0 +1,356 @@
+//! src/compiler/interp.rs
+//!
+//! A small pure-Rust reference interpreter for [`UntypedAst`] programs.
+//!
+//! It mirrors `Push3Interpreter.sol` step by step (same exec-stack loop, same
+//! "not enough operands => no-op" rule, same stack capacities), but runs in-process,
+//! which makes it handy for quick experiments and for cross-checking the EVM.
+//!
+//! Differences to keep in mind:
+//! - Integers are `i128` instead of `int256`. Any arithmetic that would leave the
+//!   `i128` range is reported as [`InterpError::Overflow`], even if `int256` would
+//!   still hold it.
+//! - `RAND` depends on block data and is not supported.
+
+use std::fmt;
+
+use crate::compiler::ast::{OpCode, UntypedAst};
+
+/// Extra room the on-chain interpreter allocates on top of each initial stack.
+const STACK_HEADROOM: usize = 256;
+
+/// Why `eval_pure` stopped early. On chain, all of these are reverts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpError {
+    /// Checked arithmetic overflowed.
+    Overflow(OpCode),
+    /// A stack grew beyond its initial length + 256.
+    StackOverflow,
+    /// The opcode has no deterministic meaning off chain (e.g. `RAND`).
+    Unsupported(OpCode),
+}
+
+impl fmt::Display for InterpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpError::Overflow(op) => write!(f, "arithmetic overflow in {:?}", op),
+            InterpError::StackOverflow => write!(f, "stack overflow"),
+            InterpError::Unsupported(op) => write!(f, "unsupported opcode {:?}", op),
+        }
+    }
+}
+
+impl std::error::Error for InterpError {}
+
+/// A stack with the same fixed capacity as the Solidity memory arrays.
+struct BoundedStack<T> {
+    items: Vec<T>,
+    capacity: usize,
+}
+
+impl<T: Copy> BoundedStack<T> {
+    fn new(init: &[T]) -> Self {
+        Self {
+            items: init.to_vec(),
+            capacity: init.len() + STACK_HEADROOM,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn push(&mut self, val: T) -> Result<(), InterpError> {
+        if self.items.len() >= self.capacity {
+            return Err(InterpError::StackOverflow);
+        }
+        self.items.push(val);
+        Ok(())
+    }
+
+    /// Pop, assuming the caller already checked the depth.
+    fn pop(&mut self) -> T {
+        self.items.pop().expect("depth checked by caller")
+    }
+}
+
+/// Run `ast` from the given initial int/bool stacks and return the final stacks
+/// (bottom first, like the EVM outputs).
+///
+/// ```
+/// use offchain::compiler::ast::{OpCode, UntypedAst};
+/// use offchain::compiler::interp::eval_pure;
+///
+/// let ast = UntypedAst::Sublist(vec![
+///     UntypedAst::IntLiteral(3),
+///     UntypedAst::Instruction(OpCode::Plus),
+/// ]);
+/// let (ints, bools) = eval_pure(&ast, &[4], &[]).unwrap();
+/// assert_eq!(ints, vec![7]);
+/// assert!(bools.is_empty());
+/// ```
+pub fn eval_pure(
+    ast: &UntypedAst,
+    int_stack: &[i128],
+    bool_stack: &[bool],
+) -> Result<(Vec<i128>, Vec<bool>), InterpError> {
+    run(ast, int_stack, bool_stack, |_, _, _, _| {})
+}
+
+/// The stacks right after one literal or instruction ran, as recorded by
+/// [`eval_pure_traced`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackSnapshot {
+    /// The `IntLiteral` or `Instruction` that ran.
+    pub item: UntypedAst,
+    /// Bottom first.
+    pub int_stack: Vec<i128>,
+    pub bool_stack: Vec<bool>,
+    /// Set on the last snapshot if this item stopped the run; the stacks are
+    /// then as the item left them.
+    pub error: Option<InterpError>,
+}
+
+/// Run `ast` like [`eval_pure`] (with an empty bool stack) and record the
+/// stacks after every literal and instruction. Entering a sublist changes no
+/// stack, so it gets no snapshot of its own.
+///
+/// ```
+/// use offchain::compiler::ast::{OpCode, UntypedAst};
+/// use offchain::compiler::interp::eval_pure_traced;
+/// use offchain::push3;
+///
+/// let trace = eval_pure_traced(&push3!((3 5 + 2 *)), &[]);
+/// let steps: Vec<(UntypedAst, Vec<i128>)> =
+///     trace.iter().map(|s| (s.item.clone(), s.int_stack.clone())).collect();
+/// assert_eq!(steps, [
+///     (UntypedAst::IntLiteral(3), vec![3]),
+///     (UntypedAst::IntLiteral(5), vec![3, 5]),
+///     (UntypedAst::Instruction(OpCode::Plus), vec![8]),
+///     (UntypedAst::IntLiteral(2), vec![8, 2]),
+///     (UntypedAst::Instruction(OpCode::Mult), vec![16]),
+/// ]);
+/// assert!(trace.iter().all(|s| s.bool_stack.is_empty() && s.error.is_none()));
+///
+/// // A failing run ends with the step that failed
+/// let trace = eval_pure_traced(&push3!((2 *)), &[i128::MAX]);
+/// assert_eq!(trace.len(), 2);
+/// assert_eq!(trace[1].int_stack, Vec::<i128>::new());
+/// assert!(trace[1].error.is_some());
+/// ```
+pub fn eval_pure_traced(ast: &UntypedAst, int_stack: &[i128]) -> Vec<StackSnapshot> {
+    let mut trace = Vec::new();
+    let _ = run(ast, int_stack, &[], |item, ints, bools, error| {
+        trace.push(StackSnapshot {
+            item: item.clone(),
+            int_stack: ints.to_vec(),
+            bool_stack: bools.to_vec(),
+            error: error.cloned(),
+        })
+    });
+    trace
+}
+
+/// The exec-stack loop shared by [`eval_pure`] and [`eval_pure_traced`]:
+/// `on_step` sees every literal and instruction with the stacks it left,
+/// and every item that failed, with its error.
+fn run(
+    ast: &UntypedAst,
+    int_stack: &[i128],
+    bool_stack: &[bool],
+    mut on_step: impl FnMut(&UntypedAst, &[i128], &[bool], Option<&InterpError>),
+) -> Result<(Vec<i128>, Vec<bool>), InterpError> {
+    let mut exec: BoundedStack<&UntypedAst> = BoundedStack::new(&[ast]);
+    let mut ints = BoundedStack::new(int_stack);
+    let mut bools = BoundedStack::new(bool_stack);
+
+    while let Some(item) = exec.items.pop() {
+        let result = match item {
+            UntypedAst::IntLiteral(val) => ints.push(*val as i128),
+            UntypedAst::Sublist(children) => {
+                // push in reverse so the first child runs first
+                children.iter().rev().try_for_each(|child| exec.push(child))
+            }
+            UntypedAst::Instruction(op) => step(op, &mut exec, &mut ints, &mut bools),
+        };
+        if result.is_err() || !matches!(item, UntypedAst::Sublist(_)) {
+            on_step(item, &ints.items, &bools.items, result.as_ref().err());
+        }
+        result?;
+    }
+
+    Ok((ints.items, bools.items))
+}
+
+/// Execute one instruction. Ops without enough operands do nothing, as on chain.
+fn step(
+    op: &OpCode,
+    exec: &mut BoundedStack<&UntypedAst>,
+    ints: &mut BoundedStack<i128>,
+    bools: &mut BoundedStack<bool>,
+) -> Result<(), InterpError> {
+    use OpCode::*;
+    let overflow = || InterpError::Overflow(op.clone());
+
+    match op {
+        Noop => {}
+
+        // (b, a) => b OP a, with `a` on top
+        Plus | Minus | Mult | Mod | Pow => {
+            if ints.len() >= 2 {
+                let a = ints.pop();
+                let b = ints.pop();
+                let result = match op {
+                    Plus => b.checked_add(a).ok_or_else(overflow)?,
+                    Minus => b.checked_sub(a).ok_or_else(overflow)?,
+                    Mult => b.checked_mul(a).ok_or_else(overflow)?,
+                    Mod => if a != 0 { b.wrapping_rem(a) } else { 0 },
+                    _ => pow(b, a).ok_or_else(overflow)?,
+                };
+                ints.push(result)?;
+            }
+        }
+        Dup => {
+            if !ints.is_empty() {
+                let a = *ints.items.last().unwrap();
+                ints.push(a)?;
+            }
+        }
+        Pop => {
+            if !ints.is_empty() {
+                ints.pop();
+            }
+        }
+
+        GreaterThan | LessThan | Equal | NotEqual | GreaterEqual | LessEqual => {
+            if ints.len() >= 2 {
+                let a = ints.pop();
+                let b = ints.pop();
+                let result = match op {
+                    GreaterThan => b > a,
+                    LessThan => b < a,
+                    Equal => b == a,
+                    NotEqual => b != a,
+                    GreaterEqual => b >= a,
+                    _ => b <= a,
+                };
+                bools.push(result)?;
+            }
+        }
+
+        // Unary ops replace the top in place
+        Sin | Cos | Sqrt | Abs => {
+            if let Some(top) = ints.items.last_mut() {
+                let a = *top;
+                *top = match op {
+                    Sin => sin(a),
+                    Cos => sin(a.checked_add(900).ok_or_else(overflow)?),
+                    Sqrt => if a >= 0 { isqrt(a as u128) as i128 } else { 0 },
+                    _ => a.checked_abs().ok_or_else(overflow)?,
+                };
+            }
+        }
+
+        ConstPi => ints.push(3141)?,
+        ConstE => ints.push(2718)?,
+        ConstRand => return Err(InterpError::Unsupported(op.clone())),
+
+        BoolToInt => {
+            if !bools.is_empty() {
+                let val = bools.pop();
+                ints.push(val as i128)?;
+            }
+        }
+        IntToBool => {
+            if !ints.is_empty() {
+                let val = ints.pop();
+                bools.push(val != 0)?;
+            }
+        }
+
+        IfThen => {
+            if !bools.is_empty() && !exec.is_empty() {
+                let condition = bools.pop();
+                if !condition {
+                    // skip the next exec item
+                    exec.pop();
+                }
+            }
+        }
+        IfElse => {
+            if !bools.is_empty() && exec.len() >= 2 {
+                let condition = bools.pop();
+                let then_item = exec.pop();
+                let else_item = exec.pop();
+                exec.push(if condition { then_item } else { else_item })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Same algorithm as `_pow` on chain, including the final `b * b` which can
+/// overflow even when its result is not needed.
+fn pow(base: i128, exponent: i128) -> Option<i128> {
+    if exponent < 0 {
+        return Some(0);
+    }
+    if exponent == 0 {
+        return Some(1);
+    }
+    if base == 0 {
+        return Some(0);
+    }
+
+    let mut result: i128 = 1;
+    let mut b = base;
+    let mut exp = exponent as u128;
+    while exp > 0 {
+        if exp % 2 == 1 {
+            result = result.checked_mul(b)?;
+        }
+        b = b.checked_mul(b)?;
+        exp /= 2;
+    }
+    Some(result)
+}
+
+/// Integer square root, same iteration as `_sqrt` on chain.
+fn isqrt(x: u128) -> u128 {
+    if x == 0 {
+        return 0;
+    }
+    if x < 4 {
+        return 1;
+    }
+    let mut z = x;
+    let mut y = x / 2 + 1;
+    while y < z {
+        z = y;
+        y = (x / y + y) / 2;
+    }
+    z
+}
+
+/// Piecewise-linear sine on degrees * 10, same as `_sin` on chain.
+fn sin(x: i128) -> i128 {
+    let mut x = x % 3600;
+    if x < 0 {
+        x += 3600;
+    }
+
+    match x {
+        0 => 0,
+        900 => 1000,
+        1800 => 0,
+        2700 => -1000,
+        x if x <= 900 => (1000 * x) / 900,
+        x if x <= 1800 => (1000 * (1800 - x)) / 900,
+        x if x <= 2700 => -(1000 * (x - 1800)) / 900,
+        x => -(1000 * (3600 - x)) / 900,
+    }
+}
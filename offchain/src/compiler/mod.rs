@@ -1,2 +1,5 @@
 pub mod ast;
+pub mod fold;
+pub mod interp;
 pub mod push3_describtor;
+pub mod stack_effect;
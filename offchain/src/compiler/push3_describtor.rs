@@ -4,13 +4,19 @@
 //! used by our on-chain `Push3Interpreter`. Each descriptor encodes a tag, an offset,
 //! a length, and any leftover bits (e.g., immediate data).
 
+use std::fmt;
+
 use ethers::types::U256;
 
+use crate::compiler::ast::{
+    bytecode_to_ast, DecodeError, OpCodeMapping, Push3Ast, UntypedAst, DEFAULT_OP_MAPPING,
+};
+
 /// The 256-bit descriptor layout is as follows:
 /// [  8 bits: tag  |  32 bits: offset  |  32 bits: length  |  184 bits: leftover ]
 ///
 /// We'll define small helper functions to build or parse these. 
-/// Our `make_sublist_descriptor` is an example for tag=3 (SUBLIST).
+/// Our `make_sublist_descriptor` is an example for tag=4 (SUBLIST).
 
 /// Tag constants, matching Solidity CodeTag enum:
 pub const TAG_NONE: u8 = 0;
@@ -30,8 +36,15 @@ fn tag_bits(tag: u8) -> U256 {
 ///
 /// # Example
 /// ```
+/// use offchain::compiler::ast::Push3Ast;
+/// use offchain::compiler::push3_describtor::*;
+/// use offchain::push3;
+///
+/// let code_bytes = push3!((3 5 +)).to_bytecode();
 /// let desc = make_sublist_descriptor(0, code_bytes.len() as u32);
 /// // pass `desc` into the interpreter's exec stack
+/// assert_eq!(get_tag(desc), TAG_SUBLIST);
+/// assert_eq!((get_offset(desc), get_length(desc)), (0, code_bytes.len() as u32));
 /// ```
 pub fn make_sublist_descriptor(offset: u32, length: u32) -> U256 {
     tag_bits(TAG_SUBLIST as u8)
@@ -40,6 +53,32 @@ pub fn make_sublist_descriptor(offset: u32, length: u32) -> U256 {
         | U256::zero() // leftover
 }
 
+/// Like `make_sublist_descriptor`, but packs an execution `budget` into the low
+/// 64 bits of the leftover field (the interpreter currently ignores it).
+/// `make_sublist_descriptor(o, l)` is the same as a budget of 0.
+///
+/// ```
+/// use offchain::compiler::push3_describtor::*;
+///
+/// let desc = make_sublist_descriptor_with_budget(12, 34, 5_000);
+/// assert_eq!(get_tag(desc), TAG_SUBLIST);
+/// assert_eq!((get_offset(desc), get_length(desc), get_budget(desc)), (12, 34, 5_000));
+///
+/// let max = make_sublist_descriptor_with_budget(u32::MAX, u32::MAX, u64::MAX);
+/// assert_eq!((get_offset(max), get_length(max), get_budget(max)), (u32::MAX, u32::MAX, u64::MAX));
+///
+/// assert_eq!(make_sublist_descriptor_with_budget(7, 9, 0), make_sublist_descriptor(7, 9));
+/// assert_eq!(get_budget(make_sublist_descriptor(7, 9)), 0);
+/// ```
+pub fn make_sublist_descriptor_with_budget(offset: u32, length: u32, budget: u64) -> U256 {
+    make_descriptor(TAG_SUBLIST, offset, length, U256::from(budget))
+}
+
+/// The budget stored by `make_sublist_descriptor_with_budget` (low 64 bits).
+pub fn get_budget(desc: U256) -> u64 {
+    desc.low_u64()
+}
+
 /// Possibly you want a more general helper that builds any descriptor:
 /// If you have other uses for leftover bits, you can pass that in:
 pub fn make_descriptor(tag: u8, offset: u32, length: u32, leftover: U256) -> U256 {
@@ -66,3 +105,218 @@ pub fn get_length(desc: U256) -> u32 {
 pub fn get_low_184(desc: U256) -> U256 {
     desc & ((U256::from(1u64) << 184) - 1)
 }
+
+/// Append each AST's bytecode to `code` and return one SUBLIST descriptor per
+/// AST, pointing at its bytes, in the same order as `asts`.
+pub fn append_sublists(asts: &[&UntypedAst], code: &mut Vec<u8>) -> Vec<U256> {
+    asts.iter()
+        .map(|ast| {
+            let offset = code.len();
+            code.extend(ast.to_bytecode());
+            make_sublist_descriptor(offset as u32, (code.len() - offset) as u32)
+        })
+        .collect()
+}
+
+/// Build an `init_exec_stack` that runs `asts` one after the other: their
+/// bytecode is appended to the shared `code` buffer (passed as the `code`
+/// input of the same call), and the descriptors are ordered so `asts[0]` is on
+/// top of the stack and runs first.
+///
+/// ```
+/// use offchain::compiler::ast::Push3Ast;
+/// use offchain::compiler::push3_describtor::*;
+/// use offchain::push3;
+///
+/// let (first, second) = (push3!((3 5 +)), push3!((DUP *)));
+/// let mut code = Vec::new();
+/// let exec_stack = exec_stack_from_asts(&[&first, &second], &mut code);
+///
+/// assert_eq!(code, [first.to_bytecode(), second.to_bytecode()].concat());
+/// // The top of the stack (last element) runs first
+/// let top = exec_stack[1];
+/// assert_eq!(get_tag(top), TAG_SUBLIST);
+/// assert_eq!((get_offset(top), get_length(top)), (0, first.bytecode_len() as u32));
+/// let below = exec_stack[0];
+/// assert_eq!(
+///     (get_offset(below), get_length(below)),
+///     (first.bytecode_len() as u32, second.bytecode_len() as u32)
+/// );
+/// ```
+pub fn exec_stack_from_asts(asts: &[&UntypedAst], code: &mut Vec<u8>) -> Vec<U256> {
+    let mut descriptors = append_sublists(asts, code);
+    descriptors.reverse();
+    descriptors
+}
+
+/// Like [`exec_stack_from_asts`], for `init_code_stack`: `asts[0]` ends up on top.
+pub fn code_stack_from_asts(asts: &[&UntypedAst], code: &mut Vec<u8>) -> Vec<U256> {
+    exec_stack_from_asts(asts, code)
+}
+
+/// A descriptor split into its fields, by tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Descriptor {
+    None,
+    /// The opcode byte, in the low bits.
+    Instruction(u8),
+    /// The value, in the low 32 bits.
+    IntLiteral(i32),
+    BoolLiteral(bool),
+    /// `length` bytes of `code` from `offset`.
+    Sublist { offset: u32, length: u32 },
+    /// A tag the interpreter doesn't define.
+    Unknown(u8),
+}
+
+/// Split a descriptor, e.g. one of `final_code_stack` / `final_exec_stack`.
+pub fn decode_descriptor(desc: U256) -> Descriptor {
+    match get_tag(desc) {
+        TAG_NONE => Descriptor::None,
+        TAG_INSTRUCTION => Descriptor::Instruction(desc.low_u32() as u8),
+        TAG_INT_LITERAL => Descriptor::IntLiteral(desc.low_u32() as i32),
+        TAG_BOOL_LITERAL => Descriptor::BoolLiteral(desc.low_u32() & 1 == 1),
+        TAG_SUBLIST => Descriptor::Sublist { offset: get_offset(desc), length: get_length(desc) },
+        tag => Descriptor::Unknown(tag),
+    }
+}
+
+/// Why [`descriptor_to_ast`] could not rebuild a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DescriptorError {
+    /// The descriptor holds no code (`None`, a bool literal, or an unknown tag).
+    NotCode(Descriptor),
+    /// The opcode byte is not one `DEFAULT_OP_MAPPING` knows.
+    UnknownOpcode(u8),
+    /// The sublist points outside the code buffer.
+    OutOfBounds { offset: u32, length: u32, code_len: usize },
+    /// The sublist's bytes don't decode to one node.
+    Decode(DecodeError),
+    /// A sublist descriptor was expected.
+    NotSublist(Descriptor),
+    /// The sublist descriptor does not point at a sublist header: `byte` is
+    /// not the sublist tag, or the header runs past the end of the code.
+    NoSublistHeader { offset: u32, byte: Option<u8> },
+    /// The descriptor's length disagrees with the inline length prefix
+    /// at `offset` (which excludes the 3-byte header).
+    LengthMismatch { offset: u32, length: u32, inline_length: u16 },
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptorError::NotCode(desc) => write!(f, "descriptor holds no code: {:?}", desc),
+            DescriptorError::UnknownOpcode(byte) => write!(f, "unknown opcode 0x{:02x}", byte),
+            DescriptorError::OutOfBounds { offset, length, code_len } => write!(
+                f,
+                "sublist at {} of length {} is outside the {}-byte code buffer",
+                offset, length, code_len
+            ),
+            DescriptorError::Decode(e) => write!(f, "invalid sublist bytecode: {}", e),
+            DescriptorError::NotSublist(desc) => write!(f, "not a sublist descriptor: {:?}", desc),
+            DescriptorError::NoSublistHeader { offset, byte: Some(byte) } => {
+                write!(f, "no sublist header at {}: found byte 0x{:02x}", offset, byte)
+            }
+            DescriptorError::NoSublistHeader { offset, byte: None } => {
+                write!(f, "no sublist header at {}: code ends first", offset)
+            }
+            DescriptorError::LengthMismatch { offset, length, inline_length } => write!(
+                f,
+                "sublist at {}: descriptor length {} does not match inline length {} + 3-byte header",
+                offset, length, inline_length
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DescriptorError {}
+
+/// Rebuild the program a descriptor stands for. A sublist descriptor is
+/// resolved against `code`, the buffer passed as the `code` input of the same
+/// call, and must span exactly one encoded node (as built by
+/// [`append_sublists`]).
+///
+/// ```
+/// use offchain::compiler::push3_describtor::*;
+/// use offchain::compiler::ast::{OpCode, UntypedAst};
+/// use offchain::push3;
+///
+/// let (a, b) = (push3!((3 5 +)), push3!((DUP (2 *))));
+/// let mut code = Vec::new();
+/// let code_stack = code_stack_from_asts(&[&a, &b], &mut code);
+/// assert_eq!(decode_stack(&code_stack, &code), Ok(vec![b.clone(), a.clone()]));
+///
+/// let op = make_descriptor(TAG_INSTRUCTION, 0, 0, 0x05.into());
+/// assert_eq!(descriptor_to_ast(op, &code), Ok(UntypedAst::Instruction(OpCode::Plus)));
+/// let lit = make_descriptor(TAG_INT_LITERAL, 0, 0, (-4i32 as u32).into());
+/// assert_eq!(descriptor_to_ast(lit, &code), Ok(UntypedAst::IntLiteral(-4)));
+///
+/// let past_end = make_sublist_descriptor(code.len() as u32, 3);
+/// assert!(matches!(descriptor_to_ast(past_end, &code), Err(DescriptorError::OutOfBounds { .. })));
+/// ```
+pub fn descriptor_to_ast(desc: U256, code: &[u8]) -> Result<UntypedAst, DescriptorError> {
+    match decode_descriptor(desc) {
+        Descriptor::Instruction(byte) => DEFAULT_OP_MAPPING
+            .opcode_from_byte(byte)
+            .map(UntypedAst::Instruction)
+            .ok_or(DescriptorError::UnknownOpcode(byte)),
+        Descriptor::IntLiteral(val) => Ok(UntypedAst::IntLiteral(val)),
+        Descriptor::Sublist { offset, length } => {
+            let bytes = code
+                .get(offset as usize..offset as usize + length as usize)
+                .ok_or(DescriptorError::OutOfBounds { offset, length, code_len: code.len() })?;
+            bytecode_to_ast(bytes).map_err(DescriptorError::Decode)
+        }
+        other => Err(DescriptorError::NotCode(other)),
+    }
+}
+
+/// Check that a SUBLIST descriptor agrees with the bytecode it points at: the
+/// bytes at its offset must be a sublist header whose inline 2-byte length,
+/// plus the 3-byte header itself, equals the descriptor's length (as
+/// [`append_sublists`] builds them).
+///
+/// ```
+/// use offchain::compiler::push3_describtor::*;
+/// use offchain::push3;
+///
+/// let (a, b) = (push3!((3 5 +)), push3!((DUP (2 *))));
+/// let mut code = Vec::new();
+/// for desc in append_sublists(&[&a, &b], &mut code) {
+///     assert_eq!(verify_descriptor_matches_code(desc, &code), Ok(()));
+/// }
+///
+/// // (3 5 +) is 14 bytes: a 3-byte header and 11 bytes of children
+/// assert_eq!(
+///     verify_descriptor_matches_code(make_sublist_descriptor(0, 11), &code),
+///     Err(DescriptorError::LengthMismatch { offset: 0, length: 11, inline_length: 11 })
+/// );
+/// assert_eq!(
+///     verify_descriptor_matches_code(make_sublist_descriptor(3, 5), &code),
+///     Err(DescriptorError::NoSublistHeader { offset: 3, byte: Some(0x02) })
+/// );
+/// ```
+pub fn verify_descriptor_matches_code(desc: U256, code: &[u8]) -> Result<(), DescriptorError> {
+    let Descriptor::Sublist { offset, length } = decode_descriptor(desc) else {
+        return Err(DescriptorError::NotSublist(decode_descriptor(desc)));
+    };
+    let start = offset as usize;
+    if start + length as usize > code.len() {
+        return Err(DescriptorError::OutOfBounds { offset, length, code_len: code.len() });
+    }
+    let header = match code.get(start..start + 3) {
+        Some(header) if header[0] == TAG_SUBLIST => header,
+        _ => return Err(DescriptorError::NoSublistHeader { offset, byte: code.get(start).copied() }),
+    };
+    let inline_length = u16::from_be_bytes([header[1], header[2]]);
+    if length != inline_length as u32 + 3 {
+        return Err(DescriptorError::LengthMismatch { offset, length, inline_length });
+    }
+    Ok(())
+}
+
+/// [`descriptor_to_ast`] of every entry of a final code or exec stack, bottom
+/// to top.
+pub fn decode_stack(stack: &[U256], code: &[u8]) -> Result<Vec<UntypedAst>, DescriptorError> {
+    stack.iter().map(|&desc| descriptor_to_ast(desc, code)).collect()
+}
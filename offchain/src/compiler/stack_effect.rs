@@ -0,0 +1,153 @@
+//! src/compiler/stack_effect.rs
+//!
+//! Static stack-effect analysis for [`UntypedAst`] programs.
+//!
+//! For every node we compute how deep the int/bool stacks must be *before* it runs
+//! so that no instruction silently no-ops for lack of operands, and how much each
+//! stack grows or shrinks afterwards. This lets later passes (e.g. constant folding)
+//! decide whether a subtree is self-contained without running it.
+
+use crate::compiler::ast::{OpCode, UntypedAst};
+
+/// Summary of what a subtree does to the stacks when run in isolation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StackEffect {
+    /// Minimum int stack depth required so that every op finds its operands.
+    pub int_needed: usize,
+    /// Net change of the int stack depth (pushes minus pops).
+    pub int_delta: isize,
+    /// Minimum bool stack depth required so that every op finds its operands.
+    pub bool_needed: usize,
+    /// Net change of the bool stack depth.
+    pub bool_delta: isize,
+    /// `IF_THEN` / `IF_ELSE` appear in the subtree. They touch the exec stack,
+    /// so the counts above are only valid for the straight-line case.
+    pub has_conditionals: bool,
+    /// `RAND` appears in the subtree, so its result is not a pure function of the inputs.
+    pub nondeterministic: bool,
+}
+
+impl StackEffect {
+    /// Effect of a single instruction with the given pops/pushes per stack.
+    fn op(int_pop: usize, int_push: usize, bool_pop: usize, bool_push: usize) -> Self {
+        Self {
+            int_needed: int_pop,
+            int_delta: int_push as isize - int_pop as isize,
+            bool_needed: bool_pop,
+            bool_delta: bool_push as isize - bool_pop as isize,
+            has_conditionals: false,
+            nondeterministic: false,
+        }
+    }
+
+    /// Effect of running `self` and then `next`.
+    pub fn then(self, next: StackEffect) -> StackEffect {
+        StackEffect {
+            int_needed: self.int_needed.max(needed_after(next.int_needed, self.int_delta)),
+            int_delta: self.int_delta + next.int_delta,
+            bool_needed: self.bool_needed.max(needed_after(next.bool_needed, self.bool_delta)),
+            bool_delta: self.bool_delta + next.bool_delta,
+            has_conditionals: self.has_conditionals || next.has_conditionals,
+            nondeterministic: self.nondeterministic || next.nondeterministic,
+        }
+    }
+
+    /// True if `other` leaves the int and bool stacks the same net depth as
+    /// `self`, so either can stand in for the other without changing what the
+    /// surrounding program sees produced.
+    pub fn same_net_effect(&self, other: &StackEffect) -> bool {
+        self.int_delta == other.int_delta && self.bool_delta == other.bool_delta
+    }
+
+    /// True if the subtree runs from empty stacks without any op being skipped,
+    /// and has no conditionals or randomness. Such a subtree always computes the
+    /// same thing, wherever it is placed.
+    pub fn is_self_contained(&self) -> bool {
+        self.int_needed == 0
+            && self.bool_needed == 0
+            && !self.has_conditionals
+            && !self.nondeterministic
+    }
+}
+
+/// Depth the stack needs *before* the first part, given that the second part
+/// needs `needed` and the first part changed the depth by `delta`.
+fn needed_after(needed: usize, delta: isize) -> usize {
+    (needed as isize - delta).max(0) as usize
+}
+
+/// Stack effect of a single opcode, mirroring the on-chain interpreter.
+pub fn opcode_effect(op: &OpCode) -> StackEffect {
+    use OpCode::*;
+    match op {
+        Noop => StackEffect::default(),
+        Plus | Minus | Mult | Mod | Pow => StackEffect::op(2, 1, 0, 0),
+        Dup => StackEffect::op(1, 2, 0, 0),
+        Pop => StackEffect::op(1, 0, 0, 0),
+        GreaterThan | LessThan | Equal | NotEqual | GreaterEqual | LessEqual => {
+            StackEffect::op(2, 0, 0, 1)
+        }
+        Sin | Cos | Sqrt | Abs => StackEffect::op(1, 1, 0, 0),
+        ConstPi | ConstE => StackEffect::op(0, 1, 0, 0),
+        ConstRand => StackEffect {
+            nondeterministic: true,
+            ..StackEffect::op(0, 1, 0, 0)
+        },
+        BoolToInt => StackEffect::op(0, 1, 1, 0),
+        IntToBool => StackEffect::op(1, 0, 0, 1),
+        IfThen | IfElse => StackEffect {
+            has_conditionals: true,
+            ..StackEffect::op(0, 0, 1, 0)
+        },
+    }
+}
+
+/// Compute the [`StackEffect`] of a whole subtree.
+/// A `Sublist` runs its children in order, so their effects are chained.
+pub fn stack_effect(ast: &UntypedAst) -> StackEffect {
+    match ast {
+        UntypedAst::IntLiteral(_) => StackEffect::op(0, 1, 0, 0),
+        UntypedAst::Instruction(op) => opcode_effect(op),
+        UntypedAst::Sublist(children) => children
+            .iter()
+            .map(stack_effect)
+            .fold(StackEffect::default(), StackEffect::then),
+    }
+}
+
+/// Exact int and bool stack depths after running `ast` from the given depths.
+/// The interpreter skips an op that lacks operands, so, without conditionals,
+/// the depths never depend on the values. `None` if `ast` has `IF_THEN` /
+/// `IF_ELSE`, whose path does.
+///
+/// ```
+/// use offchain::compiler::stack_effect::final_depths;
+/// use offchain::push3;
+///
+/// assert_eq!(final_depths(&push3!((DUP *)), 1, 0), Some((1, 0)));
+/// // The second POP finds nothing and is skipped; so is the `+`
+/// assert_eq!(final_depths(&push3!((POP POP +)), 1, 0), Some((0, 0)));
+/// assert_eq!(final_depths(&push3!((3 >)), 1, 0), Some((0, 1)));
+/// assert_eq!(final_depths(&push3!((0 > IF_THEN 5)), 1, 0), None);
+/// ```
+pub fn final_depths(ast: &UntypedAst, int_depth: usize, bool_depth: usize) -> Option<(usize, usize)> {
+    match ast {
+        UntypedAst::IntLiteral(_) => Some((int_depth + 1, bool_depth)),
+        UntypedAst::Instruction(op) => {
+            let effect = opcode_effect(op);
+            if effect.has_conditionals {
+                return None;
+            }
+            if int_depth < effect.int_needed || bool_depth < effect.bool_needed {
+                return Some((int_depth, bool_depth));
+            }
+            Some((
+                (int_depth as isize + effect.int_delta) as usize,
+                (bool_depth as isize + effect.bool_delta) as usize,
+            ))
+        }
+        UntypedAst::Sublist(children) => children
+            .iter()
+            .try_fold((int_depth, bool_depth), |(ints, bools), child| final_depths(child, ints, bools)),
+    }
+}
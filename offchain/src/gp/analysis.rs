@@ -0,0 +1,308 @@
+//! src/gp/analysis.rs
+//!
+//! Per-sample breakdown of how a program does against a target, the final
+//! stacks it leaves (bool stack included, for comparison programs), and the
+//! printing shared by the bins that inspect a finished program.
+
+use std::io::{self, Write};
+
+use crate::compiler::ast::{OpCode, Push3Ast, UntypedAst};
+use crate::gp::fitness::{abs_error, safe_eval_with, AnswerPolicy};
+use crate::runner::backend::Push3Backend;
+use crate::runner::revm_runner::Push3InterpreterInputs;
+
+/// Largest error still reported as "close".
+pub const CLOSE_ERROR: i128 = 5;
+
+/// One sample run through a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleRow {
+    pub x: i128,
+    pub target: i128,
+    /// `None` if the run failed (revert, out of gas, or an empty stack to read
+    /// the answer from).
+    pub predicted: Option<i128>,
+}
+
+impl SampleRow {
+    pub fn error(&self) -> Option<i128> {
+        self.predicted.map(|p| abs_error(p, self.target))
+    }
+
+    /// `PERFECT`, `CLOSE(e)`, `OFF(e)` or `FAIL`.
+    pub fn status(&self) -> String {
+        match self.error() {
+            Some(0) => "PERFECT".to_string(),
+            Some(e) if e <= CLOSE_ERROR => format!("CLOSE({})", e),
+            Some(e) => format!("OFF({})", e),
+            None => "FAIL".to_string(),
+        }
+    }
+}
+
+/// Error statistics over a set of [`SampleRow`]s. The error figures only cover
+/// the samples that ran; they are `None` if none did.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorSummary {
+    pub samples: usize,
+    pub perfect: usize,
+    /// Off by at most [`CLOSE_ERROR`], but not perfect.
+    pub close: usize,
+    pub failures: usize,
+    pub mean_abs_error: Option<f64>,
+    pub max_abs_error: Option<i128>,
+    pub mse: Option<f64>,
+}
+
+impl ErrorSummary {
+    /// ```
+    /// use offchain::gp::analysis::{ErrorSummary, SampleRow};
+    ///
+    /// let rows = [
+    ///     SampleRow { x: 0, target: 1, predicted: Some(1) },
+    ///     SampleRow { x: 1, target: 2, predicted: Some(5) },
+    ///     SampleRow { x: 2, target: 5, predicted: None },
+    /// ];
+    /// let summary = ErrorSummary::from_rows(&rows);
+    /// assert_eq!((summary.perfect, summary.close, summary.failures), (1, 1, 1));
+    /// assert_eq!(summary.mean_abs_error, Some(1.5));
+    /// assert_eq!(summary.max_abs_error, Some(3));
+    /// assert_eq!(summary.mse, Some(4.5));
+    /// ```
+    pub fn from_rows(rows: &[SampleRow]) -> Self {
+        let errors: Vec<i128> = rows.iter().filter_map(SampleRow::error).collect();
+        let ran = errors.len() as f64;
+        let (mean_abs_error, mse) = if errors.is_empty() {
+            (None, None)
+        } else {
+            (
+                Some(errors.iter().map(|&e| e as f64).sum::<f64>() / ran),
+                Some(errors.iter().map(|&e| (e as f64).powi(2)).sum::<f64>() / ran),
+            )
+        };
+        Self {
+            samples: rows.len(),
+            perfect: errors.iter().filter(|&&e| e == 0).count(),
+            close: errors.iter().filter(|&&e| e > 0 && e <= CLOSE_ERROR).count(),
+            failures: rows.len() - errors.len(),
+            mean_abs_error,
+            max_abs_error: errors.iter().copied().max(),
+            mse,
+        }
+    }
+
+    pub fn print(&self) {
+        let n = self.samples;
+        println!("Perfect matches: {}/{}", self.perfect, n);
+        println!("Close matches (≤{}): {}/{}", CLOSE_ERROR, self.close, n);
+        println!("Failures: {}/{}", self.failures, n);
+        match (self.mean_abs_error, self.max_abs_error, self.mse) {
+            (Some(mean), Some(max), Some(mse)) => {
+                println!("Average error: {:.1}", mean);
+                println!("Max error: {}", max);
+                println!("MSE: {:.1}", mse);
+            }
+            _ => println!("Average error: ∞"),
+        }
+        if n > 0 {
+            println!("Success rate: {:.1}%", (n - self.failures) as f64 / n as f64 * 100.0);
+        }
+    }
+}
+
+/// Run `ast` on each `(x, target)` sample.
+pub fn sample_rows(runner: &mut impl Push3Backend, ast: &UntypedAst, samples: &[(i128, i128)]) -> Vec<SampleRow> {
+    sample_rows_with(runner, ast, samples, AnswerPolicy::TopInt)
+}
+
+/// [`sample_rows`] reading the prediction according to `policy`, e.g.
+/// `AnswerPolicy::TopBool` to score a comparison as 1 / 0.
+pub fn sample_rows_with(
+    runner: &mut impl Push3Backend,
+    ast: &UntypedAst,
+    samples: &[(i128, i128)],
+    policy: AnswerPolicy,
+) -> Vec<SampleRow> {
+    samples
+        .iter()
+        .map(|&(x, target)| SampleRow { x, target, predicted: safe_eval_with(runner, ast, &[x], policy).value() })
+        .collect()
+}
+
+/// Print one line per sample: x, target, prediction, error and status.
+pub fn print_sample_table(rows: &[SampleRow]) {
+    println!("x\tTarget\tPredicted\tError\tStatus");
+    println!("─────────────────────────────────────");
+    for row in rows {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            row.x,
+            row.target,
+            row.predicted.map_or("FAIL".to_string(), |p| p.to_string()),
+            row.error().map_or("∞".to_string(), |e| e.to_string()),
+            row.status()
+        );
+    }
+}
+
+/// The final stacks of one run on `x`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackRow {
+    pub x: i128,
+    /// The int and bool stacks, bottom to top, or `None` if the run failed.
+    pub final_stacks: Option<(Vec<i128>, Vec<bool>)>,
+}
+
+/// Run `ast` on each `x` and keep both final stacks, so programs answering on
+/// the bool stack can be inspected too.
+pub fn stack_rows(runner: &mut impl Push3Backend, ast: &UntypedAst, xs: &[i128]) -> Vec<StackRow> {
+    let code = ast.to_bytecode();
+    xs.iter()
+        .map(|&x| {
+            let inputs = Push3InterpreterInputs::for_program(code.clone(), &[x], Vec::new());
+            let final_stacks = runner.run(&inputs).ok().map(|out| (out.final_int_stack, out.final_bool_stack));
+            StackRow { x, final_stacks }
+        })
+        .collect()
+}
+
+/// Write one line per run: x, then the final int and bool stacks (top last).
+///
+/// ```
+/// use offchain::gp::analysis::{write_stack_table, StackRow};
+///
+/// let rows = [
+///     StackRow { x: 3, final_stacks: Some((vec![], vec![false])) },
+///     StackRow { x: 7, final_stacks: Some((vec![1], vec![true])) },
+///     StackRow { x: 9, final_stacks: None },
+/// ];
+/// let mut out = Vec::new();
+/// write_stack_table(&rows, &mut out).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "x\tInt stack\tBool stack
+/// 3\t[]\t[false]
+/// 7\t[1]\t[true]
+/// 9\tFAIL\tFAIL
+/// "
+/// );
+/// ```
+pub fn write_stack_table(rows: &[StackRow], out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "x\tInt stack\tBool stack")?;
+    for row in rows {
+        match &row.final_stacks {
+            Some((ints, bools)) => writeln!(out, "{}\t{:?}\t{:?}", row.x, ints, bools)?,
+            None => writeln!(out, "{}\tFAIL\tFAIL", row.x)?,
+        }
+    }
+    Ok(())
+}
+
+/// How [`describe_ast`] renders each node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescribeOptions {
+    /// Follow each instruction with its [`OpCode::description`].
+    pub verbose: bool,
+    /// Mark each line with a symbol instead of a `Constant:` / `Operation:` label.
+    pub emoji: bool,
+}
+
+impl Default for DescribeOptions {
+    fn default() -> Self {
+        Self { verbose: true, emoji: false }
+    }
+}
+
+/// Write `ast` to `out` as an indented outline, one node per line.
+///
+/// ```
+/// use offchain::gp::analysis::{describe_ast, DescribeOptions};
+/// use offchain::push3;
+///
+/// let ast = push3!((DUP (2 *) +));
+/// let mut out = Vec::new();
+/// describe_ast(&ast, &mut out, DescribeOptions::default()).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "Block with 3 operations:
+///   Operation: Dup - push a copy of the top int
+///   Block with 2 operations:
+///     Constant: 2
+///     Operation: Mult - pop a, b; push b * a
+///   Operation: Plus - pop a, b; push b + a
+/// "
+/// );
+///
+/// let mut out = Vec::new();
+/// describe_ast(&ast, &mut out, DescribeOptions { verbose: false, emoji: true }).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "📦 Block with 3 operations:
+///   📋 Dup
+///   📦 Block with 2 operations:
+///     📊 2
+///     ✖️ Mult
+///   ➕ Plus
+/// "
+/// );
+/// ```
+pub fn describe_ast(ast: &UntypedAst, out: &mut impl Write, options: DescribeOptions) -> io::Result<()> {
+    describe_node(ast, out, options, 0)
+}
+
+fn describe_node(ast: &UntypedAst, out: &mut impl Write, options: DescribeOptions, depth: usize) -> io::Result<()> {
+    let indent = "  ".repeat(depth);
+
+    match ast {
+        UntypedAst::IntLiteral(val) => {
+            let label = if options.emoji { "📊" } else { "Constant:" };
+            writeln!(out, "{}{} {}", indent, label, val)?;
+        }
+        UntypedAst::Instruction(op) => {
+            let label = if options.emoji { emoji(op) } else { "Operation:" };
+            if options.verbose {
+                writeln!(out, "{}{} {:?} - {}", indent, label, op, op.description())?;
+            } else {
+                writeln!(out, "{}{} {:?}", indent, label, op)?;
+            }
+        }
+        UntypedAst::Sublist(children) => {
+            let label = if options.emoji { "📦 Block" } else { "Block" };
+            writeln!(out, "{}{} with {} operations:", indent, label, children.len())?;
+            for child in children {
+                describe_node(child, out, options, depth + 1)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn emoji(op: &OpCode) -> &'static str {
+    match op {
+        OpCode::Noop => "·",
+        OpCode::Plus => "➕",
+        OpCode::Minus => "➖",
+        OpCode::Mult => "✖️",
+        OpCode::Dup => "📋",
+        OpCode::Pop => "🗑️",
+        OpCode::GreaterThan => "🔍>",
+        OpCode::LessThan => "🔍<",
+        OpCode::Equal => "🔍=",
+        OpCode::NotEqual => "🔍≠",
+        OpCode::GreaterEqual => "🔍≥",
+        OpCode::LessEqual => "🔍≤",
+        OpCode::Sin => "sin",
+        OpCode::Cos => "cos",
+        OpCode::Sqrt => "√",
+        OpCode::Abs => "📏",
+        OpCode::Mod => "%",
+        OpCode::Pow => "^",
+        OpCode::ConstPi => "π",
+        OpCode::ConstE => "e",
+        OpCode::ConstRand => "🎲",
+        OpCode::BoolToInt => "bool→int",
+        OpCode::IntToBool => "int→bool",
+        OpCode::IfThen => "if",
+        OpCode::IfElse => "if-else",
+    }
+}
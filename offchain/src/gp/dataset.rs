@@ -0,0 +1,135 @@
+//! src/gp/dataset.rs
+//!
+//! Samples loaded from a table instead of sampled from a formula: one CSV row
+//! per sample, the inputs first and the target last, as the
+//! [`MultiSample`]s the multi-variable fitness functions take, and a random
+//! train/validation split of any sample set.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use rand::Rng;
+
+use crate::gp::fitness::MultiSample;
+
+/// Split `samples` at random into a training set of `ratio` of them (rounded,
+/// clamped to `[0, 1]`) and a validation set of the rest, e.g. to score the
+/// best program on samples evolution never saw (see
+/// [`Evolution::validate_with`](crate::gp::engine::Evolution::validate_with)).
+/// Both keep the samples' original order.
+///
+/// ```
+/// use offchain::gp::dataset::split_samples;
+/// use offchain::helpers::rng::seeded_rng;
+///
+/// let samples: Vec<(i128, i128)> = (-10..=10).map(|x| (x, x * x)).collect();
+/// let (train, val) = split_samples(&samples, 0.75, &mut seeded_rng(3));
+/// assert_eq!((train.len(), val.len()), (16, 5));
+/// assert!(train.iter().all(|s| !val.contains(s)));
+///
+/// let mut all = [train, val].concat();
+/// all.sort();
+/// assert_eq!(all, samples);
+/// ```
+pub fn split_samples<T: Clone>(samples: &[T], ratio: f64, rng: &mut impl Rng) -> (Vec<T>, Vec<T>) {
+    let train_len = ((samples.len() as f64 * ratio.clamp(0.0, 1.0)).round() as usize).min(samples.len());
+    let mut in_train = vec![false; samples.len()];
+    for i in rand::seq::index::sample(rng, samples.len(), train_len).into_vec() {
+        in_train[i] = true;
+    }
+
+    let (mut train, mut val) = (Vec::with_capacity(train_len), Vec::new());
+    for (sample, &is_train) in samples.iter().zip(&in_train) {
+        if is_train {
+            train.push(sample.clone());
+        } else {
+            val.push(sample.clone());
+        }
+    }
+    (train, val)
+}
+
+/// Read `(inputs..., target)` rows from the CSV file at `path`; see
+/// [`parse_samples_csv`].
+///
+/// ```
+/// use offchain::gp::dataset::load_samples_csv;
+///
+/// let path = std::env::temp_dir().join("push3_dataset.csv");
+/// std::fs::write(&path, "x,y,target\n1,2,3\n-4, 5, 1\n\n10,0,10\n").unwrap();
+///
+/// let samples = load_samples_csv(&path).unwrap();
+/// assert_eq!(samples.len(), 3);
+/// assert_eq!(samples[0], (vec![1, 2], 3));
+/// assert_eq!(samples[1], (vec![-4, 5], 1));
+/// assert_eq!(samples[2], (vec![10, 0], 10));
+/// ```
+pub fn load_samples_csv(path: impl AsRef<Path>) -> Result<Vec<MultiSample>> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read dataset {}: {}", path.display(), e))?;
+    parse_samples_csv(&text).map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+/// Parse comma-separated integer rows: every column but the last is an input
+/// (pushed in order, so the last input ends up on top of the int stack), the
+/// last one is the target.
+///
+/// Blank lines are skipped. The first row is taken as a header if none of its
+/// fields is an integer; anywhere else, a field that is not an integer is an
+/// error naming its line and column. Every row needs the same number of
+/// columns, at least two.
+///
+/// ```
+/// use offchain::gp::dataset::parse_samples_csv;
+///
+/// assert_eq!(parse_samples_csv("2,4\n3,9").unwrap(), vec![(vec![2], 4), (vec![3], 9)]);
+///
+/// let err = parse_samples_csv("x,target\n1,2\n3,four\n").unwrap_err();
+/// assert_eq!(err.to_string(), "line 3, column 2: 'four' is not an integer");
+/// let err = parse_samples_csv("1,2\n3,4,5\n").unwrap_err();
+/// assert_eq!(err.to_string(), "line 2: expected 2 columns, found 3");
+/// assert!(parse_samples_csv("x,target\n").is_err());
+/// ```
+pub fn parse_samples_csv(text: &str) -> Result<Vec<MultiSample>> {
+    let mut samples = Vec::new();
+    let mut columns = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_no = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let is_first_row = columns.is_none() && samples.is_empty();
+        if is_first_row && fields.iter().all(|f| f.parse::<i128>().is_err()) {
+            // Header
+            columns = Some(fields.len());
+            continue;
+        }
+
+        let expected = *columns.get_or_insert(fields.len());
+        if fields.len() != expected {
+            bail!("line {}: expected {} columns, found {}", line_no, expected, fields.len());
+        }
+        if fields.len() < 2 {
+            bail!("line {}: need at least one input and a target", line_no);
+        }
+
+        let mut values = Vec::with_capacity(fields.len());
+        for (column, field) in fields.iter().enumerate() {
+            let value = field
+                .parse::<i128>()
+                .map_err(|_| anyhow!("line {}, column {}: '{}' is not an integer", line_no, column + 1, field))?;
+            values.push(value);
+        }
+        let target = values.pop().expect("at least two columns");
+        samples.push((values, target));
+    }
+
+    if samples.is_empty() {
+        bail!("no samples found");
+    }
+    Ok(samples)
+}
@@ -0,0 +1,1451 @@
+//! src/gp/engine.rs
+//!
+//! A reusable evolution driver. The `symreg_*` bins used to each carry their own
+//! copy of the same generational loop; they now only build an [`EvolutionConfig`]
+//! and a fitness closure, and hand both to [`Evolution::evolve`].
+//!
+//! One generation is:
+//! 1. age everyone, refresh novelty scores / fitness sharing if enabled,
+//! 2. sort by fitness, record the best, stop early if `stop_when` says so,
+//! 3. copy the elites, add random immigrants, then fill the rest with
+//!    crossover / mutation children of selected parents,
+//! 4. optionally enforce a minimum structural diversity and refill with random programs,
+//!    and restart part of the population if the run has stagnated,
+//!    then, if elitism is enabled, put the previous best back if neither step kept it
+//!    (so the best fitness never drops from one generation to the next),
+//! 5. every `checkpoint_every` generations, write the population to `checkpoint_path`
+//!    so the run can be picked up again with [`Evolution::resume_from`].
+//!
+//! With `stats_csv` set, every generation also appends a row to a CSV file (see
+//! [`StatsLogger`]).
+//!
+//! With [`Evolution::validate_with`], the best program of every generation is
+//! also scored on held-out samples, to spot overfitting.
+//!
+//! With `sample_subset` set, [`Evolution::evolve_on_samples`] scores each
+//! generation on a fresh random subset of the samples, and only the best
+//! programs (for the reported best and the hall of fame) on all of them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::compiler::ast::{ast_fingerprint, repair, simplify, UntypedAst};
+use crate::gp::fitness::{FailurePolicy, ParsimonyPressure};
+use crate::gp::generate_spec::{random_code_with_max_children, InstructionSet};
+use crate::gp::metrics::size_histogram;
+use crate::gp::minimize::minimize;
+use crate::gp::mutation::{
+    get_depth, mutate_by_index_in_set, point_mutate_with_max_children, size_aware_crossover,
+    size_limited_mutate_in_set, IntMutation, POINT_MUTATION_MAX_CHILDREN,
+};
+use crate::gp::population_management::{
+    age_population, apply_fitness_sharing_with, calculate_novelty_score_with,
+    calculate_population_stats, calculate_population_stats_sampled, dedup_identical,
+    diverse_elitism, diverse_tournament_selection, enforce_minimum_diversity, hard_reset,
+    preserve_best, HallOfFame, restart_worst_fraction, sort_population, DistanceMetric, Individual,
+    PopulationStats,
+};
+use crate::gp::stats_logger::StatsLogger;
+use crate::helpers::rng::{entropy_seed, seeded_rng};
+use crate::runner::revm_runner::EvmRunner;
+
+/// How parents are picked for reproduction.
+#[derive(Debug, Clone)]
+pub enum SelectionStrategy {
+    /// Pick uniformly among the fittest `fraction` of the population
+    /// (e.g. `0.5` = top half).
+    Truncation { fraction: f64 },
+    /// Tournament selection; `diversity_weight` mixes in the novelty score
+    /// (`0.0` = plain fitness tournament).
+    Tournament { size: usize, diversity_weight: f64 },
+}
+
+/// The genetic operators a child can be produced with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operator {
+    /// `size_aware_crossover` of two parents (yields two children), capped at `max_size`.
+    Crossover,
+    /// `point_mutate_with` with `point_mutation_rate`, `int_mutation` and `max_children`.
+    PointMutation,
+    /// `mutate_by_index`: replace one random subtree, no size limit.
+    SubtreeMutation,
+    /// `size_limited_mutate` with `max_size` and `max_children`.
+    SizeLimitedMutation,
+}
+
+/// Probability of each operator being picked for a reproduction step.
+/// The weights must be non-negative and sum to 1.0.
+///
+/// ```
+/// use offchain::gp::engine::{Operator, OperatorWeights};
+/// use offchain::helpers::rng::seeded_rng;
+///
+/// let weights = OperatorWeights {
+///     crossover: 0.5,
+///     point_mutation: 0.3,
+///     subtree_mutation: 0.0,
+///     size_limited_mutation: 0.2,
+/// };
+/// weights.validate().unwrap();
+///
+/// let mut rng = seeded_rng(7);
+/// let n = 100_000;
+/// let crossovers = (0..n)
+///     .filter(|_| weights.sample(&mut rng) == Operator::Crossover)
+///     .count();
+/// assert!((crossovers as f64 / n as f64 - 0.5).abs() < 0.01);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OperatorWeights {
+    pub crossover: f64,
+    pub point_mutation: f64,
+    pub subtree_mutation: f64,
+    pub size_limited_mutation: f64,
+}
+
+impl OperatorWeights {
+    /// Allowed distance of the sum from 1.0.
+    const TOLERANCE: f64 = 1e-6;
+
+    fn as_array(&self) -> [(Operator, f64); 4] {
+        [
+            (Operator::Crossover, self.crossover),
+            (Operator::PointMutation, self.point_mutation),
+            (Operator::SubtreeMutation, self.subtree_mutation),
+            (Operator::SizeLimitedMutation, self.size_limited_mutation),
+        ]
+    }
+
+    /// Check that every weight is a finite, non-negative number and that
+    /// they sum to 1.0.
+    pub fn validate(&self) -> Result<()> {
+        for (op, w) in self.as_array() {
+            if !w.is_finite() || w < 0.0 {
+                bail!("operator weight for {:?} must be finite and non-negative, got {}", op, w);
+            }
+        }
+        let sum: f64 = self.as_array().iter().map(|(_, w)| w).sum();
+        if (sum - 1.0).abs() > Self::TOLERANCE {
+            bail!("operator weights must sum to 1.0, got {}", sum);
+        }
+        Ok(())
+    }
+
+    /// Pick an operator with probability proportional to its weight.
+    pub fn sample(&self, rng: &mut impl Rng) -> Operator {
+        let mut roll = rng.gen::<f64>();
+        for (op, w) in self.as_array() {
+            if roll < w {
+                return op;
+            }
+            roll -= w;
+        }
+        // Rounding left a sliver past the last weight: use the last
+        // operator that can actually be picked.
+        self.as_array()
+            .iter()
+            .rev()
+            .find(|(_, w)| *w > 0.0)
+            .map(|(op, _)| *op)
+            .unwrap_or(Operator::Crossover)
+    }
+}
+
+impl OperatorWeights {
+    /// Combined weight of the three mutation operators.
+    pub fn mutation_rate(&self) -> f64 {
+        self.point_mutation + self.subtree_mutation + self.size_limited_mutation
+    }
+
+    /// These weights with the mutation operators rescaled to sum to `rate`
+    /// (keeping their proportions) and crossover taking the rest. If every
+    /// mutation weight is zero, all of `rate` goes to point mutation.
+    ///
+    /// ```
+    /// use offchain::gp::engine::OperatorWeights;
+    ///
+    /// let weights = OperatorWeights::default().with_mutation_rate(0.6);
+    /// weights.validate().unwrap();
+    /// assert!((weights.mutation_rate() - 0.6).abs() < 1e-12);
+    /// assert!((weights.crossover - 0.4).abs() < 1e-12);
+    /// assert!((weights.point_mutation / weights.size_limited_mutation - 1.5).abs() < 1e-12);
+    /// ```
+    pub fn with_mutation_rate(&self, rate: f64) -> Self {
+        let rate = rate.clamp(0.0, 1.0);
+        let current = self.mutation_rate();
+        if current <= 0.0 {
+            return Self { crossover: 1.0 - rate, point_mutation: rate, ..*self };
+        }
+        let scale = rate / current;
+        Self {
+            crossover: 1.0 - rate,
+            point_mutation: self.point_mutation * scale,
+            subtree_mutation: self.subtree_mutation * scale,
+            size_limited_mutation: self.size_limited_mutation * scale,
+        }
+    }
+}
+
+/// Adapts the share of mutation among the operators as a run goes: it moves
+/// towards `max_rate` while the best fitness stagnates or the population
+/// converges, and back towards `min_rate` while fitness improves.
+///
+/// Each [`update`](Self::update) computes a target between the bounds from the
+/// stagnation count and the diversity score (whichever is more alarming), and
+/// moves the rate towards it, smoothed by `smoothing`.
+///
+/// ```
+/// use offchain::gp::engine::MutationRateController;
+///
+/// let mut controller = MutationRateController::new(0.1, 0.6, 0.5);
+/// assert_eq!(controller.rate(), 0.1);
+///
+/// // Stagnation: the rate climbs towards max_rate
+/// let mut last = controller.rate();
+/// for stagnant in 1..=8 {
+///     let rate = controller.update(stagnant, 0.8);
+///     assert!(rate >= last && rate <= 0.6);
+///     last = rate;
+/// }
+/// assert!(last > 0.5);
+///
+/// // Improvement resumes: it falls back towards min_rate
+/// for _ in 0..8 {
+///     let rate = controller.update(0, 0.8);
+///     assert!(rate < last && rate >= 0.1);
+///     last = rate;
+/// }
+/// assert!(last < 0.15);
+///
+/// // A converged population also raises it, even while improving
+/// let mut converged = MutationRateController::new(0.1, 0.6, 0.0).with_min_diversity(0.4);
+/// assert!((converged.update(0, 0.1) - 0.475).abs() < 1e-12);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MutationRateController {
+    pub min_rate: f64,
+    pub max_rate: f64,
+    /// Weight of the previous rate in each update, in `[0, 1)`: 0 jumps
+    /// straight to the target, values close to 1 move slowly.
+    pub smoothing: f64,
+    /// Stagnant generations after which the target is `max_rate`.
+    pub stagnation_generations: usize,
+    /// Diversity score under which the population counts as converging; the
+    /// target reaches `max_rate` at a score of 0. 0 ignores diversity.
+    pub min_diversity: f64,
+    rate: f64,
+}
+
+impl MutationRateController {
+    /// Start at `min_rate`, reaching `max_rate` after 5 stagnant generations,
+    /// ignoring diversity.
+    pub fn new(min_rate: f64, max_rate: f64, smoothing: f64) -> Self {
+        Self {
+            min_rate,
+            max_rate,
+            smoothing,
+            stagnation_generations: 5,
+            min_diversity: 0.0,
+            rate: min_rate,
+        }
+    }
+
+    /// Builder-style helper to set `stagnation_generations`.
+    pub fn with_stagnation_generations(mut self, generations: usize) -> Self {
+        self.stagnation_generations = generations;
+        self
+    }
+
+    /// Builder-style helper to set `min_diversity`.
+    pub fn with_min_diversity(mut self, min_diversity: f64) -> Self {
+        self.min_diversity = min_diversity;
+        self
+    }
+
+    /// Check that `0 <= min_rate <= max_rate <= 1` and `0 <= smoothing < 1`.
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0 <= self.min_rate && self.min_rate <= self.max_rate && self.max_rate <= 1.0) {
+            bail!(
+                "mutation rate bounds must satisfy 0 <= min <= max <= 1, got [{}, {}]",
+                self.min_rate, self.max_rate
+            );
+        }
+        if !(0.0..1.0).contains(&self.smoothing) {
+            bail!("mutation rate smoothing must be in [0, 1), got {}", self.smoothing);
+        }
+        Ok(())
+    }
+
+    /// The current rate.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Move the rate after a generation that has gone `stagnation_count`
+    /// generations without improving and has the given diversity score, and
+    /// return it.
+    pub fn update(&mut self, stagnation_count: usize, diversity: f64) -> f64 {
+        let stagnation = if self.stagnation_generations == 0 {
+            if stagnation_count > 0 { 1.0 } else { 0.0 }
+        } else {
+            (stagnation_count as f64 / self.stagnation_generations as f64).min(1.0)
+        };
+        let convergence = if self.min_diversity > 0.0 && diversity.is_finite() {
+            (1.0 - diversity / self.min_diversity).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let target = self.min_rate + stagnation.max(convergence) * (self.max_rate - self.min_rate);
+        self.rate = (self.smoothing * self.rate + (1.0 - self.smoothing) * target)
+            .clamp(self.min_rate, self.max_rate);
+        self.rate
+    }
+}
+
+impl Default for OperatorWeights {
+    fn default() -> Self {
+        Self {
+            crossover: 0.7,
+            point_mutation: 0.18,
+            subtree_mutation: 0.0,
+            size_limited_mutation: 0.12,
+        }
+    }
+}
+
+/// Which individuals are copied unchanged into the next generation.
+#[derive(Debug, Clone)]
+pub enum Elitism {
+    /// No elites.
+    None,
+    /// The `n` fittest individuals.
+    Top(usize),
+    /// `diverse_elitism`: the best one, then fitness with a bonus for being
+    /// at least `min_distance` away from the elites chosen so far.
+    Diverse { count: usize, min_distance: f64 },
+}
+
+impl Elitism {
+    /// True if at least one elite is kept, in which case the engine also
+    /// guarantees the best individual survives each generation.
+    pub fn keeps_best(&self) -> bool {
+        match self {
+            Elitism::None => false,
+            Elitism::Top(n) => *n > 0,
+            Elitism::Diverse { count, .. } => *count > 0,
+        }
+    }
+}
+
+/// A Koza-style dynamic depth limit for bloat control: children may be no
+/// deeper (as `mutation::get_depth`) than the current limit, which starts at
+/// `initial` and goes up by one whenever the best fitness improves with a
+/// program that already reaches the limit, up to `max`.
+///
+/// Programs that were already deeper (e.g. from the initial population) are
+/// kept; only new children are held to the limit.
+///
+/// ```
+/// use offchain::gp::engine::DynamicDepthLimit;
+/// use offchain::push3;
+///
+/// let mut limit = DynamicDepthLimit::new(2, 4);
+/// let shallow = push3!((1 2 +));     // depth 1
+/// let at_limit = push3!((1 (2 3) +)); // depth 2
+/// let deeper = push3!((1 ((2) 3) +)); // depth 3
+///
+/// // An improvement below the limit, or none at all, leaves it alone
+/// assert_eq!(limit.update(true, &shallow), 2);
+/// assert_eq!(limit.update(false, &at_limit), 2);
+/// assert!(limit.allows(&at_limit) && !limit.allows(&deeper));
+///
+/// // An improving program at the limit raises it by one
+/// assert_eq!(limit.update(true, &at_limit), 3);
+/// assert!(limit.allows(&deeper));
+/// assert_eq!(limit.update(true, &deeper), 4);
+///
+/// // ... but never past `max`
+/// assert_eq!(limit.update(true, &push3!((((((1))))))), 4);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DynamicDepthLimit {
+    /// The limit never grows past this.
+    pub max: usize,
+    current: usize,
+}
+
+impl DynamicDepthLimit {
+    /// Start at `initial` (at most `max`).
+    pub fn new(initial: usize, max: usize) -> Self {
+        Self { max, current: initial.min(max) }
+    }
+
+    /// The current limit.
+    pub fn limit(&self) -> usize {
+        self.current
+    }
+
+    /// True if `ast` is no deeper than the current limit.
+    pub fn allows(&self, ast: &UntypedAst) -> bool {
+        get_depth(ast) <= self.current
+    }
+
+    /// Raise the limit by one if the best fitness `improved` and `best` (the
+    /// new best program) reaches the current limit; return the limit.
+    pub fn update(&mut self, improved: bool, best: &UntypedAst) -> usize {
+        if improved && get_depth(best) >= self.current && self.current < self.max {
+            self.current += 1;
+        }
+        self.current
+    }
+}
+
+/// Which fitness picks [`EvolutionResult::best`] (parents are always selected
+/// on training fitness).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionCriterion {
+    /// The program with the best training fitness seen over the run.
+    #[default]
+    Train,
+    /// Among the best program of each generation (and of the final
+    /// population), the one with the best [`Evolution::validate_with`] score,
+    /// so an overfit late winner doesn't displace one that generalizes. Without
+    /// a validation function this falls back to `Train`.
+    Validation,
+}
+
+/// What to do with the population once a run has stagnated.
+#[derive(Debug, Clone)]
+pub enum RestartStrategy {
+    /// `restart_worst_fraction`: replace the worst `fraction` with random programs.
+    WorstFraction(f64),
+    /// `hard_reset`: keep the `keep_elites` best, replace everyone else.
+    HardReset { keep_elites: usize },
+}
+
+/// When to apply a [`RestartStrategy`].
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    /// Restart once the best fitness has moved by less than `min_improvement`
+    /// for more than this many consecutive generations.
+    pub stagnation_generations: usize,
+    pub min_improvement: f64,
+    /// If set, only restart while the diversity score is below this.
+    pub max_diversity: Option<f64>,
+    pub strategy: RestartStrategy,
+}
+
+/// Everything that parameterizes a run.
+#[derive(Debug, Clone)]
+pub struct EvolutionConfig {
+    pub pop_size: usize,
+    pub generations: usize,
+    /// Max points for freshly generated random programs.
+    pub max_points: usize,
+    /// Size limit used by `size_limited_mutate`; larger crossover children are rejected.
+    pub max_size: usize,
+
+    /// How children are produced; validated by [`Evolution::new`].
+    pub operators: OperatorWeights,
+    /// If set, the combined weight of the mutation operators is adapted every
+    /// generation (see [`OperatorWeights::with_mutation_rate`]) instead of
+    /// staying at that of `operators`.
+    pub adaptive_mutation: Option<MutationRateController>,
+    /// Per-node probability passed to `point_mutate`.
+    pub point_mutation_rate: f64,
+    /// Most children any generated or mutated sublist may have. `None` keeps
+    /// each operator's own default (no limit for random programs and subtree
+    /// mutation, [`POINT_MUTATION_MAX_CHILDREN`] for point mutation).
+    pub max_children: Option<usize>,
+    /// Atoms random programs and subtree mutations are built from, e.g.
+    /// `InstructionSet::load(path)?` to repeat an experiment. Point mutation
+    /// keeps its own opcode choices.
+    pub instruction_set: InstructionSet,
+    /// Size penalty applied on top of every fitness value.
+    pub parsimony: ParsimonyPressure,
+    /// How failed samples are credited by [`Evolution::evolve_on_errors`] and
+    /// [`Evolution::evolve_on_samples`].
+    pub failure_policy: FailurePolicy,
+    /// How point mutation changes integer literals.
+    pub int_mutation: IntMutation,
+    /// Run `compiler::ast::repair` on every child, removing empty sublists.
+    pub repair_offspring: bool,
+    /// Fraction of every new generation made of fresh random programs.
+    pub immigrant_fraction: f64,
+    /// If set, children deeper than its current limit are replaced by a copy
+    /// of their parent, and the limit only grows as the best program improves.
+    pub dynamic_depth: Option<DynamicDepthLimit>,
+    /// Which fitness the reported best program is chosen by.
+    pub select_by: SelectionCriterion,
+    /// Shrink the reported best program with `compiler::ast::simplify` and
+    /// `gp::minimize::minimize` before returning it, keeping only edits that
+    /// don't lower its fitness on all the samples. The program as evolved
+    /// stays available as [`EvolutionResult::raw_best`].
+    pub simplify_best: bool,
+    /// If set (and `verbose`), every generation also prints how program sizes
+    /// are spread, in buckets this many nodes wide.
+    pub size_histogram_width: Option<usize>,
+
+    pub selection: SelectionStrategy,
+    pub elitism: Elitism,
+    /// If set, parents are selected on fitness shared within this radius.
+    pub fitness_sharing_sigma: Option<f64>,
+    /// Distance used for novelty scores and fitness sharing.
+    pub distance_metric: DistanceMetric,
+    /// If set, near-duplicates closer than this are removed after reproduction.
+    pub min_diversity: Option<f64>,
+    /// If set, the per-generation diversity score is estimated from at most
+    /// this many random pairs instead of all of them.
+    pub diversity_sample_pairs: Option<usize>,
+    /// If set, part of the population is replaced when the run stagnates.
+    pub restart: Option<RestartPolicy>,
+
+    /// Write a checkpoint every this many generations (reseeding the RNG each
+    /// time, see [`Evolution::resume_from`]).
+    pub checkpoint_every: Option<usize>,
+    /// Where checkpoints are written.
+    pub checkpoint_path: PathBuf,
+    /// If set, per-generation statistics are appended to this CSV file.
+    pub stats_csv: Option<PathBuf>,
+    /// How many of the best distinct programs seen over the run to keep in
+    /// [`EvolutionResult::hall_of_fame`] (0 keeps none).
+    pub hall_of_fame_size: usize,
+    /// If set, [`Evolution::evolve_on_samples`] scores each generation on a
+    /// random subset of this many samples. The best program and the hall of
+    /// fame are still judged on every sample.
+    pub sample_subset: Option<usize>,
+    /// With `sample_subset`, every this many generations are scored on all the
+    /// samples instead (0: never).
+    pub full_sample_every: usize,
+
+    /// Seed for a reproducible run; `None` draws one from entropy. Either way
+    /// the seed used is reported in [`EvolutionResult::seed`].
+    pub seed: Option<u64>,
+    /// Print a short summary every generation.
+    pub verbose: bool,
+}
+
+impl Default for EvolutionConfig {
+    fn default() -> Self {
+        Self {
+            pop_size: 100,
+            generations: 20,
+            max_points: 10,
+            max_size: 30,
+            operators: OperatorWeights::default(),
+            adaptive_mutation: None,
+            point_mutation_rate: 0.15,
+            max_children: None,
+            instruction_set: InstructionSet::new_default(),
+            parsimony: ParsimonyPressure::None,
+            failure_policy: FailurePolicy::default(),
+            int_mutation: IntMutation::default(),
+            repair_offspring: false,
+            immigrant_fraction: 0.0,
+            dynamic_depth: None,
+            select_by: SelectionCriterion::Train,
+            simplify_best: false,
+            size_histogram_width: None,
+            selection: SelectionStrategy::Tournament { size: 5, diversity_weight: 0.0 },
+            elitism: Elitism::Top(10),
+            fitness_sharing_sigma: None,
+            distance_metric: DistanceMetric::Structural,
+            min_diversity: None,
+            diversity_sample_pairs: None,
+            restart: None,
+            checkpoint_every: None,
+            checkpoint_path: PathBuf::from("evolution_checkpoint.json"),
+            stats_csv: None,
+            hall_of_fame_size: 0,
+            sample_subset: None,
+            full_sample_every: 10,
+            seed: None,
+            verbose: true,
+        }
+    }
+}
+
+/// Summary of one evaluated generation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationStats {
+    pub generation: usize,
+    pub best_fitness: f64,
+    pub best_size: usize,
+    pub avg_fitness: f64,
+    pub fitness_std: f64,
+    #[serde(default)]
+    pub min_fitness: f64,
+    #[serde(default)]
+    pub median_fitness: f64,
+    #[serde(default)]
+    pub max_fitness: f64,
+    pub avg_size: f64,
+    pub diversity: f64,
+    /// Fitness of the generation's best program on held-out samples, if the
+    /// run has a [`Evolution::validate_with`] function.
+    #[serde(default)]
+    pub validation_fitness: Option<f64>,
+}
+
+/// What a run produced.
+#[derive(Debug, Clone)]
+pub struct EvolutionResult {
+    /// Best individual seen over the whole run, by training fitness, or by
+    /// validation fitness with [`SelectionCriterion::Validation`].
+    pub best: Individual,
+    /// With `simplify_best`, the best program as evolved, before `best` was
+    /// simplified; `None` otherwise.
+    pub raw_best: Option<Individual>,
+    /// `best`'s validation fitness, if the run had a validation function.
+    pub best_validation_fitness: Option<f64>,
+    /// Final population, sorted best first.
+    pub population: Vec<Individual>,
+    /// Best fitness of every generation that was evaluated.
+    pub best_fitness_history: Vec<f64>,
+    /// Statistics of every generation that was evaluated.
+    pub generation_stats: Vec<GenerationStats>,
+    pub generations_run: usize,
+    /// True if `stop_when` ended the run before the last generation.
+    pub stopped_early: bool,
+    /// The best distinct programs seen in any generation (see `hall_of_fame_size`).
+    pub hall_of_fame: HallOfFame,
+    /// The RNG seed the run used; set `EvolutionConfig::seed` to it to replay.
+    pub seed: u64,
+    /// The instruction set the run used.
+    pub instruction_set: InstructionSet,
+}
+
+/// A program in a JSON report.
+#[derive(Serialize)]
+struct ProgramReport {
+    program: String,
+    /// The same program in its serde form, loadable with `UntypedAst`'s
+    /// `Deserialize` (see the `replay` bin).
+    ast: UntypedAst,
+    fingerprint: String,
+    fitness: f64,
+    size: usize,
+}
+
+impl From<&Individual> for ProgramReport {
+    fn from(individual: &Individual) -> Self {
+        Self {
+            program: individual.ast.to_string(),
+            ast: individual.ast.clone(),
+            fingerprint: ast_fingerprint(&individual.ast),
+            fitness: individual.fitness,
+            size: individual.size,
+        }
+    }
+}
+
+/// The JSON document produced by [`EvolutionResult::report_json`].
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    seed: u64,
+    generations_run: usize,
+    stopped_early: bool,
+    generations: &'a [GenerationStats],
+    best: ProgramReport,
+    top: Vec<ProgramReport>,
+    instruction_set: &'a InstructionSet,
+}
+
+impl EvolutionResult {
+    /// The run as one JSON document, for plotting scripts: per-generation
+    /// statistics, the best program, and the `top_k` programs of the final
+    /// population. Programs are S-expression strings, plus their serde form
+    /// under `ast`.
+    ///
+    /// ```text
+    /// {
+    ///   "seed": 1234567890,
+    ///   "generations_run": 12,
+    ///   "stopped_early": true,
+    ///   "generations": [{ "generation": 0, "best_fitness": 41.7, "best_size": 5,
+    ///                     "avg_fitness": 9.2, "fitness_std": 8.1, "min_fitness": 0.0,
+    ///                     "median_fitness": 6.5, "max_fitness": 41.7, "avg_size": 6.3,
+    ///                     "diversity": 0.71 }, ...],
+    ///   "best": { "program": "(DUP DUP * +)", "ast": { "Sublist": [...] },
+    ///             "fitness": 1200.0, "size": 5 },
+    ///   "top": [{ "program": "(DUP DUP * +)", "fitness": 1200.0, "size": 5 }, ...],
+    ///   "instruction_set": { "atoms": [{ "Opcode": "Plus" }, ..., "EphemeralInt"],
+    ///                        "ephemeral_range": [-30, 30] }
+    /// }
+    /// ```
+    pub fn report_json(&self, top_k: usize) -> String {
+        let report = JsonReport {
+            seed: self.seed,
+            generations_run: self.generations_run,
+            stopped_early: self.stopped_early,
+            generations: &self.generation_stats,
+            best: (&self.best).into(),
+            top: self.population.iter().take(top_k).map(ProgramReport::from).collect(),
+            instruction_set: &self.instruction_set,
+        };
+        serde_json::to_string_pretty(&report).expect("report contains only plain data")
+    }
+}
+
+/// Early-stop predicate, called once per generation with the population
+/// statistics and the generation's best individual.
+pub type StopPredicate = Box<dyn Fn(&PopulationStats, &Individual) -> bool>;
+
+/// Scores a program on held-out samples, called once per generation with its
+/// best program.
+pub type ValidationFn = Box<dyn FnMut(&mut EvmRunner, &UntypedAst) -> f64>;
+
+/// Everything needed to pick a run up where it left off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The generation `population` is about to enter.
+    pub generation: usize,
+    pub population: Vec<Individual>,
+    pub best: Option<Individual>,
+    pub best_fitness_history: Vec<f64>,
+    #[serde(default)]
+    pub generation_stats: Vec<GenerationStats>,
+    /// Generations without improvement so far.
+    #[serde(default)]
+    pub stagnation_count: usize,
+    /// The run's hall of fame, adaptive mutation rate and depth limit; `None`
+    /// (e.g. in older checkpoints) starts them afresh from the config.
+    #[serde(default)]
+    pub hall_of_fame: Option<HallOfFame>,
+    #[serde(default)]
+    pub adaptive_mutation: Option<MutationRateController>,
+    #[serde(default)]
+    pub dynamic_depth: Option<DynamicDepthLimit>,
+}
+
+impl Checkpoint {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string(self)?;
+        fs::write(path, json)
+            .map_err(|e| anyhow!("Failed to write checkpoint {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read checkpoint {}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| anyhow!("Failed to parse checkpoint: {}", e))
+    }
+}
+
+/// The evolution driver: a config plus the RNG it runs with.
+pub struct Evolution {
+    pub config: EvolutionConfig,
+    seed: u64,
+    rng: StdRng,
+    stop_when: Option<StopPredicate>,
+    validation: Option<ValidationFn>,
+    resume: Option<Checkpoint>,
+}
+
+impl Evolution {
+    /// Fails if the operator weights don't form a valid distribution.
+    pub fn new(config: EvolutionConfig) -> Result<Self> {
+        config.operators.validate()?;
+        config.int_mutation.validate()?;
+        config.instruction_set.validate()?;
+        if let Some(controller) = &config.adaptive_mutation {
+            controller.validate()?;
+        }
+        let seed = config.seed.unwrap_or_else(entropy_seed);
+        Ok(Self { config, seed, rng: seeded_rng(seed), stop_when: None, validation: None, resume: None })
+    }
+
+    /// The seed this run uses: `config.seed`, or the one drawn from entropy.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Stop the run as soon as `pred` returns true for a generation, e.g.
+    /// `.stop_when(|_, best| best.fitness > 900.0)`.
+    pub fn stop_when<P>(mut self, pred: P) -> Self
+    where
+        P: Fn(&PopulationStats, &Individual) -> bool + 'static,
+    {
+        self.stop_when = Some(Box::new(pred));
+        self
+    }
+
+    /// Score each generation's best program with `validate` too, e.g. its
+    /// fitness on the validation half of [`split_samples`], and report it as
+    /// [`GenerationStats::validation_fitness`]. Only the best program is
+    /// scored, so this costs one evaluation per generation; a validation
+    /// fitness falling while the training fitness climbs means overfitting.
+    ///
+    /// ```no_run
+    /// # use offchain::gp::dataset::split_samples;
+    /// # use offchain::gp::engine::{Evolution, EvolutionConfig};
+    /// # use offchain::gp::fitness::{evaluate_errors, graded_fitness};
+    /// # use offchain::helpers::rng::seeded_rng;
+    /// # use offchain::runner::revm_runner::EvmRunner;
+    /// # fn demo(runner: &mut EvmRunner, samples: &[(i128, i128)]) -> anyhow::Result<()> {
+    /// let (train, val) = split_samples(samples, 0.8, &mut seeded_rng(1));
+    /// let result = Evolution::new(EvolutionConfig::default())?
+    ///     .validate_with(move |runner, ast| graded_fitness(runner, ast, &val))
+    ///     .evolve_on_errors(runner, |runner, ast| evaluate_errors(runner, ast, &train));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`split_samples`]: crate::gp::dataset::split_samples
+    pub fn validate_with<V>(mut self, validate: V) -> Self
+    where
+        V: FnMut(&mut EvmRunner, &UntypedAst) -> f64 + 'static,
+    {
+        self.validation = Some(Box::new(validate));
+        self
+    }
+
+    /// Continue from a checkpoint written by an earlier run instead of starting
+    /// from a random population. `config.generations` is still the total, so
+    /// a run checkpointed at generation 20 of 50 does 30 more.
+    ///
+    /// The RNG state isn't stored: every run reseeds its RNG from its seed and
+    /// the generation whenever it writes a checkpoint, and a resumed run does
+    /// the same, so with the same config and seed it continues exactly as the
+    /// run that wrote the checkpoint did.
+    ///
+    /// ```no_run
+    /// # use offchain::gp::engine::{Evolution, EvolutionConfig};
+    /// # use offchain::gp::mutation::get_subtree_size;
+    /// # use offchain::runner::revm_runner::EvmRunner;
+    /// # fn demo(runner: &mut EvmRunner) -> anyhow::Result<()> {
+    /// let config = EvolutionConfig {
+    ///     generations: 50,
+    ///     checkpoint_every: Some(10),
+    ///     seed: Some(1),
+    ///     ..EvolutionConfig::default()
+    /// };
+    /// // ... an earlier run with this config was interrupted ...
+    /// let path = config.checkpoint_path.clone();
+    /// let result = Evolution::new(config)?
+    ///     .resume_from(&path)?
+    ///     .evolve(runner, |_, ast| -(get_subtree_size(ast) as f64));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn resume_from(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        let checkpoint = Checkpoint::load(path)?;
+        if checkpoint.population.is_empty() {
+            bail!("checkpoint has an empty population");
+        }
+        self.rng = seeded_rng(self.seed.wrapping_add(checkpoint.generation as u64));
+        self.resume = Some(checkpoint);
+        Ok(self)
+    }
+
+    /// Run the configured number of generations (or until the target fitness
+    /// is reached). `fitness_fn` scores one program; higher is better. The
+    /// configured `parsimony` is applied to its result.
+    pub fn evolve<F>(&mut self, runner: &mut EvmRunner, mut fitness_fn: F) -> EvolutionResult
+    where
+        F: FnMut(&mut EvmRunner, &UntypedAst) -> f64,
+    {
+        let parsimony = self.config.parsimony.clone();
+        self.evolve_individuals(runner, None, |runner, ast, _| {
+            let fitness = parsimony.apply(fitness_fn(runner, &ast), &ast);
+            Individual::new(ast, fitness)
+        })
+    }
+
+    /// Like [`Evolution::evolve`], but `errors_fn` returns the per-sample errors
+    /// (e.g. `fitness::evaluate_errors`) instead of a score. Fitness is
+    /// `failure_policy.score_errors` (by default `graded_fitness_from_errors`),
+    /// and every individual records `reverted` /
+    /// `mean_error`, so `PopulationStats::success_rate` and `avg_error` are
+    /// meaningful (including in `stop_when`).
+    pub fn evolve_on_errors<F>(&mut self, runner: &mut EvmRunner, mut errors_fn: F) -> EvolutionResult
+    where
+        F: FnMut(&mut EvmRunner, &UntypedAst) -> Vec<Option<i128>>,
+    {
+        let parsimony = self.config.parsimony.clone();
+        let failure_policy = self.config.failure_policy;
+        self.evolve_individuals(runner, None, |runner, ast, _| {
+            let errors = errors_fn(runner, &ast);
+            let mut individual = Individual::from_errors_with(ast, &errors, &failure_policy);
+            individual.fitness = parsimony.apply(individual.fitness, &individual.ast);
+            individual
+        })
+    }
+
+    /// Like [`Evolution::evolve_on_errors`], but the engine decides which of
+    /// `samples` a program is scored on: all of them, or with `sample_subset`
+    /// set, the current generation's random subset. `errors_fn` gets the
+    /// samples to use, e.g. `|runner, ast, samples| evaluate_errors(runner, ast, samples)`.
+    pub fn evolve_on_samples<F>(
+        &mut self,
+        runner: &mut EvmRunner,
+        samples: &[(i128, i128)],
+        mut errors_fn: F,
+    ) -> EvolutionResult
+    where
+        F: FnMut(&mut EvmRunner, &UntypedAst, &[(i128, i128)]) -> Vec<Option<i128>>,
+    {
+        let parsimony = self.config.parsimony.clone();
+        let failure_policy = self.config.failure_policy;
+        self.evolve_individuals(runner, Some(samples.len()), |runner, ast, subset| {
+            let errors = match subset {
+                Some(indices) => {
+                    let chosen: Vec<(i128, i128)> = indices.iter().map(|&i| samples[i]).collect();
+                    errors_fn(runner, &ast, &chosen)
+                }
+                None => errors_fn(runner, &ast, samples),
+            };
+            let mut individual = Individual::from_errors_with(ast, &errors, &failure_policy);
+            individual.fitness = parsimony.apply(individual.fitness, &individual.ast);
+            individual
+        })
+    }
+
+    /// The generational loop; `evaluate` turns a new program into a scored
+    /// individual, on the given sample indices (`None`: all of them).
+    /// `sample_count` is how many samples there are to subsample, if the
+    /// caller supports it.
+    fn evolve_individuals<E>(
+        &mut self,
+        runner: &mut EvmRunner,
+        sample_count: Option<usize>,
+        mut evaluate: E,
+    ) -> EvolutionResult
+    where
+        E: FnMut(&mut EvmRunner, UntypedAst, Option<&[usize]>) -> Individual,
+    {
+        let pop_size = self.config.pop_size;
+        assert!(pop_size > 0, "pop_size must be positive");
+        if self.config.verbose {
+            println!("Seed: {} (pass it as the seed to replay this run)", self.seed);
+        }
+
+        // The sample subset the current population is scored on
+        let sample_count = sample_count.filter(|_| self.config.sample_subset.is_some());
+        let start = self.resume.as_ref().map_or(0, |c| c.generation);
+        let mut subset = match sample_count {
+            Some(count) => self.draw_subset(count, start),
+            None => None,
+        };
+
+        let mut hall_of_fame = HallOfFame::new(self.config.hall_of_fame_size);
+        let mut stagnation_count = 0;
+        let mut rate_controller = self.config.adaptive_mutation.clone();
+        let mut depth_limit = self.config.dynamic_depth.clone();
+
+        // 1) Initial population: from a checkpoint, or random
+        let (start_gen, mut population, mut best, mut best_fitness_history, mut generation_stats) =
+            match self.resume.take() {
+                Some(c) => {
+                    stagnation_count = c.stagnation_count;
+                    hall_of_fame = c.hall_of_fame.unwrap_or(hall_of_fame);
+                    rate_controller = c.adaptive_mutation.or(rate_controller);
+                    depth_limit = c.dynamic_depth.or(depth_limit);
+                    (c.generation, c.population, c.best, c.best_fitness_history, c.generation_stats)
+                }
+                None => {
+                    let population = (0..pop_size)
+                        .map(|_| self.random_individual(runner, &mut evaluate, subset.as_deref()))
+                        .collect();
+                    (0, population, None, Vec::new(), Vec::new())
+                }
+            };
+
+        let mut generations_run = start_gen;
+        let mut stopped_early = false;
+        // The generation best with the highest validation fitness, and that fitness
+        let mut validated_best: Option<(Individual, f64)> = None;
+        let mut stats_logger = match &self.config.stats_csv {
+            Some(path) => match StatsLogger::new(path, self.seed) {
+                Ok(logger) => Some(logger),
+                Err(e) => {
+                    eprintln!("Warning: {}; statistics will not be logged", e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        for gen in start_gen..self.config.generations {
+            generations_run = gen + 1;
+
+            // (a) Bookkeeping
+            age_population(&mut population);
+            if self.uses_novelty() {
+                for i in 0..population.len() {
+                    population[i].novelty_score = calculate_novelty_score_with(
+                        &population[i].ast,
+                        &population,
+                        self.config.distance_metric,
+                    );
+                }
+            }
+            sort_population(&mut population);
+            // On a subset, the best and the hall of fame are judged on all the
+            // samples: the top programs are scored again
+            let full_scored = match subset {
+                Some(_) => {
+                    let top = self.config.hall_of_fame_size.clamp(1, population.len());
+                    let mut rescored: Vec<Individual> = population[..top]
+                        .iter()
+                        .map(|individual| rescore(runner, &mut evaluate, individual, None))
+                        .collect();
+                    sort_population(&mut rescored);
+                    Some(rescored)
+                }
+                None => None,
+            };
+            let tracked = full_scored.as_deref().unwrap_or(&population);
+            for individual in tracked {
+                hall_of_fame.consider(individual);
+            }
+
+            let gen_best = tracked[0].clone();
+            if let Some(&last_best) = best_fitness_history.last() {
+                let min_improvement = self.config.restart.as_ref().map_or(f64::EPSILON, |p| p.min_improvement);
+                if (gen_best.fitness - last_best).abs() < min_improvement {
+                    stagnation_count += 1;
+                } else {
+                    stagnation_count = 0;
+                }
+            }
+            best_fitness_history.push(gen_best.fitness);
+            let improved = match &best {
+                Some(b) => gen_best.fitness > b.fitness,
+                None => true,
+            };
+            if improved {
+                best = Some(gen_best.clone());
+            }
+            if let Some(limit) = &mut depth_limit {
+                let before = limit.limit();
+                if limit.update(improved, &gen_best.ast) > before && self.config.verbose {
+                    println!("Depth limit raised to {}", limit.limit());
+                }
+            }
+
+            let stats = match self.config.diversity_sample_pairs {
+                Some(max_pairs) => {
+                    calculate_population_stats_sampled(&population, max_pairs, &mut self.rng)
+                }
+                None => calculate_population_stats(&population),
+            };
+            let validation_fitness = self.validation.as_mut().map(|validate| validate(runner, &gen_best.ast));
+            if let Some(fitness) = validation_fitness {
+                let better = match &validated_best {
+                    Some((_, best_fitness)) => fitness > *best_fitness,
+                    None => true,
+                };
+                if better {
+                    validated_best = Some((gen_best.clone(), fitness));
+                }
+            }
+            generation_stats.push(GenerationStats {
+                generation: gen,
+                best_fitness: gen_best.fitness,
+                best_size: gen_best.size,
+                avg_fitness: stats.avg_fitness,
+                fitness_std: stats.fitness_std,
+                min_fitness: stats.min_fitness,
+                median_fitness: stats.median_fitness,
+                max_fitness: stats.max_fitness,
+                avg_size: stats.avg_size,
+                diversity: stats.diversity_score,
+                validation_fitness,
+            });
+            if let Some(logger) = &mut stats_logger {
+                if let Err(e) = logger.log_generation(gen, gen_best.fitness, &stats, stagnation_count) {
+                    eprintln!("Warning: failed to log statistics: {}", e);
+                }
+            }
+            if self.config.verbose {
+                println!("\n=== Generation {} ===", gen);
+                println!("Best: {:.2} (size: {}, age: {})", gen_best.fitness, gen_best.size, gen_best.age);
+                if let Some(fitness) = validation_fitness {
+                    println!("Validation: {:.2}", fitness);
+                }
+                println!("Population: avg={:.2}±{:.2}, diversity={:.3}",
+                         stats.avg_fitness, stats.fitness_std, stats.diversity_score);
+                println!("Fitness: min={:.2}, median={:.2}, max={:.2}",
+                         stats.min_fitness, stats.median_fitness, stats.max_fitness);
+                println!("Sizes: avg={:.1}±{:.1}", stats.avg_size, stats.size_std);
+                if let Some(width) = self.config.size_histogram_width {
+                    let buckets: Vec<String> = size_histogram(&population, width)
+                        .into_iter()
+                        .map(|(start, count)| format!("{}-{}:{}", start, start + width.max(1) - 1, count))
+                        .collect();
+                    println!("Size histogram: {}", buckets.join(" "));
+                }
+                if stagnation_count > 0 {
+                    println!("Stagnation: {} generations", stagnation_count);
+                }
+            }
+
+            // (b) Early stop
+            if let Some(stop_when) = &self.stop_when {
+                if stop_when(&stats, &gen_best) {
+                    if self.config.verbose {
+                        println!("Stop condition met at generation {}.", gen);
+                    }
+                    stopped_early = true;
+                    break;
+                }
+            }
+
+            // (c) Reproduction
+            let operators = match &mut rate_controller {
+                Some(controller) => {
+                    let rate = controller.update(stagnation_count, stats.diversity_score);
+                    if self.config.verbose {
+                        println!("Mutation rate: {:.3}", rate);
+                    }
+                    self.config.operators.with_mutation_rate(rate)
+                }
+                None => self.config.operators,
+            };
+            let mut champion = gen_best.clone();
+            let mut new_population = self.select_elites(&population);
+            if let Some(count) = sample_count {
+                // The whole next generation is scored on the same new subset
+                subset = self.draw_subset(count, gen + 1);
+                champion = rescore(runner, &mut evaluate, &population[0], subset.as_deref());
+                for elite in &mut new_population {
+                    *elite = rescore(runner, &mut evaluate, elite, subset.as_deref());
+                }
+            }
+
+            let immigrants = (pop_size as f64 * self.config.immigrant_fraction) as usize;
+            for _ in 0..immigrants {
+                new_population.push(self.random_individual(runner, &mut evaluate, subset.as_deref()));
+            }
+
+            // Parents are chosen on shared fitness (if enabled), on a copy so the
+            // stored fitness values are never divided twice.
+            let mut pool = population.clone();
+            if let Some(sigma) = self.config.fitness_sharing_sigma {
+                apply_fitness_sharing_with(&mut pool, sigma, self.config.distance_metric);
+                sort_population(&mut pool);
+            }
+
+            while new_population.len() < pop_size {
+                let max_depth = depth_limit.as_ref().map(DynamicDepthLimit::limit);
+                for child in self.reproduce(&pool, &operators, max_depth) {
+                    if new_population.len() < pop_size {
+                        new_population.push(evaluate(runner, child, subset.as_deref()));
+                    }
+                }
+            }
+
+            // (d) Diversity maintenance
+            if let Some(min_distance) = self.config.min_diversity {
+                dedup_identical(&mut new_population);
+                enforce_minimum_diversity(&mut new_population, min_distance, &mut self.rng);
+                while new_population.len() < pop_size {
+                    new_population.push(self.random_individual(runner, &mut evaluate, subset.as_deref()));
+                }
+            }
+
+            new_population.truncate(pop_size);
+            population = new_population;
+
+            // (e) Restart on stagnation
+            if let Some(policy) = &self.config.restart {
+                let low_diversity = match policy.max_diversity {
+                    Some(max) => stats.diversity_score < max,
+                    None => true,
+                };
+                if stagnation_count > policy.stagnation_generations && low_diversity {
+                    let max_points = self.config.max_points;
+                    let on_subset = |runner: &mut EvmRunner, ast| evaluate(runner, ast, subset.as_deref());
+                    let replaced = match policy.strategy {
+                        RestartStrategy::WorstFraction(fraction) => restart_worst_fraction(
+                            &mut population, fraction, max_points, runner, on_subset, &mut self.rng,
+                        ),
+                        RestartStrategy::HardReset { keep_elites } => hard_reset(
+                            &mut population, keep_elites, max_points, runner, on_subset, &mut self.rng,
+                        ),
+                    };
+                    if self.config.verbose {
+                        println!("Stagnation detected - restarted {} individuals", replaced);
+                    }
+                    stagnation_count = 0;
+                }
+            }
+
+            // Elitism invariant: the best individual is carried over unchanged
+            if self.config.elitism.keeps_best() {
+                preserve_best(&mut population, &champion);
+                debug_assert!(
+                    population.iter().any(|i| i.fitness >= champion.fitness),
+                    "elitism lost the best individual"
+                );
+            }
+
+            // (f) Checkpoint
+            if let Some(every) = self.config.checkpoint_every {
+                if every > 0 && (gen + 1) % every == 0 {
+                    let checkpoint = Checkpoint {
+                        generation: gen + 1,
+                        population: population.clone(),
+                        best: best.clone(),
+                        best_fitness_history: best_fitness_history.clone(),
+                        generation_stats: generation_stats.clone(),
+                        stagnation_count,
+                        hall_of_fame: Some(hall_of_fame.clone()),
+                        adaptive_mutation: rate_controller.clone(),
+                        dynamic_depth: depth_limit.clone(),
+                    };
+                    match checkpoint.save(&self.config.checkpoint_path) {
+                        Ok(()) if self.config.verbose => println!(
+                            "Checkpoint written to {}",
+                            self.config.checkpoint_path.display()
+                        ),
+                        Ok(()) => {}
+                        Err(e) => eprintln!("Warning: {}", e),
+                    }
+                    // Where a run resumed from this checkpoint picks up too
+                    self.rng = seeded_rng(self.seed.wrapping_add(gen as u64 + 1));
+                }
+            }
+        }
+
+        if subset.is_some() {
+            // Report fitness on all the samples, like `best`
+            for individual in &mut population {
+                *individual = rescore(runner, &mut evaluate, individual, None);
+            }
+        }
+        sort_population(&mut population);
+        for individual in &population {
+            hall_of_fame.consider(individual);
+        }
+        let best = match best {
+            Some(b) if b.fitness >= population[0].fitness => b,
+            _ => population[0].clone(),
+        };
+        let (mut best, mut best_validation_fitness) = match self.validation.as_mut() {
+            Some(validate) => {
+                let final_fitness = validate(runner, &population[0].ast);
+                let validated_best = match validated_best {
+                    Some((individual, fitness)) if fitness >= final_fitness => (individual, fitness),
+                    _ => (population[0].clone(), final_fitness),
+                };
+                match self.config.select_by {
+                    SelectionCriterion::Validation => (validated_best.0, Some(validated_best.1)),
+                    SelectionCriterion::Train => {
+                        let fitness = validate(runner, &best.ast);
+                        (best, Some(fitness))
+                    }
+                }
+            }
+            None => (best, None),
+        };
+        let raw_best = if self.config.simplify_best {
+            let simplified = simplify_individual(runner, &mut evaluate, &best);
+            if let Some(validate) = self.validation.as_mut() {
+                if simplified.ast != best.ast {
+                    best_validation_fitness = Some(validate(runner, &simplified.ast));
+                }
+            }
+            Some(std::mem::replace(&mut best, simplified))
+        } else {
+            None
+        };
+
+        EvolutionResult {
+            best,
+            raw_best,
+            best_validation_fitness,
+            population,
+            best_fitness_history,
+            generation_stats,
+            generations_run,
+            stopped_early,
+            hall_of_fame,
+            seed: self.seed,
+            instruction_set: self.config.instruction_set.clone(),
+        }
+    }
+
+    /// True if the selection strategy looks at novelty scores.
+    fn uses_novelty(&self) -> bool {
+        matches!(
+            self.config.selection,
+            SelectionStrategy::Tournament { diversity_weight, .. } if diversity_weight != 0.0
+        )
+    }
+
+    fn random_individual<E>(
+        &mut self,
+        runner: &mut EvmRunner,
+        evaluate: &mut E,
+        subset: Option<&[usize]>,
+    ) -> Individual
+    where
+        E: FnMut(&mut EvmRunner, UntypedAst, Option<&[usize]>) -> Individual,
+    {
+        let ast = random_code_with_max_children(
+            &mut self.rng,
+            &self.config.instruction_set,
+            self.config.max_points,
+            self.config.max_children.unwrap_or(usize::MAX),
+        );
+        evaluate(runner, ast, subset)
+    }
+
+    /// Indices of the `sample_subset` samples generation `gen` is scored on,
+    /// or `None` for all of them: every `full_sample_every` generations, or if
+    /// the subset would not be smaller.
+    fn draw_subset(&mut self, sample_count: usize, gen: usize) -> Option<Vec<usize>> {
+        let size = self.config.sample_subset?;
+        let every = self.config.full_sample_every;
+        if size >= sample_count || (every > 0 && gen.is_multiple_of(every)) {
+            return None;
+        }
+        let mut indices = rand::seq::index::sample(&mut self.rng, sample_count, size).into_vec();
+        indices.sort_unstable();
+        Some(indices)
+    }
+
+    /// `population` must be sorted best first.
+    fn select_elites(&self, population: &[Individual]) -> Vec<Individual> {
+        match self.config.elitism {
+            Elitism::None => Vec::new(),
+            Elitism::Top(n) => population.iter().take(n).cloned().collect(),
+            Elitism::Diverse { count, min_distance } => {
+                diverse_elitism(population, count, min_distance)
+            }
+        }
+    }
+
+    /// `pool` must be sorted best first.
+    fn select_parent<'a>(&mut self, pool: &'a [Individual]) -> &'a Individual {
+        match self.config.selection {
+            SelectionStrategy::Truncation { fraction } => {
+                let count = ((pool.len() as f64 * fraction) as usize).clamp(1, pool.len());
+                &pool[self.rng.gen_range(0..count)]
+            }
+            SelectionStrategy::Tournament { size, diversity_weight } => {
+                diverse_tournament_selection(pool, size, diversity_weight, &mut self.rng)
+            }
+        }
+    }
+
+    /// Produce one or two children from selected parents.
+    /// Children deeper than `max_depth` are replaced by a copy of their parent.
+    fn reproduce(
+        &mut self,
+        pool: &[Individual],
+        operators: &OperatorWeights,
+        max_depth: Option<usize>,
+    ) -> Vec<UntypedAst> {
+        let parent1 = self.select_parent(pool);
+        let mut parents = vec![parent1];
+
+        let children = match operators.sample(&mut self.rng) {
+            Operator::Crossover => {
+                let parent2 = self.select_parent(pool);
+                parents.push(parent2);
+                let (child1, child2) = size_aware_crossover(
+                    &parent1.ast,
+                    &parent2.ast,
+                    &mut self.rng,
+                    Some(self.config.max_size),
+                );
+                vec![child1, child2]
+            }
+            Operator::PointMutation => {
+                vec![point_mutate_with_max_children(
+                    &parent1.ast,
+                    &mut self.rng,
+                    self.config.point_mutation_rate,
+                    &self.config.int_mutation,
+                    self.config.max_children.unwrap_or(POINT_MUTATION_MAX_CHILDREN),
+                )]
+            }
+            Operator::SubtreeMutation => vec![mutate_by_index_in_set(
+                &parent1.ast,
+                &mut self.rng,
+                &self.config.instruction_set,
+                self.config.max_points,
+                self.config.max_children.unwrap_or(usize::MAX),
+            )],
+            Operator::SizeLimitedMutation => vec![size_limited_mutate_in_set(
+                &parent1.ast,
+                &mut self.rng,
+                &self.config.instruction_set,
+                self.config.max_points,
+                self.config.max_size,
+                self.config.max_children.unwrap_or(usize::MAX),
+            )],
+        };
+
+        let children = if self.config.repair_offspring {
+            children.iter().map(repair).collect()
+        } else {
+            children
+        };
+        match max_depth {
+            Some(limit) => children
+                .into_iter()
+                .zip(parents)
+                .map(|(child, parent)| if get_depth(&child) <= limit { child } else { parent.ast.clone() })
+                .collect(),
+            None => children,
+        }
+    }
+}
+
+/// `individual` after `simplify` and then `minimize`, scored on all the
+/// samples. Each step is only kept if the fitness does not drop, so the result
+/// is never worse than `individual`.
+fn simplify_individual<E>(runner: &mut EvmRunner, evaluate: &mut E, individual: &Individual) -> Individual
+where
+    E: FnMut(&mut EvmRunner, UntypedAst, Option<&[usize]>) -> Individual,
+{
+    let target = individual.fitness;
+    let mut fitness_of = |runner: &mut EvmRunner, ast: &UntypedAst| evaluate(runner, ast.clone(), None).fitness;
+
+    let simplified = simplify(&individual.ast);
+    let start = if simplified != individual.ast && fitness_of(runner, &simplified) >= target {
+        simplified
+    } else {
+        individual.ast.clone()
+    };
+    let minimized = minimize(&start, runner, &mut fitness_of, target);
+    if minimized == individual.ast {
+        return individual.clone();
+    }
+
+    let mut result = evaluate(runner, minimized, None);
+    if result.fitness < target {
+        return individual.clone();
+    }
+    result.age = individual.age;
+    result.novelty_score = individual.novelty_score;
+    result
+}
+
+/// `individual`'s program scored again on `subset` (`None`: all the samples),
+/// keeping its age and novelty score.
+fn rescore<E>(
+    runner: &mut EvmRunner,
+    evaluate: &mut E,
+    individual: &Individual,
+    subset: Option<&[usize]>,
+) -> Individual
+where
+    E: FnMut(&mut EvmRunner, UntypedAst, Option<&[usize]>) -> Individual,
+{
+    let mut rescored = evaluate(runner, individual.ast.clone(), subset);
+    rescored.age = individual.age;
+    rescored.novelty_score = individual.novelty_score;
+    rescored
+}
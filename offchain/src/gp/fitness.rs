@@ -0,0 +1,928 @@
+//! src/gp/fitness.rs
+//!
+//! Shared helpers for scoring programs on (x, y) samples, or on
+//! multi-variable samples ([`MultiSample`]).
+//!
+//! Programs run on any [`Push3Backend`], usually an `EvmRunner`.
+//!
+//! Everything here works in `i128`, the width `EvmRunner` decodes the int stack to,
+//! so large results are compared as they are instead of being truncated to `i32`.
+//! Narrowing only happens at the very end, explicitly, via [`saturating_i32`].
+
+use crate::compiler::ast::{ast_contains, OpCode, Push3Ast, UntypedAst};
+use crate::compiler::stack_effect::final_depths;
+use crate::gp::metrics::{ast_metrics, weighted_size};
+use crate::runner::backend::Push3Backend;
+use crate::runner::revm_runner::{Push3InterpreterInputs, Push3InterpreterOutputs, RunError};
+
+/// One sample of a multi-variable target: the inputs, pushed in order onto
+/// the int stack (so the last one ends up on top), and the expected output.
+pub type MultiSample = (Vec<i128>, i128);
+
+/// One sample of a vector-valued target: the inputs (as in [`MultiSample`]) and
+/// the expected top of the int stack, bottom to top (the last one on top).
+pub type VectorSample = (Vec<i128>, Vec<i128>);
+
+/// What running a program on some inputs produced. Fitness functions decide
+/// how to score the failure cases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalOutcome {
+    /// The top of the final int stack.
+    Value(i128),
+    /// The call reverted or halted (e.g. a checked-arithmetic overflow or a
+    /// stack overflow in the interpreter).
+    Reverted,
+    /// The call used up its whole gas limit: most likely a program that keeps
+    /// pushing items back onto the exec stack (e.g. through `IF_THEN` / `IF_ELSE`).
+    OutOfGas,
+    /// The program ran but left the int stack empty, or left an int too wide
+    /// for `i128` where the answer is read.
+    EmptyStack,
+}
+
+impl EvalOutcome {
+    /// The value, or `None` for either kind of failure.
+    pub fn value(self) -> Option<i128> {
+        match self {
+            EvalOutcome::Value(v) => Some(v),
+            EvalOutcome::Reverted | EvalOutcome::OutOfGas | EvalOutcome::EmptyStack => None,
+        }
+    }
+
+    /// Classify the result of a run (e.g. of `EvmRunner::run_ast`): an `Ok` run
+    /// whose stack `policy` reads from is empty is `EmptyStack`, never a failure
+    /// of the call itself.
+    ///
+    /// ```
+    /// use offchain::gp::fitness::{AnswerPolicy, EvalOutcome};
+    /// use offchain::runner::revm_runner::{Push3InterpreterOutputs, RunError};
+    ///
+    /// let ran = |ints: Vec<i128>| Ok(Push3InterpreterOutputs {
+    ///     final_code_stack: Vec::new(),
+    ///     final_exec_stack: Vec::new(),
+    ///     final_int_stack: ints,
+    ///     final_bool_stack: Vec::new(),
+    ///     saturated_ints: Vec::new(),
+    /// });
+    /// let reverted = |out_of_gas| Err(RunError::Revert {
+    ///     gas_used: 100, gas_limit: 100, out_of_gas, reason: String::new(),
+    /// }.into());
+    ///
+    /// let top = AnswerPolicy::TopInt;
+    /// assert_eq!(EvalOutcome::from_run(&ran(vec![3]), top), EvalOutcome::Value(3));
+    /// assert_eq!(EvalOutcome::from_run(&ran(vec![]), top), EvalOutcome::EmptyStack);
+    /// assert_eq!(EvalOutcome::from_run(&reverted(false), top), EvalOutcome::Reverted);
+    /// assert_eq!(EvalOutcome::from_run(&reverted(true), top), EvalOutcome::OutOfGas);
+    /// ```
+    pub fn from_run(run: &anyhow::Result<Push3InterpreterOutputs>, policy: AnswerPolicy) -> Self {
+        match run {
+            Err(e) if e.downcast_ref::<RunError>().is_some_and(RunError::is_out_of_gas) => {
+                EvalOutcome::OutOfGas
+            }
+            Err(_) => EvalOutcome::Reverted,
+            Ok(outputs) => match policy.read(outputs) {
+                Some(v) => EvalOutcome::Value(v),
+                None => EvalOutcome::EmptyStack,
+            },
+        }
+    }
+}
+
+/// Which part of the final stacks counts as the program's answer.
+///
+/// ```
+/// use offchain::gp::fitness::AnswerPolicy;
+/// use offchain::runner::revm_runner::Push3InterpreterOutputs;
+///
+/// let outputs = Push3InterpreterOutputs {
+///     final_code_stack: Vec::new(),
+///     final_exec_stack: Vec::new(),
+///     final_int_stack: vec![7, -2, 5],
+///     final_bool_stack: vec![false, true],
+///     saturated_ints: Vec::new(),
+/// };
+/// assert_eq!(AnswerPolicy::TopInt.read(&outputs), Some(5));
+/// assert_eq!(AnswerPolicy::BottomInt.read(&outputs), Some(7));
+/// assert_eq!(AnswerPolicy::TopBool.read(&outputs), Some(1));
+/// assert_eq!(AnswerPolicy::SumInt.read(&outputs), Some(10));
+/// assert_eq!(AnswerPolicy::IntAtOffset(1).read(&outputs), Some(-2));
+/// assert_eq!(AnswerPolicy::IntAtOffset(3).read(&outputs), None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AnswerPolicy {
+    /// The top of the int stack (what every bin has always used).
+    #[default]
+    TopInt,
+    /// The bottom of the int stack.
+    BottomInt,
+    /// The top of the bool stack, as 1 / 0.
+    TopBool,
+    /// The sum of the whole int stack (saturating).
+    SumInt,
+    /// The int this many entries below the top, for programs that leave
+    /// scratch values above the answer (see [`top_int_at_offset`]).
+    IntAtOffset(usize),
+}
+
+impl AnswerPolicy {
+    /// Read the answer from `outputs`, or `None` if the stack it looks at is empty
+    /// or the int it reads was too wide for `i128` (see [`top_int_checked`]).
+    pub fn read(self, outputs: &Push3InterpreterOutputs) -> Option<i128> {
+        let ints = &outputs.final_int_stack;
+        match self {
+            AnswerPolicy::TopInt => top_int_checked(outputs),
+            AnswerPolicy::BottomInt => int_at(outputs, 0),
+            AnswerPolicy::TopBool => outputs.final_bool_stack.last().map(|&b| b as i128),
+            AnswerPolicy::SumInt => {
+                if ints.is_empty() || !outputs.saturated_ints.is_empty() {
+                    None
+                } else {
+                    Some(ints.iter().fold(0i128, |acc, &v| acc.saturating_add(v)))
+                }
+            }
+            AnswerPolicy::IntAtOffset(offset) => top_int_at_offset(outputs, offset),
+        }
+    }
+}
+
+/// The top of the final int stack, or `None` if the stack is empty or the top
+/// was out of `i128` range (the decoder clamped it, see
+/// [`Push3InterpreterOutputs::saturated_ints`]).
+///
+/// ```
+/// use offchain::gp::fitness::top_int_checked;
+/// use offchain::runner::revm_runner::Push3InterpreterOutputs;
+///
+/// let outputs = |ints: Vec<i128>, saturated_ints: Vec<usize>| Push3InterpreterOutputs {
+///     final_code_stack: Vec::new(),
+///     final_exec_stack: Vec::new(),
+///     final_int_stack: ints,
+///     final_bool_stack: Vec::new(),
+///     saturated_ints,
+/// };
+///
+/// // Wider than i32 but still an i128: kept as it is
+/// let wide = i32::MAX as i128 + 1;
+/// assert_eq!(top_int_checked(&outputs(vec![wide], vec![])), Some(wide));
+/// // An int256 above i128::MAX, clamped by the decoder
+/// assert_eq!(top_int_checked(&outputs(vec![1, i128::MAX], vec![1])), None);
+/// // Only the top matters
+/// assert_eq!(top_int_checked(&outputs(vec![i128::MIN, 4], vec![0])), Some(4));
+/// assert_eq!(top_int_checked(&outputs(vec![], vec![])), None);
+/// ```
+pub fn top_int_checked(outputs: &Push3InterpreterOutputs) -> Option<i128> {
+    let top = outputs.final_int_stack.len().checked_sub(1)?;
+    int_at(outputs, top)
+}
+
+/// The int at `index` (0 is the bottom), unless it is missing or was clamped.
+fn int_at(outputs: &Push3InterpreterOutputs, index: usize) -> Option<i128> {
+    if outputs.saturated_ints.contains(&index) {
+        return None;
+    }
+    outputs.final_int_stack.get(index).copied()
+}
+
+/// The int `offset` entries below the top of the final int stack (0 is the top,
+/// as [`top_int_checked`]), or `None` if the stack is not that deep or that int
+/// was out of `i128` range.
+///
+/// ```
+/// use offchain::gp::fitness::top_int_at_offset;
+/// use offchain::runner::revm_runner::Push3InterpreterOutputs;
+///
+/// // A program that left its answer, 9, below a scratch value, 1
+/// let outputs = Push3InterpreterOutputs {
+///     final_code_stack: Vec::new(),
+///     final_exec_stack: Vec::new(),
+///     final_int_stack: vec![9, 1],
+///     final_bool_stack: Vec::new(),
+///     saturated_ints: Vec::new(),
+/// };
+/// assert_eq!(top_int_at_offset(&outputs, 0), Some(1));
+/// assert_eq!(top_int_at_offset(&outputs, 1), Some(9));
+/// assert_eq!(top_int_at_offset(&outputs, 2), None);
+/// ```
+pub fn top_int_at_offset(outputs: &Push3InterpreterOutputs, offset: usize) -> Option<i128> {
+    let index = outputs.final_int_stack.len().checked_sub(offset + 1)?;
+    int_at(outputs, index)
+}
+
+/// Narrow an `i128` to `i32`, clamping instead of wrapping.
+pub fn saturating_i32(val: i128) -> i32 {
+    val.clamp(i32::MIN as i128, i32::MAX as i128) as i32
+}
+
+/// Absolute difference between prediction and target, saturating at `i128::MAX`.
+pub fn abs_error(predicted: i128, target: i128) -> i128 {
+    predicted.saturating_sub(target).saturating_abs()
+}
+
+/// Evaluate `ast` on a single input `x`: push `x` onto the int stack, run it on
+/// the EVM and read the top of the final int stack.
+///
+/// Returns `None` if the call fails/reverts or the int stack ends up empty.
+pub fn evaluate_ast_on_x(runner: &mut impl Push3Backend, ast: &UntypedAst, x: i128) -> Option<i128> {
+    safe_eval(runner, ast, &[x]).value()
+}
+
+/// Evaluate `ast` on several inputs: push `inputs` onto the int stack in order
+/// (`inputs[0]` at the bottom), run it on the EVM and read the top of the final
+/// int stack. For `f(x, y)` pass `&[x, y]`, so `y` is on top.
+///
+/// Returns `None` if the call fails/reverts or the int stack ends up empty.
+pub fn evaluate_ast_on_inputs(
+    runner: &mut impl Push3Backend,
+    ast: &UntypedAst,
+    inputs: &[i128],
+) -> Option<i128> {
+    safe_eval(runner, ast, inputs).value()
+}
+
+/// Run `ast` on `inputs` (as [`evaluate_ast_on_inputs`]) and return the top
+/// `expected_len` entries of the final int stack, bottom to top. If the stack
+/// holds fewer, all of them are returned (so the result is shorter). `None`
+/// means the call failed.
+pub fn evaluate_vector_output(
+    runner: &mut impl Push3Backend,
+    ast: &UntypedAst,
+    inputs: &[i128],
+    expected_len: usize,
+) -> Option<Vec<i128>> {
+    let call_inputs = Push3InterpreterInputs::for_program(ast.to_bytecode(), inputs, Vec::new());
+    let outputs = runner.run(&call_inputs).ok()?;
+    let ints = outputs.final_int_stack;
+    let start = ints.len().saturating_sub(expected_len);
+    Some(ints[start..].to_vec())
+}
+
+/// Squared error charged for each expected output the program did not produce
+/// (its square, per element). Large, so producing every output matters first.
+pub const MISSING_OUTPUT_ERROR: f64 = 1e6;
+
+/// Sum of element-wise squared errors between `predicted` and `expected`, both
+/// bottom to top and aligned at the top of the stack. Expected entries with no
+/// counterpart (`predicted` is shorter) cost `MISSING_OUTPUT_ERROR²` each.
+///
+/// ```
+/// use offchain::gp::fitness::{vector_squared_error, MISSING_OUTPUT_ERROR};
+///
+/// assert_eq!(vector_squared_error(&[3, 4], &[3, 4]), 0.0);
+/// assert_eq!(vector_squared_error(&[1, 4], &[3, 5]), 4.0 + 1.0);
+/// // Only the top was produced: it is compared with the last expected value
+/// assert_eq!(vector_squared_error(&[5], &[3, 5]), MISSING_OUTPUT_ERROR.powi(2));
+/// ```
+pub fn vector_squared_error(predicted: &[i128], expected: &[i128]) -> f64 {
+    let missing = expected.len().saturating_sub(predicted.len());
+    let predicted = &predicted[predicted.len().saturating_sub(expected.len())..];
+    let matched: f64 = predicted
+        .iter()
+        .zip(&expected[missing..])
+        .map(|(&p, &e)| (abs_error(p, e) as f64).powi(2))
+        .sum();
+    matched + missing as f64 * MISSING_OUTPUT_ERROR.powi(2)
+}
+
+/// Mean squared error of `ast` over vector-valued samples, per output element
+/// (lower is better, 0 is perfect). A failed run counts every output as missing.
+/// For the engine, which maximizes, use e.g. `1.0 / (1.0 + vector_mse(...))`.
+pub fn vector_mse(runner: &mut impl Push3Backend, ast: &UntypedAst, samples: &[VectorSample]) -> f64 {
+    let mut total = 0.0;
+    let mut count = 0;
+    for (inputs, expected) in samples {
+        let predicted = evaluate_vector_output(runner, ast, inputs, expected.len()).unwrap_or_default();
+        total += vector_squared_error(&predicted, expected);
+        count += expected.len();
+    }
+    if count == 0 {
+        0.0
+    } else {
+        total / count as f64
+    }
+}
+
+/// Like [`evaluate_ast_on_inputs`], but tells the ways of failing apart.
+pub fn safe_eval(runner: &mut impl Push3Backend, ast: &UntypedAst, inputs: &[i128]) -> EvalOutcome {
+    safe_eval_with(runner, ast, inputs, AnswerPolicy::TopInt)
+}
+
+/// [`safe_eval`] reading the answer according to `policy`. `EmptyStack` means
+/// the stack the policy reads from was empty.
+pub fn safe_eval_with(
+    runner: &mut impl Push3Backend,
+    ast: &UntypedAst,
+    inputs: &[i128],
+    policy: AnswerPolicy,
+) -> EvalOutcome {
+    let call_inputs = Push3InterpreterInputs::for_program(ast.to_bytecode(), inputs, Vec::new());
+    EvalOutcome::from_run(&runner.run(&call_inputs).map_err(anyhow::Error::from), policy)
+}
+
+/// Reward for one sample, given the absolute error: the closer, the higher.
+/// Uses inverse decay in bands for smooth gradients (1000 for a perfect hit).
+pub fn graded_sample_fitness(diff: i128) -> f64 {
+    let diff = diff as f64;
+    if diff == 0.0 {
+        1000.0 // Perfect match gets high reward
+    } else if diff <= 1.0 {
+        100.0 / (1.0 + diff) // Very close gets good reward
+    } else if diff <= 10.0 {
+        50.0 / (1.0 + diff * 0.5) // Moderately close gets decent reward
+    } else if diff <= 100.0 {
+        20.0 / (1.0 + diff * 0.1) // Within range gets some reward
+    } else {
+        10.0 / (1.0 + diff * 0.01) // Far but finite gets minimal reward
+    }
+}
+
+/// Parsimony multiplier from size bands: `bands` are `(threshold, factor)` pairs,
+/// and the factor of the largest threshold that `size` exceeds applies (1.0 if
+/// none does). Typically `size` is one field of `metrics::ast_metrics`.
+///
+/// ```
+/// use offchain::gp::fitness::banded_penalty;
+///
+/// let bands = [(40, 0.8), (25, 0.9), (15, 0.95)];
+/// assert_eq!(banded_penalty(10, &bands), 1.0);
+/// assert_eq!(banded_penalty(15, &bands), 1.0);
+/// assert_eq!(banded_penalty(16, &bands), 0.95);
+/// assert_eq!(banded_penalty(41, &bands), 0.8);
+/// ```
+pub fn banded_penalty(size: usize, bands: &[(usize, f64)]) -> f64 {
+    bands
+        .iter()
+        .filter(|&&(threshold, _)| size > threshold)
+        .max_by_key(|&&(threshold, _)| threshold)
+        .map_or(1.0, |&(_, factor)| factor)
+}
+
+/// How much a program's size (its node count) lowers its fitness.
+///
+/// ```
+/// use offchain::gp::fitness::ParsimonyPressure;
+/// use offchain::push3;
+///
+/// let ast = push3!((1 2 + DUP *)); // 6 nodes
+/// assert_eq!(ParsimonyPressure::None.apply(100.0, &ast), 100.0);
+/// assert_eq!(ParsimonyPressure::Linear { coeff: 0.5 }.apply(100.0, &ast), 97.0);
+/// assert_eq!(ParsimonyPressure::Banded(vec![(10, 0.5), (5, 0.9)]).apply(100.0, &ast), 90.0);
+///
+/// // Lexicographic: size only breaks ties
+/// let small = push3!((1 2 +));
+/// let lex = ParsimonyPressure::Lexicographic;
+/// assert!(lex.apply(100.0, &small) > lex.apply(100.0, &ast));
+/// assert!(lex.apply(100.0, &ast) > lex.apply(99.9, &small));
+///
+/// // Weighted: POW costs 4 nodes' worth
+/// let weighted = ParsimonyPressure::Weighted { coeff: 0.5 };
+/// assert_eq!(weighted.apply(100.0, &ast), 97.0);
+/// assert_eq!(weighted.apply(100.0, &push3!((1 2 POW DUP *))), 95.5);
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ParsimonyPressure {
+    /// Fitness is left as is.
+    #[default]
+    None,
+    /// Subtract `coeff` per node.
+    Linear { coeff: f64 },
+    /// Multiply by [`banded_penalty`] of the node count.
+    Banded(Vec<(usize, f64)>),
+    /// Prefer the smaller of two programs only when their fitness is equal:
+    /// subtracts [`LEXICOGRAPHIC_EPSILON`] per node, far below any difference
+    /// between the graded sample rewards.
+    Lexicographic,
+    /// Subtract `coeff` per unit of [`weighted_size`], steering the search
+    /// towards cheaper instruction mixes.
+    Weighted { coeff: f64 },
+}
+
+/// Per-node penalty of [`ParsimonyPressure::Lexicographic`].
+pub const LEXICOGRAPHIC_EPSILON: f64 = 1e-6;
+
+impl ParsimonyPressure {
+    /// `fitness` of `ast` after the size penalty.
+    pub fn apply(&self, fitness: f64, ast: &UntypedAst) -> f64 {
+        let size = ast_metrics(ast).total_nodes;
+        match self {
+            ParsimonyPressure::None => fitness,
+            ParsimonyPressure::Linear { coeff } => fitness - coeff * size as f64,
+            ParsimonyPressure::Banded(bands) => fitness * banded_penalty(size, bands),
+            ParsimonyPressure::Lexicographic => fitness - LEXICOGRAPHIC_EPSILON * size as f64,
+            ParsimonyPressure::Weighted { coeff } => fitness - coeff * weighted_size(ast),
+        }
+    }
+}
+
+/// How [`graded_fitness_with_policy`] scores failed samples. Suspected loops
+/// (out of gas) are punished harder than stack or arithmetic errors: they earn
+/// less per sample and also scale down the whole fitness, since they cost far
+/// more gas to evaluate.
+///
+/// A failed sample's credit goes into the sum that is then divided by the
+/// number of samples, like the rewards of the samples that ran. So a failure
+/// costs `(its reward had it run - failure_credit) / n`, less the more samples
+/// there are, and a program failing everywhere scores exactly
+/// `failure_credit` whatever `n` is. `run_fraction_weight` adds an explicit
+/// term for the fraction of samples that ran, which does not dilute with `n`.
+///
+/// ```
+/// use offchain::gp::fitness::{EvalOutcome, FailurePolicy};
+///
+/// let policy = FailurePolicy::default();
+/// let reverted = policy.score(&[(EvalOutcome::Value(5), 5), (EvalOutcome::Reverted, 1)]);
+/// let looped = policy.score(&[(EvalOutcome::Value(5), 5), (EvalOutcome::OutOfGas, 1)]);
+/// assert_eq!(reverted, (1000.0 + 0.1) / 2.0);
+/// assert_eq!(looped, 1000.0 / 2.0 * 0.5);
+/// assert!(looped < reverted);
+///
+/// // Without failures it is `graded_fitness`
+/// let all_ran = policy.score(&[(EvalOutcome::Value(5), 5), (EvalOutcome::Value(2), 5)]);
+/// assert_eq!(all_ran, offchain::gp::fitness::graded_fitness_from_errors(&[Some(0), Some(3)]));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FailurePolicy {
+    /// Per-sample credit for a revert or an empty stack.
+    pub failure_credit: f64,
+    /// Per-sample credit for running out of gas.
+    pub out_of_gas_credit: f64,
+    /// Multiplier for the whole fitness once any sample ran out of gas.
+    pub out_of_gas_factor: f64,
+    /// Added to the fitness times the fraction of samples that ran (0 to 1).
+    pub run_fraction_weight: f64,
+}
+
+impl Default for FailurePolicy {
+    fn default() -> Self {
+        Self {
+            failure_credit: 0.1,
+            out_of_gas_credit: 0.0,
+            out_of_gas_factor: 0.5,
+            run_fraction_weight: 0.0,
+        }
+    }
+}
+
+impl FailurePolicy {
+    /// Graded fitness of per-sample errors (`None` where the run failed), as
+    /// [`graded_fitness_from_errors`] with this policy's credits. Errors don't
+    /// say why a run failed, so every failure earns `failure_credit`.
+    ///
+    /// ```
+    /// use offchain::gp::fitness::{graded_fitness_from_errors, FailurePolicy};
+    ///
+    /// let errors = [Some(0), None, None, None];
+    /// assert_eq!(FailurePolicy::default().score_errors(&errors), graded_fitness_from_errors(&errors));
+    ///
+    /// // No credit at all: failures only count through the average
+    /// let harsh = FailurePolicy { failure_credit: 0.0, ..FailurePolicy::default() };
+    /// assert_eq!(harsh.score_errors(&errors), 1000.0 / 4.0);
+    /// assert_eq!(harsh.score_errors(&[None, None]), 0.0);
+    ///
+    /// // A failing-everywhere program scores its credit, whatever the sample count
+    /// let lenient = FailurePolicy { failure_credit: 5.0, ..FailurePolicy::default() };
+    /// assert_eq!(lenient.score_errors(&[None; 3]), 5.0);
+    /// assert_eq!(lenient.score_errors(&[None; 30]), 5.0);
+    ///
+    /// // Reward running at all: 1 of 4 samples ran
+    /// let counted = FailurePolicy { failure_credit: 0.0, run_fraction_weight: 100.0, ..FailurePolicy::default() };
+    /// assert_eq!(counted.score_errors(&errors), 1000.0 / 4.0 + 25.0);
+    /// ```
+    pub fn score_errors(&self, errors: &[Option<i128>]) -> f64 {
+        let mut total_fitness = 0.0;
+        let mut successful_evaluations = 0;
+
+        for error in errors {
+            match error {
+                Some(diff) => {
+                    successful_evaluations += 1;
+                    total_fitness += graded_sample_fitness(*diff);
+                }
+                None => total_fitness += self.failure_credit,
+            }
+        }
+
+        self.average(total_fitness, successful_evaluations, errors.len(), false)
+    }
+
+    /// Graded fitness of `(outcome, target)` pairs, one per sample: as
+    /// [`graded_fitness_from_errors`], with failures scored by this policy.
+    pub fn score(&self, outcomes: &[(EvalOutcome, i128)]) -> f64 {
+        let mut total_fitness = 0.0;
+        let mut successful_evaluations = 0;
+        let mut ran_out_of_gas = false;
+
+        for &(outcome, target) in outcomes {
+            match outcome {
+                EvalOutcome::Value(v) => {
+                    successful_evaluations += 1;
+                    total_fitness += graded_sample_fitness(abs_error(v, target));
+                }
+                EvalOutcome::OutOfGas => {
+                    ran_out_of_gas = true;
+                    total_fitness += self.out_of_gas_credit;
+                }
+                EvalOutcome::Reverted | EvalOutcome::EmptyStack => {
+                    total_fitness += self.failure_credit;
+                }
+            }
+        }
+
+        self.average(total_fitness, successful_evaluations, outcomes.len(), ran_out_of_gas)
+    }
+
+    /// Turn the summed per-sample rewards into the fitness: the reliability
+    /// bonus, the out-of-gas factor, the average, then the run-fraction term.
+    fn average(&self, mut total_fitness: f64, successful: usize, samples: usize, ran_out_of_gas: bool) -> f64 {
+        // Bonus for programs that execute successfully on all samples
+        if successful == samples {
+            total_fitness *= 1.2;
+        }
+        if ran_out_of_gas {
+            total_fitness *= self.out_of_gas_factor;
+        }
+
+        let fitness = total_fitness / samples as f64;
+        if self.run_fraction_weight == 0.0 {
+            fitness
+        } else {
+            fitness + self.run_fraction_weight * successful as f64 / samples as f64
+        }
+    }
+}
+
+/// [`graded_fitness`] with failed samples scored by `policy`; see [`FailurePolicy`].
+/// Pair it with `EvmRunner::with_gas_limit` so looping programs stop early.
+pub fn graded_fitness_with_policy(
+    runner: &mut impl Push3Backend,
+    ast: &UntypedAst,
+    samples: &[(i128, i128)],
+    policy: &FailurePolicy,
+) -> f64 {
+    let outcomes: Vec<(EvalOutcome, i128)> = samples
+        .iter()
+        .map(|&(x, target_y)| (safe_eval(runner, ast, &[x]), target_y))
+        .collect();
+    policy.score(&outcomes)
+}
+
+/// Absolute error of `ast` on each `(x, y)` sample, in order; `None` where the
+/// run reverted or left the int stack empty.
+///
+/// This is the raw material for lexicase selection and diagnostics; the
+/// aggregate [`graded_fitness`] is [`graded_fitness_from_errors`] of it.
+pub fn evaluate_errors(
+    runner: &mut impl Push3Backend,
+    ast: &UntypedAst,
+    samples: &[(i128, i128)],
+) -> Vec<Option<i128>> {
+    evaluate_errors_with(runner, ast, samples, AnswerPolicy::TopInt)
+}
+
+/// [`evaluate_errors`] reading each answer according to `policy`.
+pub fn evaluate_errors_with(
+    runner: &mut impl Push3Backend,
+    ast: &UntypedAst,
+    samples: &[(i128, i128)],
+    policy: AnswerPolicy,
+) -> Vec<Option<i128>> {
+    let options = EvalOptions { answer: policy, ..EvalOptions::default() };
+    evaluate_errors_with_options(runner, ast, samples, &options)
+}
+
+/// How an `(x, y)` sample is fed to a program and its answer read back.
+///
+/// ```
+/// use offchain::gp::fitness::EvalOptions;
+///
+/// assert_eq!(EvalOptions::default().initial_int_stack(4), [4]);
+/// let triple = EvalOptions { replicate_input: 3, ..EvalOptions::default() };
+/// assert_eq!(triple.initial_int_stack(4), [4, 4, 4]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvalOptions {
+    pub answer: AnswerPolicy,
+    /// Copies of `x` on the initial int stack, so programs can use it several
+    /// times without `DUP`. 1 is the usual single input.
+    pub replicate_input: usize,
+    /// Skip the EVM for programs that provably leave no answer (see
+    /// [`EvalOptions::provably_no_answer`]) and score every sample as failed,
+    /// as the run would have.
+    pub static_prune: bool,
+    /// Runs per sample for programs containing `ConstRand`, whose answer can
+    /// change from run to run; their errors are combined by `rand_aggregate`,
+    /// and the sample fails if any run does. Other programs always run once.
+    pub rand_repeats: usize,
+    pub rand_aggregate: RepeatAggregate,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        Self {
+            answer: AnswerPolicy::TopInt,
+            replicate_input: 1,
+            static_prune: false,
+            rand_repeats: 1,
+            rand_aggregate: RepeatAggregate::Mean,
+        }
+    }
+}
+
+/// How the errors of repeated runs on one sample are combined (see
+/// [`EvalOptions::rand_repeats`]).
+///
+/// ```
+/// use offchain::gp::fitness::RepeatAggregate;
+///
+/// assert_eq!(RepeatAggregate::Mean.combine(&[1, 2, 6]), 3);
+/// assert_eq!(RepeatAggregate::Mean.combine(&[1, 2]), 2);
+/// assert_eq!(RepeatAggregate::Min.combine(&[4, 1, 6]), 1);
+/// assert_eq!(RepeatAggregate::Max.combine(&[4, 1, 6]), 6);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepeatAggregate {
+    /// The mean error, rounded to the nearest integer.
+    #[default]
+    Mean,
+    /// The best run.
+    Min,
+    /// The worst run.
+    Max,
+}
+
+impl RepeatAggregate {
+    /// Combine the errors of a non-empty set of runs.
+    pub fn combine(self, errors: &[i128]) -> i128 {
+        match self {
+            RepeatAggregate::Mean => {
+                let sum: f64 = errors.iter().map(|&e| e as f64).sum();
+                (sum / errors.len() as f64).round() as i128
+            }
+            RepeatAggregate::Min => errors.iter().copied().min().unwrap_or(0),
+            RepeatAggregate::Max => errors.iter().copied().max().unwrap_or(0),
+        }
+    }
+}
+
+impl EvalOptions {
+    /// The int stack a program starts from for input `x`.
+    pub fn initial_int_stack(&self, x: i128) -> Vec<i128> {
+        vec![x; self.replicate_input]
+    }
+
+    /// True if, whatever `x` is, `ast` ends with the stack `answer` reads too
+    /// shallow to read from (see `stack_effect::final_depths`). Programs with
+    /// conditionals are never pruned.
+    ///
+    /// ```
+    /// use offchain::gp::fitness::{AnswerPolicy, EvalOptions};
+    /// use offchain::push3;
+    ///
+    /// let options = EvalOptions::default();
+    /// assert!(options.provably_no_answer(&push3!((POP))));
+    /// assert!(options.provably_no_answer(&push3!((DUP + 3 <))));
+    /// assert!(!options.provably_no_answer(&push3!((DUP * 1 POP))));
+    ///
+    /// let offset = EvalOptions { answer: AnswerPolicy::IntAtOffset(1), ..EvalOptions::default() };
+    /// assert!(offset.provably_no_answer(&push3!((DUP *))));
+    /// ```
+    pub fn provably_no_answer(&self, ast: &UntypedAst) -> bool {
+        let Some((ints, bools)) = final_depths(ast, self.replicate_input, 0) else {
+            return false;
+        };
+        match self.answer {
+            AnswerPolicy::TopInt | AnswerPolicy::BottomInt | AnswerPolicy::SumInt => ints == 0,
+            AnswerPolicy::TopBool => bools == 0,
+            AnswerPolicy::IntAtOffset(offset) => ints <= offset,
+        }
+    }
+}
+
+/// [`evaluate_errors`] with the input and answer handled as `options` says.
+///
+/// With `rand_repeats` above 1, programs using `ConstRand` run that many
+/// times per sample. A backend whose answer drifts by 10 on every call shows
+/// the repeats being averaged:
+///
+/// ```
+/// use offchain::gp::fitness::{evaluate_errors_with_options, EvalOptions, RepeatAggregate};
+/// use offchain::push3;
+/// use offchain::runner::backend::Push3Backend;
+/// use offchain::runner::revm_runner::{Push3InterpreterInputs, Push3InterpreterOutputs, RunError};
+///
+/// struct Drifting {
+///     calls: i128,
+/// }
+///
+/// impl Push3Backend for Drifting {
+///     fn run(&mut self, _inputs: &Push3InterpreterInputs) -> Result<Push3InterpreterOutputs, RunError> {
+///         self.calls += 1;
+///         Ok(Push3InterpreterOutputs {
+///             final_code_stack: Vec::new(),
+///             final_exec_stack: Vec::new(),
+///             final_int_stack: vec![self.calls * 10],
+///             final_bool_stack: Vec::new(),
+///             saturated_ints: Vec::new(),
+///         })
+///     }
+/// }
+///
+/// let options = EvalOptions { rand_repeats: 3, ..EvalOptions::default() };
+///
+/// // Answers 10, 20, 30: errors 10, 0, 10 against 20
+/// let mut backend = Drifting { calls: 0 };
+/// let errors = evaluate_errors_with_options(&mut backend, &push3!((RAND +)), &[(0, 20)], &options);
+/// assert_eq!((errors, backend.calls), (vec![Some(7)], 3));
+///
+/// let worst = EvalOptions { rand_aggregate: RepeatAggregate::Max, ..options };
+/// let mut backend = Drifting { calls: 0 };
+/// let errors = evaluate_errors_with_options(&mut backend, &push3!((RAND +)), &[(0, 20)], &worst);
+/// assert_eq!(errors, vec![Some(10)]);
+///
+/// // No `ConstRand`: a single run
+/// let mut backend = Drifting { calls: 0 };
+/// let errors = evaluate_errors_with_options(&mut backend, &push3!((3 +)), &[(0, 20)], &options);
+/// assert_eq!((errors, backend.calls), (vec![Some(10)], 1));
+/// ```
+pub fn evaluate_errors_with_options(
+    runner: &mut impl Push3Backend,
+    ast: &UntypedAst,
+    samples: &[(i128, i128)],
+    options: &EvalOptions,
+) -> Vec<Option<i128>> {
+    if options.static_prune && options.provably_no_answer(ast) {
+        return vec![None; samples.len()];
+    }
+    let repeats = if ast_contains(ast, &OpCode::ConstRand) { options.rand_repeats.max(1) } else { 1 };
+    samples
+        .iter()
+        .map(|&(x, target_y)| {
+            let inputs = options.initial_int_stack(x);
+            let errors = (0..repeats)
+                .map(|_| {
+                    safe_eval_with(runner, ast, &inputs, options.answer)
+                        .value()
+                        .map(|y| abs_error(y, target_y))
+                })
+                .collect::<Option<Vec<i128>>>()?;
+            Some(options.rand_aggregate.combine(&errors))
+        })
+        .collect()
+}
+
+/// [`evaluate_errors`] over multi-variable samples.
+pub fn evaluate_errors_multi(
+    runner: &mut impl Push3Backend,
+    ast: &UntypedAst,
+    samples: &[MultiSample],
+) -> Vec<Option<i128>> {
+    samples
+        .iter()
+        .map(|(inputs, target_y)| {
+            evaluate_ast_on_inputs(runner, ast, inputs).map(|y| abs_error(y, *target_y))
+        })
+        .collect()
+}
+
+/// Average graded fitness of `ast` over all `(x, y)` samples (higher = better).
+///
+/// - failed runs get a small partial credit of 0.1,
+/// - programs that run on every sample get a 20% reliability bonus.
+pub fn graded_fitness(runner: &mut impl Push3Backend, ast: &UntypedAst, samples: &[(i128, i128)]) -> f64 {
+    graded_fitness_from_errors(&evaluate_errors(runner, ast, samples))
+}
+
+/// [`graded_fitness`] reading the answer `answer_offset` entries below the top
+/// of the int stack; an offset of 0 is exactly `graded_fitness`.
+pub fn graded_fitness_at_offset(
+    runner: &mut impl Push3Backend,
+    ast: &UntypedAst,
+    samples: &[(i128, i128)],
+    answer_offset: usize,
+) -> f64 {
+    let policy = AnswerPolicy::IntAtOffset(answer_offset);
+    graded_fitness_from_errors(&evaluate_errors_with(runner, ast, samples, policy))
+}
+
+/// [`graded_fitness`] with the input and answer handled as `options` says,
+/// e.g. with `x` replicated on the initial int stack.
+pub fn graded_fitness_with_options(
+    runner: &mut impl Push3Backend,
+    ast: &UntypedAst,
+    samples: &[(i128, i128)],
+    options: &EvalOptions,
+) -> f64 {
+    graded_fitness_from_errors(&evaluate_errors_with_options(runner, ast, samples, options))
+}
+
+/// Pearson correlation of `predicted` with `target`, in `[-1, 1]`: 1 when the
+/// program gets the shape of the target right (up to scale and offset) even if
+/// every value is off. 0 if the slices differ in length, have fewer than two
+/// points, or either is constant.
+///
+/// ```
+/// use offchain::gp::fitness::behavioral_correlation;
+///
+/// let target = [1, 4, 9, 16];
+/// assert_eq!(behavioral_correlation(&[101, 104, 109, 116], &target), 1.0);
+/// assert_eq!(behavioral_correlation(&[-2, -8, -18, -32], &target), -1.0);
+/// assert_eq!(behavioral_correlation(&[7, 7, 7, 7], &target), 0.0);
+/// ```
+pub fn behavioral_correlation(predicted: &[i128], target: &[i128]) -> f64 {
+    let n = predicted.len();
+    if n != target.len() || n < 2 {
+        return 0.0;
+    }
+    let mean = |values: &[i128]| values.iter().map(|&v| v as f64).sum::<f64>() / n as f64;
+    let (mean_p, mean_t) = (mean(predicted), mean(target));
+
+    let (mut cov, mut var_p, mut var_t) = (0.0, 0.0, 0.0);
+    for (&p, &t) in predicted.iter().zip(target) {
+        let (dp, dt) = (p as f64 - mean_p, t as f64 - mean_t);
+        cov += dp * dt;
+        var_p += dp * dp;
+        var_t += dt * dt;
+    }
+    if var_p == 0.0 || var_t == 0.0 {
+        return 0.0;
+    }
+    (cov / (var_p * var_t).sqrt()).clamp(-1.0, 1.0)
+}
+
+/// Graded fitness of a program's outputs (`None` where the run failed) plus
+/// `correlation_weight` times their [`behavioral_correlation`] with `targets`,
+/// over the samples that ran. Only positive correlation is rewarded, so a
+/// weight of 0 gives plain [`graded_fitness_from_errors`].
+///
+/// ```
+/// use offchain::gp::fitness::{correlated_fitness_from_outputs, graded_fitness_from_errors};
+///
+/// let targets = [0, 1, 2, 3, 4, 5];
+/// // Right shape, 50 too high everywhere
+/// let offset: Vec<Option<i128>> = targets.iter().map(|&t| Some(t + 50)).collect();
+/// // A constant, with slightly smaller errors (47..=52)
+/// let flat = vec![Some(52); targets.len()];
+///
+/// let errors = |outputs: &[Option<i128>]| -> Vec<Option<i128>> {
+///     outputs.iter().zip(&targets).map(|(p, &t)| p.map(|p| (p - t).abs())).collect()
+/// };
+/// // Absolute error alone slightly prefers the constant...
+/// assert!(graded_fitness_from_errors(&errors(&flat)) > graded_fitness_from_errors(&errors(&offset)));
+/// // ...the correlation term prefers the right shape
+/// assert!(
+///     correlated_fitness_from_outputs(&offset, &targets, 10.0)
+///         > correlated_fitness_from_outputs(&flat, &targets, 10.0)
+/// );
+/// ```
+pub fn correlated_fitness_from_outputs(outputs: &[Option<i128>], targets: &[i128], correlation_weight: f64) -> f64 {
+    let errors: Vec<Option<i128>> = outputs
+        .iter()
+        .zip(targets)
+        .map(|(output, &target)| output.map(|p| abs_error(p, target)))
+        .collect();
+    let (ran, ran_targets): (Vec<i128>, Vec<i128>) = outputs
+        .iter()
+        .zip(targets)
+        .filter_map(|(output, &target)| output.map(|p| (p, target)))
+        .unzip();
+    let correlation = behavioral_correlation(&ran, &ran_targets).max(0.0);
+    graded_fitness_from_errors(&errors) + correlation_weight * correlation
+}
+
+/// [`graded_fitness`] plus a reward for matching the target's shape; see
+/// [`correlated_fitness_from_outputs`]. Meant to guide early generations,
+/// when no program is close in absolute terms yet.
+pub fn correlated_graded_fitness(
+    runner: &mut impl Push3Backend,
+    ast: &UntypedAst,
+    samples: &[(i128, i128)],
+    correlation_weight: f64,
+) -> f64 {
+    let outputs: Vec<Option<i128>> =
+        samples.iter().map(|&(x, _)| evaluate_ast_on_x(runner, ast, x)).collect();
+    let targets: Vec<i128> = samples.iter().map(|&(_, y)| y).collect();
+    correlated_fitness_from_outputs(&outputs, &targets, correlation_weight)
+}
+
+/// [`graded_fitness`] over multi-variable samples.
+pub fn graded_fitness_multi(runner: &mut impl Push3Backend, ast: &UntypedAst, samples: &[MultiSample]) -> f64 {
+    graded_fitness_from_errors(&evaluate_errors_multi(runner, ast, samples))
+}
+
+/// Aggregate per-sample errors (as from [`evaluate_errors`]) into the graded
+/// fitness, so one evaluation pass can feed both.
+///
+/// ```
+/// use offchain::gp::fitness::{graded_fitness_from_errors, graded_sample_fitness};
+///
+/// // All samples ran: average reward with the 20% reliability bonus
+/// let errors = [Some(0), Some(3)];
+/// let expected = (graded_sample_fitness(0) + graded_sample_fitness(3)) * 1.2 / 2.0;
+/// assert_eq!(graded_fitness_from_errors(&errors), expected);
+///
+/// // A failed run gets 0.1 and costs the bonus
+/// let errors = [Some(0), None];
+/// assert_eq!(graded_fitness_from_errors(&errors), (1000.0 + 0.1) / 2.0);
+/// ```
+pub fn graded_fitness_from_errors(errors: &[Option<i128>]) -> f64 {
+    FailurePolicy::default().score_errors(errors)
+}
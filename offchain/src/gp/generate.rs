@@ -1,15 +1,23 @@
 use rand::Rng;
 use crate::compiler::ast::{UntypedAst, OpCode};
 
+/// Sublist arity used by `random_ast` / `random_sublist_ast`.
+pub const DEFAULT_MAX_CHILDREN: usize = 3;
+
 pub fn random_sublist_ast(rng: &mut impl Rng, max_depth: usize) -> UntypedAst {
+    random_sublist_ast_with(rng, max_depth, DEFAULT_MAX_CHILDREN)
+}
+
+/// `random_sublist_ast` with every sublist holding `1..=max_children` children.
+pub fn random_sublist_ast_with(rng: &mut impl Rng, max_depth: usize, max_children: usize) -> UntypedAst {
     // 1) Choose how many children the top-level `Sublist` will have.
-    let len = rng.gen_range(1..=3);
+    let len = rng.gen_range(1..=max_children.max(1));
 
-    // 2) Build a vector of sub-ASTs by calling `random_ast` for each child
+    // 2) Build a vector of sub-ASTs by calling `random_ast_with` for each child
     let mut children = Vec::with_capacity(len);
     for _ in 0..len {
         // We start at `depth=1` because the top-level sublist itself is `depth=0`.
-        children.push(random_ast(rng, 1, max_depth));
+        children.push(random_ast_with(rng, 1, max_depth, max_children));
     }
 
     // 3) Return a `Sublist` as the root node
@@ -22,6 +30,27 @@ pub fn random_sublist_ast(rng: &mut impl Rng, max_depth: usize) -> UntypedAst {
 /// - `depth` tracks how deep we are in the tree.
 /// - `max_depth` is the maximum allowed depth to prevent infinite recursion.
 pub fn random_ast(rng: &mut impl Rng, depth: usize, max_depth: usize) -> UntypedAst {
+    random_ast_with(rng, depth, max_depth, DEFAULT_MAX_CHILDREN)
+}
+
+/// `random_ast` with every sublist holding `1..=max_children` children.
+///
+/// ```
+/// use offchain::gp::generate::{random_ast_with, random_sublist_ast_with};
+/// use offchain::gp::metrics::ast_metrics;
+/// use offchain::helpers::rng::seeded_rng;
+///
+/// let mut rng = seeded_rng(7);
+/// for max_children in 1..=6 {
+///     for _ in 0..50 {
+///         let ast = random_ast_with(&mut rng, 0, 5, max_children);
+///         assert!(ast_metrics(&ast).max_arity <= max_children);
+///         let ast = random_sublist_ast_with(&mut rng, 5, max_children);
+///         assert!(ast_metrics(&ast).max_arity <= max_children);
+///     }
+/// }
+/// ```
+pub fn random_ast_with(rng: &mut impl Rng, depth: usize, max_depth: usize, max_children: usize) -> UntypedAst {
     if depth >= max_depth {
         // Return something "terminal," 
         // e.g. an IntLiteral or a single Instruction
@@ -36,11 +65,11 @@ pub fn random_ast(rng: &mut impl Rng, depth: usize, max_depth: usize) -> Untyped
             0 => UntypedAst::IntLiteral(rng.gen_range(-10..10)),
             1 => UntypedAst::Instruction(random_opcode(rng)),
             2 => {
-                // Make a sublist with 1..=3 children
-                let len = rng.gen_range(1..=3);
+                // Make a sublist with 1..=max_children children
+                let len = rng.gen_range(1..=max_children.max(1));
                 let mut children = Vec::with_capacity(len);
                 for _ in 0..len {
-                    children.push(random_ast(rng, depth + 1, max_depth));
+                    children.push(random_ast_with(rng, depth + 1, max_depth, max_children));
                 }
                 UntypedAst::Sublist(children)
             }
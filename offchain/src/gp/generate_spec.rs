@@ -1,8 +1,12 @@
 use crate::compiler::ast::{UntypedAst, OpCode};
+use anyhow::{anyhow, bail, Result};
 use rand::Rng;
 use rand::prelude::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum InstructionAtom {
     /// A normal opcode, e.g. `Plus`, `Minus`, `Dup`, etc.
     Opcode(OpCode),
@@ -12,10 +16,40 @@ pub enum InstructionAtom {
     // If you want ephemeral floats, booleans, etc., add more variants
 }
 
+/// `[min, max)` of the literals `EphemeralInt` produces by default.
+pub const DEFAULT_EPHEMERAL_RANGE: (i32, i32) = (-30, 30);
+
+fn default_ephemeral_range() -> (i32, i32) {
+    DEFAULT_EPHEMERAL_RANGE
+}
+
 /// A small struct to hold our entire “instruction set.”
-#[derive(Clone, Debug)]
+///
+/// Atoms are drawn uniformly, so listing one several times weights it up.
+/// The set can be saved with a run's results and loaded back to repeat it:
+///
+/// ```
+/// use offchain::compiler::ast::OpCode;
+/// use offchain::gp::generate_spec::InstructionSet;
+///
+/// let mut set = InstructionSet::without(&[OpCode::Noop]);
+/// set.ephemeral_range = (0, 100);
+/// let path = std::env::temp_dir().join("push3_instruction_set.json");
+/// set.save(&path).unwrap();
+/// assert_eq!(InstructionSet::load(&path).unwrap(), set);
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct InstructionSet {
     pub atoms: Vec<InstructionAtom>,
+    /// `[min, max)` of the literals an `EphemeralInt` atom produces.
+    #[serde(default = "default_ephemeral_range")]
+    pub ephemeral_range: (i32, i32),
+}
+
+impl Default for InstructionSet {
+    fn default() -> Self {
+        Self::new_default()
+    }
 }
 
 impl InstructionSet {
@@ -67,9 +101,120 @@ impl InstructionSet {
                 // Ephemeral constants
                 EphemeralInt,
             ],
+            ephemeral_range: DEFAULT_EPHEMERAL_RANGE,
+        }
+    }
+
+    /// `new_default` minus every opcode in `opcodes`, e.g. `&[OpCode::Noop]`
+    /// to stop the search spending points on no-ops.
+    ///
+    /// ```
+    /// use offchain::compiler::ast::{OpCode, UntypedAst};
+    /// use offchain::gp::generate_spec::{random_code, InstructionSet};
+    /// use rand::{rngs::StdRng, SeedableRng};
+    ///
+    /// let excluded = [OpCode::Noop, OpCode::Sin, OpCode::Cos, OpCode::ConstPi, OpCode::ConstE];
+    /// let set = InstructionSet::without(&excluded);
+    /// let mut rng = StdRng::seed_from_u64(7);
+    /// for _ in 0..200 {
+    ///     let prog = random_code(&mut rng, &set, 30);
+    ///     assert!(prog.iter_preorder().all(|(node, _)| match node {
+    ///         UntypedAst::Instruction(op) => !excluded.contains(op),
+    ///         _ => true,
+    ///     }));
+    /// }
+    /// ```
+    pub fn without(opcodes: &[OpCode]) -> Self {
+        let mut set = Self::new_default();
+        set.atoms.retain(|atom| match atom {
+            InstructionAtom::Opcode(op) => !opcodes.contains(op),
+            InstructionAtom::EphemeralInt => true,
+        });
+        set
+    }
+
+    /// Integer arithmetic and stack shuffling only: no `Noop`, comparisons,
+    /// trig, float constants or conditionals.
+    ///
+    /// ```
+    /// use offchain::compiler::ast::{OpCode, UntypedAst};
+    /// use offchain::gp::generate_spec::{random_code, InstructionSet};
+    /// use rand::{rngs::StdRng, SeedableRng};
+    ///
+    /// let set = InstructionSet::arithmetic_only();
+    /// let mut rng = StdRng::seed_from_u64(11);
+    /// for _ in 0..200 {
+    ///     let prog = random_code(&mut rng, &set, 30);
+    ///     assert!(prog.iter_preorder().all(|(node, _)| !matches!(
+    ///         node,
+    ///         UntypedAst::Instruction(OpCode::Noop | OpCode::Sin | OpCode::IfElse)
+    ///     )));
+    /// }
+    /// ```
+    pub fn arithmetic_only() -> Self {
+        use InstructionAtom::*;
+        use OpCode::*;
+
+        Self {
+            atoms: vec![
+                Opcode(Plus),
+                Opcode(Minus),
+                Opcode(Mult),
+                Opcode(Mod),
+                Opcode(Abs),
+                Opcode(Dup),
+                Opcode(Pop),
+                EphemeralInt,
+            ],
+            ephemeral_range: DEFAULT_EPHEMERAL_RANGE,
         }
     }
-    
+
+    /// Write this set as JSON, e.g. next to a run's results.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+            .map_err(|e| anyhow!("Failed to write instruction set {}: {}", path.display(), e))
+    }
+
+    /// Read a set written by [`InstructionSet::save`], rejecting one that
+    /// can't generate programs (see [`InstructionSet::validate`]).
+    ///
+    /// ```
+    /// use offchain::gp::generate_spec::InstructionSet;
+    ///
+    /// let path = std::env::temp_dir().join("push3_bad_instruction_set.json");
+    /// std::fs::write(&path, r#"{ "atoms": ["EphemeralInt"], "ephemeral_range": [5, 5] }"#).unwrap();
+    /// let err = InstructionSet::load(&path).unwrap_err();
+    /// assert!(err.to_string().contains("ephemeral_range"));
+    ///
+    /// std::fs::write(&path, r#"{ "atoms": [] }"#).unwrap();
+    /// let err = InstructionSet::load(&path).unwrap_err();
+    /// assert!(err.to_string().contains("no atoms"));
+    /// ```
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read instruction set {}: {}", path.display(), e))?;
+        let set: Self = serde_json::from_str(&json).map_err(|e| anyhow!("Failed to parse instruction set: {}", e))?;
+        set.validate()
+            .map_err(|e| anyhow!("Invalid instruction set {}: {}", path.display(), e))?;
+        Ok(set)
+    }
+
+    /// Fails if there are no atoms to draw from, or `ephemeral_range` is empty.
+    pub fn validate(&self) -> Result<()> {
+        if self.atoms.is_empty() {
+            bail!("the instruction set has no atoms");
+        }
+        let (min, max) = self.ephemeral_range;
+        if min >= max {
+            bail!("ephemeral_range [{}, {}) is empty", min, max);
+        }
+        Ok(())
+    }
+
     /// Pick a random atom from this set.
     /// If it's `EphemeralInt`, we produce `UntypedAst::IntLiteral(...)`.
     /// If it's `Opcode(...)`, we produce `UntypedAst::Instruction(...)`.
@@ -78,8 +223,9 @@ impl InstructionSet {
         match &self.atoms[idx] {
             InstructionAtom::Opcode(op) => UntypedAst::Instruction(op.clone()),
             InstructionAtom::EphemeralInt => {
-                // For ephemeral int, produce a random literal in some range
-                let val = rng.gen_range(-30..30);
+                // For ephemeral int, produce a random literal in `ephemeral_range`
+                let (min, max) = self.ephemeral_range;
+                let val = rng.gen_range(min..max);
                 UntypedAst::IntLiteral(val)
             }
         }
@@ -95,8 +241,34 @@ pub fn ranmdom_code_fixed(rng: &mut impl Rng, max_points: usize) -> UntypedAst {
 /// 1) Choose actual_points in [1..=max_points].
 /// 2) Return the result of random_code_with_size.
 pub fn random_code(rng: &mut impl Rng, instr_set: &InstructionSet, max_points: usize) -> UntypedAst {
+    random_code_with_max_children(rng, instr_set, max_points, usize::MAX)
+}
+
+/// `random_code` with no sublist holding more than `max_children` children
+/// (`usize::MAX` means no limit, as in `random_code`).
+///
+/// ```
+/// use offchain::gp::generate_spec::{random_code_with_max_children, InstructionSet};
+/// use offchain::gp::metrics::ast_metrics;
+/// use offchain::helpers::rng::seeded_rng;
+///
+/// let set = InstructionSet::new_default();
+/// let mut rng = seeded_rng(3);
+/// for max_children in 1..=5 {
+///     for _ in 0..50 {
+///         let ast = random_code_with_max_children(&mut rng, &set, 40, max_children);
+///         assert!(ast_metrics(&ast).max_arity <= max_children);
+///     }
+/// }
+/// ```
+pub fn random_code_with_max_children(
+    rng: &mut impl Rng,
+    instr_set: &InstructionSet,
+    max_points: usize,
+    max_children: usize,
+) -> UntypedAst {
     let actual_points = rng.gen_range(1..=max_points);
-    random_code_with_size(rng, instr_set, actual_points)
+    random_code_sized(rng, instr_set, actual_points, max_children.max(1))
 }
 
 /// The main logic: 
@@ -109,6 +281,15 @@ pub fn random_code_with_size(
     rng: &mut impl Rng,
     instr_set: &InstructionSet,
     points: usize,
+) -> UntypedAst {
+    random_code_sized(rng, instr_set, points, usize::MAX)
+}
+
+fn random_code_sized(
+    rng: &mut impl Rng,
+    instr_set: &InstructionSet,
+    points: usize,
+    max_children: usize,
 ) -> UntypedAst {
     use UntypedAst::*;
 
@@ -119,14 +300,14 @@ pub fn random_code_with_size(
     } 
 
     // If `points > 1`, let's produce a Sublist
-    // We'll break (points - 1) into sub-points via `decompose`
-    let subpoints_list = decompose(rng, points - 1, points - 1);
+    // We'll break (points - 1) into at most `max_children` sub-points via `decompose`
+    let subpoints_list = decompose(rng, points - 1, (points - 1).min(max_children));
     // subpoints_list is e.g. [2, 3, 5] and sums to (points-1)
 
     // Then for each "subpoints", we do random_code_with_size 
     let mut sub_asts: Vec<UntypedAst> = subpoints_list
         .into_iter()
-        .map(|sp| random_code_with_size(rng, instr_set, sp))
+        .map(|sp| random_code_sized(rng, instr_set, sp, max_children))
         .collect();
 
     // The spec says "Return a list containing the results, in random order"
@@ -151,3 +332,116 @@ fn decompose(rng: &mut impl Rng, number: usize, max_parts: usize) -> Vec<usize>
     result.append(&mut remainder);
     result
 }
+
+// ---------------------------------------------------------------------
+// Depth-based initialization: "grow", "full", and ramped half-and-half
+// ---------------------------------------------------------------------
+
+/// Max number of children per Sublist in `grow_tree` / `full_tree`.
+const MAX_TREE_ARITY: usize = 3;
+
+/// "Grow" method: every node may be an atom or a Sublist, so branches
+/// end at different depths. The result has depth `<= max_depth`
+/// (depth as in `mutation::get_depth`: an atom is depth 0).
+pub fn grow_tree(rng: &mut impl Rng, instr_set: &InstructionSet, max_depth: usize) -> UntypedAst {
+    grow_tree_with(rng, instr_set, max_depth, MAX_TREE_ARITY)
+}
+
+/// `grow_tree` with sublists of `1..=max_children` children.
+pub fn grow_tree_with(
+    rng: &mut impl Rng,
+    instr_set: &InstructionSet,
+    max_depth: usize,
+    max_children: usize,
+) -> UntypedAst {
+    // At depth 0 we must stop; otherwise flip a coin between atom and Sublist
+    if max_depth == 0 || rng.gen_bool(0.5) {
+        return instr_set.random_atom_as_ast(rng);
+    }
+
+    let arity = rng.gen_range(1..=max_children.max(1));
+    let children = (0..arity)
+        .map(|_| grow_tree_with(rng, instr_set, max_depth - 1, max_children))
+        .collect();
+    UntypedAst::Sublist(children)
+}
+
+/// "Full" method: every branch is a Sublist until exactly `depth`,
+/// where only atoms are placed. The result has depth exactly `depth`.
+pub fn full_tree(rng: &mut impl Rng, instr_set: &InstructionSet, depth: usize) -> UntypedAst {
+    full_tree_with(rng, instr_set, depth, MAX_TREE_ARITY)
+}
+
+/// `full_tree` with sublists of `1..=max_children` children.
+pub fn full_tree_with(
+    rng: &mut impl Rng,
+    instr_set: &InstructionSet,
+    depth: usize,
+    max_children: usize,
+) -> UntypedAst {
+    if depth == 0 {
+        return instr_set.random_atom_as_ast(rng);
+    }
+
+    let arity = rng.gen_range(1..=max_children.max(1));
+    let children = (0..arity)
+        .map(|_| full_tree_with(rng, instr_set, depth - 1, max_children))
+        .collect();
+    UntypedAst::Sublist(children)
+}
+
+/// Ramped half-and-half: depths are cycled over `min_depth..=max_depth`,
+/// and for each depth we alternate between `full_tree` and `grow_tree`.
+/// This gives a population with a spread of both shapes and depths.
+pub fn ramped_half_and_half(
+    rng: &mut impl Rng,
+    instr_set: &InstructionSet,
+    pop_size: usize,
+    min_depth: usize,
+    max_depth: usize,
+) -> Vec<UntypedAst> {
+    let max_depth = max_depth.max(min_depth);
+    let num_depths = max_depth - min_depth + 1;
+
+    (0..pop_size)
+        .map(|i| {
+            let depth = min_depth + (i / 2) % num_depths;
+            if i % 2 == 0 {
+                full_tree(rng, instr_set, depth)
+            } else {
+                grow_tree(rng, instr_set, depth)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gp::mutation::get_depth;
+    use crate::helpers::rng::seeded_rng;
+
+    #[test]
+    fn tree_depths_follow_their_limits() {
+        let instr_set = InstructionSet::new_default();
+        let mut rng = seeded_rng(11);
+
+        for depth in 0..=5 {
+            for _ in 0..20 {
+                assert_eq!(get_depth(&full_tree(&mut rng, &instr_set, depth)), depth);
+                assert!(get_depth(&grow_tree(&mut rng, &instr_set, depth)) <= depth);
+            }
+        }
+
+        let (min_depth, max_depth) = (2, 6);
+        let population = ramped_half_and_half(&mut rng, &instr_set, 40, min_depth, max_depth);
+        assert_eq!(population.len(), 40);
+        let depths: Vec<usize> = population.iter().map(get_depth).collect();
+        assert!(depths.iter().all(|&d| d <= max_depth));
+        for depth in min_depth..=max_depth {
+            assert!(depths.contains(&depth), "no tree of depth {} in {:?}", depth, depths);
+        }
+        // The full half starts at min_depth, so nothing there is shallower
+        assert!(depths.iter().step_by(2).all(|&d| d >= min_depth));
+    }
+}
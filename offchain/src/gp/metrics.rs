@@ -0,0 +1,155 @@
+//! src/gp/metrics.rs
+//!
+//! Shape metrics of a program, beyond the plain node count of
+//! `mutation::get_subtree_size`, so parsimony pressure can target depth,
+//! breadth or on-chain cost as well as size, and the size distribution of a
+//! whole population.
+
+use crate::compiler::ast::UntypedAst;
+use crate::gp::population_management::Individual;
+
+/// Shape of one AST. A leaf is an atom or an empty sublist; an internal node is
+/// a non-empty sublist (so `leaves + internal_nodes == total_nodes`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AstMetrics {
+    pub total_nodes: usize,
+    pub leaves: usize,
+    pub internal_nodes: usize,
+    /// A lone atom has depth 0; a sublist is one deeper than its deepest child
+    /// (as `mutation::get_depth`).
+    pub max_depth: usize,
+    /// Most children of any one sublist (0 if there is no sublist).
+    pub max_arity: usize,
+}
+
+/// One number out of [`AstMetrics`], for size penalties.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeMeasure {
+    #[default]
+    TotalNodes,
+    Leaves,
+    InternalNodes,
+    MaxDepth,
+    MaxArity,
+}
+
+impl AstMetrics {
+    pub fn get(&self, measure: SizeMeasure) -> usize {
+        match measure {
+            SizeMeasure::TotalNodes => self.total_nodes,
+            SizeMeasure::Leaves => self.leaves,
+            SizeMeasure::InternalNodes => self.internal_nodes,
+            SizeMeasure::MaxDepth => self.max_depth,
+            SizeMeasure::MaxArity => self.max_arity,
+        }
+    }
+}
+
+/// Measure `ast` in one traversal.
+///
+/// ```
+/// use offchain::gp::metrics::{ast_metrics, AstMetrics};
+/// use offchain::push3;
+///
+/// assert_eq!(
+///     ast_metrics(&push3!((1 (2 3 (4)) + ()))),
+///     AstMetrics { total_nodes: 9, leaves: 6, internal_nodes: 3, max_depth: 3, max_arity: 4 }
+/// );
+/// assert_eq!(
+///     ast_metrics(&push3!(DUP)),
+///     AstMetrics { total_nodes: 1, leaves: 1, internal_nodes: 0, max_depth: 0, max_arity: 0 }
+/// );
+/// ```
+pub fn ast_metrics(ast: &UntypedAst) -> AstMetrics {
+    let mut metrics = AstMetrics::default();
+
+    for (node, path) in ast.iter_preorder() {
+        metrics.total_nodes += 1;
+        match node {
+            UntypedAst::Sublist(children) if !children.is_empty() => {
+                metrics.internal_nodes += 1;
+                metrics.max_arity = metrics.max_arity.max(children.len());
+                // Its children sit one level below `path.len()`
+                metrics.max_depth = metrics.max_depth.max(path.len() + 1);
+            }
+            UntypedAst::Sublist(_) => {
+                metrics.leaves += 1;
+                metrics.max_depth = metrics.max_depth.max(path.len() + 1);
+            }
+            UntypedAst::IntLiteral(_) | UntypedAst::Instruction(_) => metrics.leaves += 1,
+        }
+    }
+
+    metrics
+}
+
+/// Node count with each instruction counted as its
+/// [`OpCode::weight`](crate::compiler::ast::OpCode::weight);
+/// literals and sublists count 1.
+///
+/// ```
+/// use offchain::gp::metrics::{ast_metrics, weighted_size};
+/// use offchain::push3;
+///
+/// let cheap = push3!((2 3 + DUP *));
+/// assert_eq!(weighted_size(&cheap), ast_metrics(&cheap).total_nodes as f64);
+///
+/// // Same node count, but POW and SQRT weigh 4 each
+/// let costly = push3!((2 3 POW DUP SQRT));
+/// assert_eq!(ast_metrics(&costly).total_nodes, 6);
+/// assert_eq!(weighted_size(&costly), 12.0);
+/// ```
+pub fn weighted_size(ast: &UntypedAst) -> f64 {
+    ast.iter_preorder()
+        .map(|(node, _)| match node {
+            UntypedAst::Instruction(op) => op.weight(),
+            UntypedAst::IntLiteral(_) | UntypedAst::Sublist(_) => 1.0,
+        })
+        .sum()
+}
+
+/// Program sizes (`Individual::size`) of a population, bucketed by
+/// `bucket_width` (at least 1): `(first size of the bucket, count)` for every
+/// bucket from the smallest program's to the largest's, empty ones included,
+/// so gaps between clusters show.
+///
+/// ```
+/// use offchain::gp::metrics::size_histogram;
+/// use offchain::gp::population_management::Individual;
+/// use offchain::push3;
+///
+/// // Sizes 4, 4, 5 and 14: two clusters the mean of 6.75 hides
+/// let population: Vec<Individual> = [
+///     push3!((1 2 +)),
+///     push3!((3 DUP *)),
+///     push3!((1 (2) +)),
+///     push3!((1 (2 3 (4 5 6) 7) (8 9) +)),
+/// ]
+/// .into_iter()
+/// .map(|ast| Individual::new(ast, 0.0))
+/// .collect();
+///
+/// assert_eq!(size_histogram(&population, 5), vec![(0, 2), (5, 1), (10, 1)]);
+/// assert_eq!(size_histogram(&population, 4), vec![(4, 3), (8, 0), (12, 1)]);
+/// assert!(size_histogram(&[], 5).is_empty());
+/// ```
+pub fn size_histogram(population: &[Individual], bucket_width: usize) -> Vec<(usize, usize)> {
+    let width = bucket_width.max(1);
+    let (Some(min), Some(max)) = (
+        population.iter().map(|ind| ind.size).min(),
+        population.iter().map(|ind| ind.size).max(),
+    ) else {
+        return Vec::new();
+    };
+
+    let first = min / width;
+    let mut counts = vec![0; max / width - first + 1];
+    for individual in population {
+        counts[individual.size / width - first] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| ((first + i) * width, count))
+        .collect()
+}
@@ -0,0 +1,83 @@
+//! src/gp/minimize.rs
+//!
+//! Post-run minimization: shrink a finished program as far as possible without
+//! letting its fitness drop below a target, in the spirit of delta debugging.
+//!
+//! Candidate edits, all of which strictly reduce the node count:
+//! - delete one node (and its subtree) from its parent sublist,
+//! - splice a nested sublist's children into its parent, i.e. `(a (b c) d)` => `(a b c d)`,
+//! - hoist a subtree to replace the whole program.
+//!
+//! The search is greedy: the first candidate that keeps the fitness is taken and
+//! the scan restarts from the new program, until no candidate is accepted. The
+//! result is therefore locally minimal with respect to these edits, not globally.
+
+use crate::compiler::ast::UntypedAst;
+use crate::gp::mutation::{enum_nodes_dfs, get_subtree, get_subtree_size, replace_subtree};
+use crate::runner::revm_runner::EvmRunner;
+
+/// Return the smallest program found whose fitness stays at or above
+/// `target_fitness`. If `ast` itself is below the target it is returned unchanged.
+///
+/// `fitness_fn` decides what "equivalent" means: to keep outputs identical on a
+/// sample set, score exact matches on those samples and pass the perfect score as
+/// `target_fitness`.
+pub fn minimize<F>(
+    ast: &UntypedAst,
+    runner: &mut EvmRunner,
+    mut fitness_fn: F,
+    target_fitness: f64,
+) -> UntypedAst
+where
+    F: FnMut(&mut EvmRunner, &UntypedAst) -> f64,
+{
+    let mut current = ast.clone();
+    if fitness_fn(runner, &current) < target_fitness {
+        return current;
+    }
+
+    'outer: loop {
+        let current_size = get_subtree_size(&current);
+        for candidate in shrink_candidates(&current) {
+            if get_subtree_size(&candidate) >= current_size {
+                continue;
+            }
+            if fitness_fn(runner, &candidate) >= target_fitness {
+                current = candidate;
+                continue 'outer;
+            }
+        }
+        return current;
+    }
+}
+
+/// All single-edit reductions of `ast`, larger removals first (nodes are visited
+/// in pre-order, so a subtree is tried before its descendants).
+pub fn shrink_candidates(ast: &UntypedAst) -> Vec<UntypedAst> {
+    let mut candidates = Vec::new();
+
+    for path in enum_nodes_dfs(ast).into_iter().skip(1) {
+        let node = get_subtree(ast, &path);
+        let (index, parent_path) = path.split_last().expect("non-root path");
+        let UntypedAst::Sublist(siblings) = get_subtree(ast, parent_path) else {
+            continue;
+        };
+
+        // Delete the node
+        let mut without = siblings.clone();
+        without.remove(*index);
+        candidates.push(replace_subtree(ast, parent_path, UntypedAst::Sublist(without)));
+
+        // Splice a nested sublist into its parent
+        if let UntypedAst::Sublist(children) = &node {
+            let mut spliced = siblings;
+            spliced.splice(*index..=*index, children.iter().cloned());
+            candidates.push(replace_subtree(ast, parent_path, UntypedAst::Sublist(spliced)));
+        }
+
+        // Hoist the node to the root
+        candidates.push(node);
+    }
+
+    candidates
+}
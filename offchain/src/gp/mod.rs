@@ -1,6 +1,14 @@
 pub mod population;
 pub mod generate;
 pub mod generate_spec;
+pub mod fitness;
+pub mod dataset;
 pub mod mutation;
 pub mod local_mutation;
+pub mod metrics;
+pub mod minimize;
+pub mod analysis;
 pub mod population_management;
+pub mod engine;
+pub mod stats_logger;
+pub mod validation;
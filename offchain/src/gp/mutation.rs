@@ -1,6 +1,8 @@
+use anyhow::{bail, Result};
 use rand::Rng;
 use crate::compiler::ast::{UntypedAst, OpCode};
-use crate::gp::generate_spec::ranmdom_code_fixed; // or push3-based generator
+use crate::compiler::stack_effect::{stack_effect, StackEffect};
+use crate::gp::generate_spec::{random_code_with_max_children, InstructionSet};
 
 /// A "path" is a list of indices leading from the root to a child.
 /// For example, [] is the root, [0] is the root's first child, [0,1] is that child's second child, etc.
@@ -8,35 +10,121 @@ pub type Path = Vec<usize>;
 
 /// Enumerate all nodes in a DFS order (root first).
 /// For each node, we store the `Path` (list of child indices).
+/// This is [`UntypedAst::iter_preorder`] without the nodes.
 pub fn enum_nodes_dfs(ast: &UntypedAst) -> Vec<Path> {
-    let mut paths = Vec::new();
-    dfs_helper(ast, &mut paths, &mut vec![]);
-    paths
+    ast.iter_preorder().map(|(_, path)| path).collect()
 }
 
-/// Recursive helper: `current_path` is a stack we push child indices onto.
-/// `paths` is the global list of final results.
-fn dfs_helper(ast: &UntypedAst, paths: &mut Vec<Path>, current_path: &mut Vec<usize>) {
-    // Record the current path as a valid node
-    paths.push(current_path.clone());
+/// Paths of the nodes for which `predicate` holds, in DFS order (root first),
+/// e.g. to aim an operator at literals only. `get_subtree(ast, path)` of each
+/// result satisfies `predicate`.
+///
+/// ```
+/// use offchain::compiler::ast::UntypedAst;
+/// use offchain::gp::mutation::{find_nodes, get_subtree_size};
+/// use offchain::push3;
+///
+/// let ast = push3!((1 (2 DUP (3 +)) *));
+/// let literals = find_nodes(&ast, |node| matches!(node, UntypedAst::IntLiteral(_)));
+/// assert_eq!(literals, [vec![0], vec![1, 0], vec![1, 2, 0_usize]]);
+///
+/// let big_sublists = find_nodes(&ast, |node| {
+///     matches!(node, UntypedAst::Sublist(_)) && get_subtree_size(node) > 3
+/// });
+/// assert_eq!(big_sublists, [vec![], vec![1_usize]]);
+/// assert!(find_nodes(&ast, |node| get_subtree_size(node) > 100).is_empty());
+/// ```
+pub fn find_nodes(ast: &UntypedAst, predicate: impl Fn(&UntypedAst) -> bool) -> Vec<Path> {
+    ast.iter_preorder()
+        .filter(|(node, _)| predicate(node))
+        .map(|(_, path)| path)
+        .collect()
+}
+
+/// Constant mutation: perturb one integer literal, picked uniformly, with
+/// `int_mutation`; nothing else changes. A program without literals comes
+/// back unchanged.
+///
+/// ```
+/// use offchain::compiler::ast::UntypedAst;
+/// use offchain::gp::mutation::{constant_mutate, IntMutation};
+/// use offchain::helpers::rng::seeded_rng;
+/// use offchain::push3;
+///
+/// let ast = push3!((10 DUP (20 +)));
+/// let mut rng = seeded_rng(1);
+/// let mutated = constant_mutate(&ast, &mut rng, &IntMutation::default());
+/// let UntypedAst::Sublist(children) = &mutated else { unreachable!() };
+/// assert_eq!(children[1], UntypedAst::Instruction(offchain::compiler::ast::OpCode::Dup));
+/// assert_ne!(mutated, ast);
+/// assert_eq!(constant_mutate(&push3!((DUP *)), &mut rng, &IntMutation::default()), push3!((DUP *)));
+/// ```
+pub fn constant_mutate(
+    original: &UntypedAst,
+    rng: &mut impl Rng,
+    int_mutation: &IntMutation,
+) -> UntypedAst {
+    let literals = find_nodes(original, |node| matches!(node, UntypedAst::IntLiteral(_)));
+    if literals.is_empty() {
+        return original.clone();
+    }
+    let path = &literals[rng.gen_range(0..literals.len())];
+    match get_subtree(original, path) {
+        UntypedAst::IntLiteral(val) => {
+            replace_subtree(original, path, UntypedAst::IntLiteral(int_mutation.mutate(val, rng)))
+        }
+        _ => unreachable!("find_nodes only returned literals"),
+    }
+}
 
+/// Add Gaussian noise with standard deviation `std` to every integer literal
+/// (rounded, saturating at the `i32` bounds), leaving the structure and
+/// every instruction as they are: fine-tuning of constants, where
+/// [`constant_mutate`] only touches one.
+///
+/// ```
+/// use offchain::compiler::ast::UntypedAst;
+/// use offchain::gp::mutation::perturb_constants;
+/// use offchain::helpers::rng::seeded_rng;
+/// use offchain::push3;
+///
+/// // Same shape and opcodes, literals replaced by a marker
+/// fn skeleton(ast: &UntypedAst) -> UntypedAst {
+///     match ast {
+///         UntypedAst::IntLiteral(_) => UntypedAst::IntLiteral(0),
+///         UntypedAst::Instruction(op) => UntypedAst::Instruction(op.clone()),
+///         UntypedAst::Sublist(children) => UntypedAst::Sublist(children.iter().map(skeleton).collect()),
+///     }
+/// }
+///
+/// let ast = push3!((100 DUP (200 + (300)) *));
+/// let mut rng = seeded_rng(5);
+/// let mut changed = false;
+/// for _ in 0..20 {
+///     let nudged = perturb_constants(&ast, &mut rng, 10.0);
+///     assert_eq!(skeleton(&nudged), skeleton(&ast));
+///     changed |= nudged != ast;
+/// }
+/// assert!(changed);
+/// assert_eq!(perturb_constants(&ast, &mut rng, 0.0), ast);
+/// ```
+pub fn perturb_constants(ast: &UntypedAst, rng: &mut impl Rng, std: f64) -> UntypedAst {
     match ast {
-        UntypedAst::Sublist(children) => {
-            // For each child, push its index, recurse, then pop
-            for (i, child) in children.iter().enumerate() {
-                current_path.push(i);
-                dfs_helper(child, paths, current_path);
-                current_path.pop();
-            }
+        UntypedAst::IntLiteral(val) => {
+            let noisy = (*val as f64 + std * standard_normal(rng)).round();
+            // `as` saturates at the i32 bounds
+            UntypedAst::IntLiteral(noisy as i32)
         }
-        UntypedAst::IntLiteral(_) | UntypedAst::Instruction(_) => {
-            // Leaf node => no children
+        UntypedAst::Instruction(_) => ast.clone(),
+        UntypedAst::Sublist(children) => {
+            UntypedAst::Sublist(children.iter().map(|child| perturb_constants(child, rng, std)).collect())
         }
     }
 }
 
 /// Replace the node at `path` in `original` with `replacement`, returning a new AST.
 /// If `path` is empty => we replace the root entirely.
+/// An invalid path is not reported; see [`try_replace_subtree`] for that.
 pub fn replace_subtree(
     original: &UntypedAst,
     path: &[usize],
@@ -64,12 +152,127 @@ pub fn replace_subtree(
     }
 }
 
+/// Why a path does not name a node, as reported by [`try_get_subtree`] and
+/// [`try_replace_subtree`]. `depth` is the position in the path of the offending index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    /// `path[depth]` is `index`, but that sublist only has `len` children.
+    IndexOutOfRange { depth: usize, index: usize, len: usize },
+    /// `path[depth]` is `index`, but the node reached so far is a leaf.
+    DescendsIntoLeaf { depth: usize, index: usize },
+}
+
+impl std::fmt::Display for PathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathError::IndexOutOfRange { depth, index, len } => write!(
+                f,
+                "path[{depth}] = {index} is out of range for a sublist of {len} children"
+            ),
+            PathError::DescendsIntoLeaf { depth, index } => {
+                write!(f, "path[{depth}] = {index} descends into a leaf")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// Strict version of [`get_subtree`]: the node at `path`, or why there is none.
+///
+/// ```
+/// use offchain::gp::mutation::{try_get_subtree, PathError};
+/// use offchain::push3;
+///
+/// let ast = push3!((3 (DUP *)));
+/// assert_eq!(try_get_subtree(&ast, &[1, 0]), Ok(push3!(DUP)));
+/// assert_eq!(
+///     try_get_subtree(&ast, &[2]),
+///     Err(PathError::IndexOutOfRange { depth: 0, index: 2, len: 2 })
+/// );
+/// assert_eq!(
+///     try_get_subtree(&ast, &[0, 0]),
+///     Err(PathError::DescendsIntoLeaf { depth: 1, index: 0 })
+/// );
+/// ```
+pub fn try_get_subtree(original: &UntypedAst, path: &[usize]) -> Result<UntypedAst, PathError> {
+    let mut node = original;
+    for (depth, &index) in path.iter().enumerate() {
+        node = child_at(node, depth, index)?;
+    }
+    Ok(node.clone())
+}
+
+/// Strict version of [`replace_subtree`]: fails instead of returning `original`
+/// unchanged when `path` does not name a node.
+pub fn try_replace_subtree(
+    original: &UntypedAst,
+    path: &[usize],
+    replacement: UntypedAst,
+) -> Result<UntypedAst, PathError> {
+    try_replace_at(original, path, 0, replacement)
+}
+
+/// Recursive helper for [`try_replace_subtree`]; `depth` is how much of the
+/// path has been consumed, for error reporting.
+fn try_replace_at(
+    node: &UntypedAst,
+    path: &[usize],
+    depth: usize,
+    replacement: UntypedAst,
+) -> Result<UntypedAst, PathError> {
+    let Some((&index, tail)) = path.split_first() else {
+        return Ok(replacement);
+    };
+    let new_child = try_replace_at(child_at(node, depth, index)?, tail, depth + 1, replacement)?;
+    let UntypedAst::Sublist(children) = node else {
+        unreachable!("child_at only succeeds on sublists");
+    };
+    let mut new_children = children.clone();
+    new_children[index] = new_child;
+    Ok(UntypedAst::Sublist(new_children))
+}
+
+/// Child `index` of `node`, where `index` is `path[depth]`.
+fn child_at(node: &UntypedAst, depth: usize, index: usize) -> Result<&UntypedAst, PathError> {
+    match node {
+        UntypedAst::Sublist(children) => children
+            .get(index)
+            .ok_or(PathError::IndexOutOfRange { depth, index, len: children.len() }),
+        UntypedAst::IntLiteral(_) | UntypedAst::Instruction(_) => {
+            Err(PathError::DescendsIntoLeaf { depth, index })
+        }
+    }
+}
+
 /// Produces a new AST by choosing exactly one node in `original` at random
 /// (by enumerating them), and replacing it with a new random subtree.
 pub fn mutate_by_index(
     original: &UntypedAst,
     rng: &mut impl Rng,
     max_points: usize,
+) -> UntypedAst {
+    mutate_by_index_with_max_children(original, rng, max_points, usize::MAX)
+}
+
+/// `mutate_by_index` where the new subtree has no sublist with more than
+/// `max_children` children.
+pub fn mutate_by_index_with_max_children(
+    original: &UntypedAst,
+    rng: &mut impl Rng,
+    max_points: usize,
+    max_children: usize,
+) -> UntypedAst {
+    mutate_by_index_in_set(original, rng, &InstructionSet::new_default(), max_points, max_children)
+}
+
+/// `mutate_by_index_with_max_children` drawing the new subtree from `instr_set`.
+pub fn mutate_by_index_in_set(
+    original: &UntypedAst,
+    rng: &mut impl Rng,
+    instr_set: &InstructionSet,
+    max_points: usize,
+    max_children: usize,
 ) -> UntypedAst {
     // 1) Enumerate all nodes => get a vector of `Path`
     let all_paths = enum_nodes_dfs(original);
@@ -77,8 +280,8 @@ pub fn mutate_by_index(
     let idx = rng.gen_range(0..all_paths.len());
     let chosen_path = &all_paths[idx];
 
-    // 2) Generate a new subtree with our "fixed" random code generator
-    let new_subtree = ranmdom_code_fixed(rng, max_points);
+    // 2) Generate a new subtree from the instruction set
+    let new_subtree = random_code_with_max_children(rng, instr_set, max_points, max_children);
 
     // 3) Replace the subtree at `chosen_path` in `original` with `new_subtree`
     replace_subtree(original, chosen_path, new_subtree)
@@ -87,6 +290,7 @@ pub fn mutate_by_index(
 /// Return the subtree of `original` at `path`, 
 /// cloning it as a `UntypedAst`. 
 /// If `path` is empty => returns the entire `original`.
+/// An invalid path is not reported; see [`try_get_subtree`] for that.
 pub fn get_subtree(original: &UntypedAst, path: &[usize]) -> UntypedAst {
     if path.is_empty() {
         // The entire AST is the subtree
@@ -116,14 +320,37 @@ pub fn get_subtree(original: &UntypedAst, path: &[usize]) -> UntypedAst {
 /// 2) We swap those subtrees,
 /// 3) Return the two new ASTs.
 ///
-/// Example usage:
-/// ```rust
-/// let (childA, childB) = crossover_by_index(&parentA, &parentB, &mut rng);
+/// With `max_size`, a child larger than that is rejected and replaced by its
+/// parent (`a` for the first child, `b` for the second).
+///
+/// ```
+/// use offchain::gp::mutation::{crossover_by_index, get_subtree_size};
+/// use offchain::helpers::rng::seeded_rng;
+/// use offchain::push3;
+///
+/// let a = push3!((1 2 3));
+/// let b = push3!(((4 5 6) (7 8 9)));
+/// let mut rng = seeded_rng(0);
+/// let mut grew = false;
+/// for _ in 0..100 {
+///     // Replay the same draws without the cap
+///     let (uncapped, _) = crossover_by_index(&a, &b, &mut rng.clone(), None);
+///     let (capped, _) = crossover_by_index(&a, &b, &mut rng, Some(4));
+///     if get_subtree_size(&uncapped) > 4 {
+///         // `a` has 4 nodes: a bigger child is rejected and `a` comes back
+///         grew = true;
+///         assert_eq!(capped, a);
+///     } else {
+///         assert_eq!(capped, uncapped);
+///     }
+/// }
+/// assert!(grew);
 /// ```
 pub fn crossover_by_index(
     a: &UntypedAst,
     b: &UntypedAst,
     rng: &mut impl Rng,
+    max_size: Option<usize>,
 ) -> (UntypedAst, UntypedAst) {
     // 1) enumerate nodes in a
     let paths_a = enum_nodes_dfs(a);
@@ -143,7 +370,7 @@ pub fn crossover_by_index(
     let new_a = replace_subtree(a, chosen_a, subtree_b);
     let new_b = replace_subtree(b, chosen_b, subtree_a);
 
-    (new_a, new_b)
+    reject_oversize((new_a, new_b), (a, b), max_size)
 }
 
 /// Point mutation: Make small changes to individual nodes
@@ -153,13 +380,126 @@ pub fn point_mutate(
     rng: &mut impl Rng,
     mutation_rate: f64,
 ) -> UntypedAst {
-    point_mutate_recursive(original, rng, mutation_rate)
+    point_mutate_with(original, rng, mutation_rate, &IntMutation::default())
+}
+
+/// `point_mutate` with explicit control over how integer literals change.
+pub fn point_mutate_with(
+    original: &UntypedAst,
+    rng: &mut impl Rng,
+    mutation_rate: f64,
+    ints: &IntMutation,
+) -> UntypedAst {
+    point_mutate_with_max_children(original, rng, mutation_rate, ints, POINT_MUTATION_MAX_CHILDREN)
+}
+
+/// How many children point mutation lets a sublist grow to by default.
+pub const POINT_MUTATION_MAX_CHILDREN: usize = 8;
+
+/// `point_mutate_with`, where a structural mutation only adds a child to a
+/// sublist with fewer than `max_children` children.
+pub fn point_mutate_with_max_children(
+    original: &UntypedAst,
+    rng: &mut impl Rng,
+    mutation_rate: f64,
+    ints: &IntMutation,
+    max_children: usize,
+) -> UntypedAst {
+    point_mutate_recursive(original, rng, mutation_rate, ints, max_children)
+}
+
+/// How `point_mutate` changes an integer literal: it adds a Gaussian delta
+/// whose standard deviation grows with the magnitude of the value, then
+/// reflects the result back into `[min, max]`.
+///
+/// Reflecting instead of saturating means a value sitting on a bound can still
+/// move away from it (and one next to it does not pile up on it).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntMutation {
+    /// Standard deviation as a fraction of `|value|`.
+    pub relative_std: f64,
+    /// Lower bound on the standard deviation, so small values still move.
+    pub min_std: f64,
+    pub min: i32,
+    pub max: i32,
+}
+
+impl Default for IntMutation {
+    fn default() -> Self {
+        Self { relative_std: 0.1, min_std: 3.0, min: i32::MIN, max: i32::MAX }
+    }
+}
+
+impl IntMutation {
+    pub fn validate(&self) -> Result<()> {
+        if self.min > self.max {
+            bail!("Empty int mutation range [{}, {}]", self.min, self.max);
+        }
+        if !(self.relative_std >= 0.0 && self.min_std >= 0.0) {
+            bail!("Int mutation standard deviations must be non-negative");
+        }
+        Ok(())
+    }
+
+    /// A mutated copy of `val`, always different from it unless the range
+    /// holds a single value.
+    ///
+    /// ```
+    /// use offchain::gp::mutation::IntMutation;
+    /// use offchain::helpers::rng::seeded_rng;
+    ///
+    /// let ints = IntMutation { min: -100, max: 100, ..IntMutation::default() };
+    /// let mut rng = seeded_rng(7);
+    /// let near_bound: Vec<i32> = (0..200).map(|_| ints.mutate(98, &mut rng)).collect();
+    /// assert!(near_bound.iter().all(|v| (-100..=100).contains(v) && *v != 98));
+    /// assert!(near_bound.iter().any(|&v| v > 98));
+    /// assert!(near_bound.iter().any(|&v| v < 98));
+    ///
+    /// // Unlike saturation, a value on the bound is not stuck there
+    /// let at_max = IntMutation::default().mutate(i32::MAX, &mut rng);
+    /// assert!(at_max < i32::MAX);
+    /// ```
+    pub fn mutate(&self, val: i32, rng: &mut impl Rng) -> i32 {
+        let (min, max) = (self.min as i64, self.max as i64);
+        if min >= max {
+            return self.min;
+        }
+        let std = self.min_std.max(self.relative_std * (val as f64).abs());
+        loop {
+            let mut delta = (standard_normal(rng) * std).round() as i64;
+            if delta == 0 {
+                delta = if rng.gen::<bool>() { 1 } else { -1 };
+            }
+
+            let mut new_val = val as i64 + delta;
+            if new_val > max {
+                new_val = max - (new_val - max);
+            }
+            if new_val < min {
+                new_val = min + (min - new_val);
+            }
+            let new_val = new_val.clamp(min, max);
+            // A reflection can land back on `val`; draw again
+            if new_val != val as i64 {
+                return new_val as i32;
+            }
+        }
+    }
+}
+
+/// A standard normal sample (Box-Muller).
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    let u1: f64 = 1.0 - rng.gen::<f64>(); // (0, 1], keeps ln finite
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
 }
 
 fn point_mutate_recursive(
     ast: &UntypedAst,
     rng: &mut impl Rng,
     mutation_rate: f64,
+    ints: &IntMutation,
+    max_children: usize,
 ) -> UntypedAst {
     // Decide if this node gets mutated
     let should_mutate = rng.gen::<f64>() < mutation_rate;
@@ -167,10 +507,7 @@ fn point_mutate_recursive(
     match ast {
         UntypedAst::IntLiteral(val) => {
             if should_mutate {
-                // Small integer mutations: add/subtract small random value
-                let delta = rng.gen_range(-5..=5);
-                let new_val = val.saturating_add(delta);
-                UntypedAst::IntLiteral(new_val)
+                UntypedAst::IntLiteral(ints.mutate(*val, rng))
             } else {
                 ast.clone()
             }
@@ -196,7 +533,7 @@ fn point_mutate_recursive(
             // Recursively apply point mutation to children
             let new_children: Vec<UntypedAst> = children
                 .iter()
-                .map(|child| point_mutate_recursive(child, rng, mutation_rate))
+                .map(|child| point_mutate_recursive(child, rng, mutation_rate, ints, max_children))
                 .collect();
             
             // Possibly add/remove children (structural mutation)
@@ -207,7 +544,7 @@ fn point_mutate_recursive(
                     // Remove a random child (10% chance)
                     let remove_idx = rng.gen_range(0..modified_children.len());
                     modified_children.remove(remove_idx);
-                } else if modified_children.len() < 8 {
+                } else if modified_children.len() < max_children {
                     // Add a simple random child (20% chance)
                     let new_child = if rng.gen::<bool>() {
                         UntypedAst::IntLiteral(rng.gen_range(-10..=10))
@@ -232,11 +569,177 @@ fn point_mutate_recursive(
     }
 }
 
-/// Size-aware crossover: prefer swapping subtrees of similar sizes
+/// Pick an index into `paths` (the nodes of `ast`), choosing an internal node
+/// (a non-empty sublist) with probability `internal_bias` and a leaf otherwise,
+/// uniformly within each class. Koza's rule is `internal_bias = 0.9`.
+/// If one class is empty, the other is used.
+///
+/// ```
+/// use offchain::gp::mutation::{enum_nodes_dfs, get_subtree, select_node_biased};
+/// use offchain::helpers::rng::seeded_rng;
+/// use offchain::compiler::ast::UntypedAst;
+/// use offchain::push3;
+///
+/// let ast = push3!((1 (2 3) (4 (5 6)))); // 4 internal nodes, 6 leaves
+/// let paths = enum_nodes_dfs(&ast);
+/// let mut rng = seeded_rng(1);
+/// let trials = 10_000;
+/// let internal = (0..trials)
+///     .map(|_| select_node_biased(&paths, &ast, 0.9, &mut rng))
+///     .filter(|&i| matches!(get_subtree(&ast, &paths[i]), UntypedAst::Sublist(_)))
+///     .count();
+/// let rate = internal as f64 / trials as f64;
+/// assert!((0.88..0.92).contains(&rate), "rate = {}", rate);
+/// ```
+pub fn select_node_biased(
+    paths: &[Path],
+    ast: &UntypedAst,
+    internal_bias: f64,
+    rng: &mut impl Rng,
+) -> usize {
+    let (internal, leaves): (Vec<usize>, Vec<usize>) = (0..paths.len()).partition(|&i| {
+        matches!(get_subtree(ast, &paths[i]), UntypedAst::Sublist(children) if !children.is_empty())
+    });
+
+    let pool = if leaves.is_empty() || (!internal.is_empty() && rng.gen::<f64>() < internal_bias) {
+        &internal
+    } else {
+        &leaves
+    };
+    pool[rng.gen_range(0..pool.len())]
+}
+
+/// Subtree crossover with crossover points chosen by [`select_node_biased`]
+/// instead of uniformly, as in `crossover_by_index` (including `max_size`).
+pub fn crossover_koza(
+    a: &UntypedAst,
+    b: &UntypedAst,
+    internal_bias: f64,
+    rng: &mut impl Rng,
+    max_size: Option<usize>,
+) -> (UntypedAst, UntypedAst) {
+    let paths_a = enum_nodes_dfs(a);
+    let chosen_a = &paths_a[select_node_biased(&paths_a, a, internal_bias, rng)];
+    let paths_b = enum_nodes_dfs(b);
+    let chosen_b = &paths_b[select_node_biased(&paths_b, b, internal_bias, rng)];
+
+    let subtree_a = get_subtree(a, chosen_a);
+    let subtree_b = get_subtree(b, chosen_b);
+
+    let new_a = replace_subtree(a, chosen_a, subtree_b);
+    let new_b = replace_subtree(b, chosen_b, subtree_a);
+
+    reject_oversize((new_a, new_b), (a, b), max_size)
+}
+
+/// How many crossover points in `a` [`typed_crossover`] tries before giving up.
+pub const TYPED_CROSSOVER_TRIES: usize = 10;
+
+/// Subtree crossover that only swaps subtrees with the same net stack effect
+/// (see `StackEffect::same_net_effect`), so an int-producing region is never
+/// replaced by a bool-producing one. A point in `a` is picked at random and
+/// its partner uniformly among the compatible points of `b`; after
+/// [`TYPED_CROSSOVER_TRIES`] points in `a` without a partner, the parents are
+/// returned unchanged. `max_size` is as in [`crossover_by_index`].
+///
+/// ```
+/// use offchain::compiler::stack_effect::stack_effect;
+/// use offchain::gp::mutation::typed_crossover;
+/// use offchain::helpers::rng::seeded_rng;
+/// use offchain::push3;
+///
+/// let a = push3!((1 2 + (3 4 <) BOOL_TO_INT *));
+/// let b = push3!((5 DUP (6 7 ==) (8 ABS) POP));
+/// let mut rng = seeded_rng(3);
+/// for _ in 0..200 {
+///     let (child_a, child_b) = typed_crossover(&a, &b, &mut rng, None);
+///     assert!(stack_effect(&child_a).same_net_effect(&stack_effect(&a)));
+///     assert!(stack_effect(&child_b).same_net_effect(&stack_effect(&b)));
+/// }
+/// ```
+pub fn typed_crossover(
+    a: &UntypedAst,
+    b: &UntypedAst,
+    rng: &mut impl Rng,
+    max_size: Option<usize>,
+) -> (UntypedAst, UntypedAst) {
+    let paths_a = enum_nodes_dfs(a);
+    let paths_b = enum_nodes_dfs(b);
+    let effects_b: Vec<StackEffect> =
+        paths_b.iter().map(|path| stack_effect(&get_subtree(b, path))).collect();
+
+    for _ in 0..TYPED_CROSSOVER_TRIES {
+        let chosen_a = &paths_a[rng.gen_range(0..paths_a.len())];
+        let subtree_a = get_subtree(a, chosen_a);
+        let effect_a = stack_effect(&subtree_a);
+
+        let compatible: Vec<usize> = (0..paths_b.len())
+            .filter(|&j| effects_b[j].same_net_effect(&effect_a))
+            .collect();
+        if compatible.is_empty() {
+            continue;
+        }
+        let chosen_b = &paths_b[compatible[rng.gen_range(0..compatible.len())]];
+        let subtree_b = get_subtree(b, chosen_b);
+
+        let new_a = replace_subtree(a, chosen_a, subtree_b);
+        let new_b = replace_subtree(b, chosen_b, subtree_a);
+        return reject_oversize((new_a, new_b), (a, b), max_size);
+    }
+
+    (a.clone(), b.clone())
+}
+
+/// Self-crossover: copy the subtree at one node of `ast` over another node of
+/// the same tree, so a useful piece (e.g. `(DUP *)`) can be repeated. The
+/// source is picked uniformly; the destination uniformly among the other nodes
+/// that keep the result within `max_size` nodes. Returns `ast` unchanged if no
+/// destination fits.
+///
+/// ```
+/// use offchain::gp::mutation::{get_subtree_size, self_graft_mutate};
+/// use offchain::helpers::rng::seeded_rng;
+/// use offchain::push3;
+///
+/// let ast = push3!((2 (DUP *)));
+/// let mut rng = seeded_rng(5);
+/// let grafts: Vec<_> = (0..200).map(|_| self_graft_mutate(&ast, &mut rng, 10)).collect();
+/// // The squaring block copied over the literal
+/// assert!(grafts.contains(&push3!(((DUP *) (DUP *)))));
+/// assert!(grafts.iter().all(|g| get_subtree_size(g) <= 10));
+///
+/// // At the program's own size, nothing may grow
+/// for _ in 0..200 {
+///     assert!(get_subtree_size(&self_graft_mutate(&ast, &mut rng, 5)) <= 5);
+/// }
+/// ```
+pub fn self_graft_mutate(ast: &UntypedAst, rng: &mut impl Rng, max_size: usize) -> UntypedAst {
+    let nodes: Vec<(Path, usize)> = ast
+        .iter_preorder()
+        .map(|(node, path)| (path, get_subtree_size(node)))
+        .collect();
+    let total = get_subtree_size(ast);
+
+    let (source, source_size) = &nodes[rng.gen_range(0..nodes.len())];
+    let targets: Vec<&Path> = nodes
+        .iter()
+        .filter(|(path, size)| path != source && total - size + source_size <= max_size)
+        .map(|(path, _)| path)
+        .collect();
+    if targets.is_empty() {
+        return ast.clone();
+    }
+    let target = targets[rng.gen_range(0..targets.len())];
+    replace_subtree(ast, target, get_subtree(ast, source))
+}
+
+/// Size-aware crossover: prefer swapping subtrees of similar sizes.
+/// Oversize children are rejected as in [`crossover_by_index`].
 pub fn size_aware_crossover(
     a: &UntypedAst,
     b: &UntypedAst,
     rng: &mut impl Rng,
+    max_size: Option<usize>,
 ) -> (UntypedAst, UntypedAst) {
     let paths_a = enum_nodes_dfs(a);
     let paths_b = enum_nodes_dfs(b);
@@ -282,7 +785,22 @@ pub fn size_aware_crossover(
     let new_a = replace_subtree(a, chosen_a, subtree_b);
     let new_b = replace_subtree(b, chosen_b, subtree_a);
     
-    (new_a, new_b)
+    reject_oversize((new_a, new_b), (a, b), max_size)
+}
+
+/// Replace each child larger than `max_size` with the corresponding parent.
+fn reject_oversize(
+    (child_a, child_b): (UntypedAst, UntypedAst),
+    (a, b): (&UntypedAst, &UntypedAst),
+    max_size: Option<usize>,
+) -> (UntypedAst, UntypedAst) {
+    let Some(max_size) = max_size else {
+        return (child_a, child_b);
+    };
+    let keep = |child: UntypedAst, parent: &UntypedAst| {
+        if get_subtree_size(&child) > max_size { parent.clone() } else { child }
+    };
+    (keep(child_a, a), keep(child_b, b))
 }
 
 /// Calculate the size (number of nodes) of an AST
@@ -295,12 +813,49 @@ pub fn get_subtree_size(ast: &UntypedAst) -> usize {
     }
 }
 
+/// Calculate the depth of an AST.
+/// A single atom has depth 0; a Sublist is one deeper than its deepest child.
+pub fn get_depth(ast: &UntypedAst) -> usize {
+    match ast {
+        UntypedAst::IntLiteral(_) | UntypedAst::Instruction(_) => 0,
+        UntypedAst::Sublist(children) => {
+            1 + children.iter().map(get_depth).max().unwrap_or(0)
+        }
+    }
+}
+
 /// Size-limited mutation: prevents excessive growth
 pub fn size_limited_mutate(
     original: &UntypedAst,
     rng: &mut impl Rng,
     max_points: usize,
     max_size: usize,
+) -> UntypedAst {
+    size_limited_mutate_with_max_children(original, rng, max_points, max_size, usize::MAX)
+}
+
+/// `size_limited_mutate` that never builds a sublist with more than
+/// `max_children` children (the point-mutation fallback also stays within
+/// [`POINT_MUTATION_MAX_CHILDREN`]).
+pub fn size_limited_mutate_with_max_children(
+    original: &UntypedAst,
+    rng: &mut impl Rng,
+    max_points: usize,
+    max_size: usize,
+    max_children: usize,
+) -> UntypedAst {
+    let instr_set = InstructionSet::new_default();
+    size_limited_mutate_in_set(original, rng, &instr_set, max_points, max_size, max_children)
+}
+
+/// `size_limited_mutate_with_max_children` drawing new subtrees from `instr_set`.
+pub fn size_limited_mutate_in_set(
+    original: &UntypedAst,
+    rng: &mut impl Rng,
+    instr_set: &InstructionSet,
+    max_points: usize,
+    max_size: usize,
+    max_children: usize,
 ) -> UntypedAst {
     let current_size = get_subtree_size(original);
     
@@ -310,14 +865,15 @@ pub fn size_limited_mutate(
     }
     
     // Otherwise, use regular mutation but check size
-    let mutated = mutate_by_index(original, rng, max_points);
+    let mutated = mutate_by_index_in_set(original, rng, instr_set, max_points, max_children);
     let new_size = get_subtree_size(&mutated);
     
     if new_size <= max_size {
         mutated
     } else {
         // Mutation made it too large, try point mutation instead
-        point_mutate(original, rng, 0.1)
+        let cap = max_children.min(POINT_MUTATION_MAX_CHILDREN);
+        point_mutate_with_max_children(original, rng, 0.1, &IntMutation::default(), cap)
     }
 }
 
@@ -1,31 +1,70 @@
 // Population management improvements for genetic programming
 
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
 use rand::Rng;
-use crate::compiler::ast::UntypedAst;
+use serde::{Deserialize, Serialize};
+use crate::compiler::ast::{ast_fingerprint, canonicalize, OpCode, UntypedAst};
+use crate::gp::fitness::{evaluate_ast_on_inputs, FailurePolicy};
+use crate::gp::generate_spec::ranmdom_code_fixed;
 use crate::gp::mutation::get_subtree_size;
+use crate::runner::revm_runner::EvmRunner;
 // use std::collections::HashMap; // Not needed for current implementation
 
 /// Diversity metrics and population analysis
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PopulationStats {
     pub avg_fitness: f64,
     pub fitness_std: f64,
+    #[serde(default)]
+    pub min_fitness: f64,
+    /// The middle fitness, or the mean of the two middle ones for an even
+    /// population size.
+    #[serde(default)]
+    pub median_fitness: f64,
+    #[serde(default)]
+    pub max_fitness: f64,
     pub avg_size: f64,
     pub size_std: f64,
     pub diversity_score: f64,
     pub stagnation_count: u32,
+    /// Fraction of individuals that ran on every sample (`!reverted`).
+    #[serde(default)]
+    pub success_rate: f64,
+    /// Mean of `mean_error` over the individuals that ran on every sample
+    /// (0 if there are none).
+    #[serde(default)]
+    pub avg_error: f64,
 }
 
 /// Individual with extended information for population management
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Individual {
     pub ast: UntypedAst,
     pub fitness: f64,
     pub size: usize,
     pub age: u32,              // How many generations this individual has survived
     pub novelty_score: f64,    // How different this individual is from others
+    /// Outputs on a probe set (see [`behavior_vector`]), cached once computed.
+    #[serde(default)]
+    pub behavior: Option<Vec<f64>>,
+    /// True if the program failed (reverted / empty stack) on some sample.
+    /// Only known if created with [`Individual::from_errors`]; `false` otherwise.
+    #[serde(default)]
+    pub reverted: bool,
+    /// Mean absolute error over the samples the program ran on
+    /// ([`FAILED_ERROR`] if it ran on none, 0 if unknown).
+    #[serde(default)]
+    pub mean_error: f64,
 }
 
+/// `mean_error` of a program that failed on every sample.
+pub const FAILED_ERROR: f64 = f64::MAX;
+
 impl Individual {
     pub fn new(ast: UntypedAst, fitness: f64) -> Self {
         let size = get_subtree_size(&ast);
@@ -35,13 +74,44 @@ impl Individual {
             size,
             age: 0,
             novelty_score: 0.0,
+            behavior: None,
+            reverted: false,
+            mean_error: 0.0,
         }
     }
+
+    /// An individual scored from its per-sample errors (as from
+    /// `fitness::evaluate_errors`): fitness is `graded_fitness_from_errors`,
+    /// and `reverted` / `mean_error` are filled in.
+    pub fn from_errors(ast: UntypedAst, errors: &[Option<i128>]) -> Self {
+        Self::from_errors_with(ast, errors, &FailurePolicy::default())
+    }
+
+    /// [`Individual::from_errors`] with failed samples credited by `policy`
+    /// (see `FailurePolicy::score_errors`).
+    pub fn from_errors_with(ast: UntypedAst, errors: &[Option<i128>], policy: &FailurePolicy) -> Self {
+        let mut individual = Self::new(ast, policy.score_errors(errors));
+        individual.record_errors(errors);
+        individual
+    }
+
+    /// Set `reverted` and `mean_error` from per-sample errors.
+    pub fn record_errors(&mut self, errors: &[Option<i128>]) {
+        let ran: Vec<f64> = errors.iter().flatten().map(|&e| e as f64).collect();
+        self.reverted = ran.len() < errors.len();
+        self.mean_error = if ran.is_empty() {
+            FAILED_ERROR
+        } else {
+            ran.iter().sum::<f64>() / ran.len() as f64
+        };
+    }
 }
 
-/// Calculate structural diversity between two ASTs
+/// Calculate structural diversity between two ASTs. Both are compared in
+/// their [`canonicalize`]d form, so swapping the operands of a commutative
+/// op doesn't count as a difference.
 pub fn structural_distance(a: &UntypedAst, b: &UntypedAst) -> f64 {
-    structural_distance_recursive(a, b, 1.0)
+    structural_distance_recursive(&canonicalize(a), &canonicalize(b), 1.0)
 }
 
 fn structural_distance_recursive(a: &UntypedAst, b: &UntypedAst, weight: f64) -> f64 {
@@ -87,8 +157,247 @@ fn structural_distance_recursive(a: &UntypedAst, b: &UntypedAst, weight: f64) ->
     }
 }
 
+/// Which distance between programs novelty and fitness sharing are based on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceMetric {
+    /// [`structural_distance`]: weighted, position-by-position comparison.
+    #[default]
+    Structural,
+    /// [`tree_edit_distance`] of the canonical forms, as an `f64`. Note that
+    /// it counts edits, so thresholds like the sharing `sigma` need to be on
+    /// that scale (≥ 1) rather than the 0..1-ish scale of `Structural`.
+    TreeEdit,
+}
+
+impl DistanceMetric {
+    pub fn distance(self, a: &UntypedAst, b: &UntypedAst) -> f64 {
+        match self {
+            DistanceMetric::Structural => structural_distance(a, b),
+            DistanceMetric::TreeEdit => {
+                tree_edit_distance(&canonicalize(a), &canonicalize(b)) as f64
+            }
+        }
+    }
+}
+
+/// Whether the selection helpers treat a higher or a lower `fitness` as
+/// better. Graded fitness is maximized; an error such as MSE stored as the
+/// fitness is minimized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Objective {
+    #[default]
+    Maximize,
+    Minimize,
+}
+
+impl Objective {
+    /// `fitness` mapped so that higher is always better.
+    ///
+    /// ```
+    /// use offchain::gp::population_management::Objective;
+    ///
+    /// assert!(Objective::Maximize.score(2.0) > Objective::Maximize.score(1.0));
+    /// assert!(Objective::Minimize.score(1.0) > Objective::Minimize.score(2.0));
+    /// ```
+    pub fn score(self, fitness: f64) -> f64 {
+        match self {
+            Objective::Maximize => fitness,
+            Objective::Minimize => -fitness,
+        }
+    }
+
+    /// Whether fitness `a` is strictly better than `b`.
+    pub fn is_better(self, a: f64, b: f64) -> bool {
+        self.score(a) > self.score(b)
+    }
+}
+
+/// Best-first order of two individuals under `objective`: any NaN fitness
+/// last, then by fitness, then the smaller program first, then by
+/// [`ast_fingerprint`] (and the AST itself, for equivalent programs) so that
+/// the order never depends on where the individuals started. Never panics.
+pub fn compare_individuals(a: &Individual, b: &Individual, objective: Objective) -> Ordering {
+    a.fitness
+        .is_nan()
+        .cmp(&b.fitness.is_nan())
+        .then_with(|| objective.score(b.fitness).total_cmp(&objective.score(a.fitness)))
+        .then_with(|| a.size.cmp(&b.size))
+        .then_with(|| ast_fingerprint(&a.ast).cmp(&ast_fingerprint(&b.ast)))
+        .then_with(|| a.ast.cmp(&b.ast))
+}
+
+/// Sort best first (highest fitness), deterministically; see
+/// [`compare_individuals`]. Use this rather than a `partial_cmp().unwrap()`
+/// sort, which panics on the NaN fitness sharing can produce.
+///
+/// ```
+/// use offchain::gp::population_management::{sort_population, Individual};
+/// use offchain::push3;
+///
+/// let mut population = vec![
+///     Individual::new(push3!((1 2 + 3 +)), 5.0),
+///     Individual::new(push3!((9 9 -)), f64::NAN),
+///     Individual::new(push3!((1 2 +)), 5.0),
+///     Individual::new(push3!((DUP *)), 8.0),
+///     Individual::new(push3!((4 1 -)), 5.0),
+/// ];
+/// sort_population(&mut population);
+///
+/// let fitness: Vec<f64> = population.iter().map(|i| i.fitness).collect();
+/// assert_eq!(fitness[..4], [8.0, 5.0, 5.0, 5.0]);
+/// assert!(fitness[4].is_nan());
+/// // Equal fitness: smaller first
+/// assert_eq!(population[3].ast, push3!((1 2 + 3 +)));
+///
+/// // Ties of fitness and size come out the same whatever the input order
+/// let mut reversed: Vec<Individual> = population.iter().rev().cloned().collect();
+/// sort_population(&mut reversed);
+/// let order = |p: &[Individual]| p.iter().map(|i| i.ast.to_string()).collect::<Vec<_>>();
+/// assert_eq!(order(&reversed)[..4], order(&population)[..4]);
+/// ```
+pub fn sort_population(population: &mut [Individual]) {
+    sort_population_with(population, Objective::Maximize)
+}
+
+/// [`sort_population`] for the given objective.
+pub fn sort_population_with(population: &mut [Individual], objective: Objective) {
+    population.sort_by(|a, b| compare_individuals(a, b, objective));
+}
+
+/// Zhang-Shasha tree edit distance: the minimum number of node insertions,
+/// deletions and relabelings turning `a` into `b`. Every node is labeled by
+/// its literal value, its opcode, or "sublist"; each edit costs 1.
+///
+/// ```
+/// use offchain::compiler::ast::{parse_string_to_sexpr, sexpr_to_untyped};
+/// use offchain::gp::population_management::tree_edit_distance;
+///
+/// let parse = |s| sexpr_to_untyped(&parse_string_to_sexpr(s).unwrap()).unwrap();
+///
+/// assert_eq!(tree_edit_distance(&parse("(1 2 +)"), &parse("(1 2 +)")), 0);
+/// // relabel `+` => `-`
+/// assert_eq!(tree_edit_distance(&parse("(1 2 +)"), &parse("(1 2 -)")), 1);
+/// // delete `2`
+/// assert_eq!(tree_edit_distance(&parse("(1 2 +)"), &parse("(1 +)")), 1);
+/// // insert a sublist node around `1 2` (its children stay in place)
+/// assert_eq!(tree_edit_distance(&parse("(1 2 +)"), &parse("((1 2) +)")), 1);
+/// // relabel `3` => `4` and delete `DUP`
+/// assert_eq!(tree_edit_distance(&parse("((3 DUP) *)"), &parse("((4) *)")), 2);
+/// ```
+pub fn tree_edit_distance(a: &UntypedAst, b: &UntypedAst) -> usize {
+    let t1 = PostorderTree::new(a);
+    let t2 = PostorderTree::new(b);
+    let (n, m) = (t1.nodes.len(), t2.nodes.len());
+
+    // tree_dist[i][j]: distance between the subtrees rooted at i and j
+    let mut tree_dist = vec![vec![0usize; m]; n];
+    for &i in &t1.keyroots {
+        for &j in &t2.keyroots {
+            forest_distance(&t1, &t2, i, j, &mut tree_dist);
+        }
+    }
+    tree_dist[n - 1][m - 1]
+}
+
+/// A tree flattened in postorder, as Zhang-Shasha needs it.
+struct PostorderTree<'a> {
+    nodes: Vec<&'a UntypedAst>,
+    /// Index of the leftmost leaf of the subtree rooted at each node.
+    leftmost: Vec<usize>,
+    /// Nodes with no later node sharing their leftmost leaf, ascending.
+    keyroots: Vec<usize>,
+}
+
+impl<'a> PostorderTree<'a> {
+    fn new(root: &'a UntypedAst) -> Self {
+        let mut tree = Self { nodes: Vec::new(), leftmost: Vec::new(), keyroots: Vec::new() };
+        tree.walk(root);
+
+        let mut seen = HashSet::new();
+        for i in (0..tree.nodes.len()).rev() {
+            if seen.insert(tree.leftmost[i]) {
+                tree.keyroots.push(i);
+            }
+        }
+        tree.keyroots.reverse();
+        tree
+    }
+
+    fn walk(&mut self, ast: &'a UntypedAst) -> usize {
+        let mut first_leaf = None;
+        if let UntypedAst::Sublist(children) = ast {
+            for child in children {
+                let idx = self.walk(child);
+                first_leaf.get_or_insert(self.leftmost[idx]);
+            }
+        }
+        let idx = self.nodes.len();
+        self.nodes.push(ast);
+        self.leftmost.push(first_leaf.unwrap_or(idx));
+        idx
+    }
+}
+
+/// Relabeling cost: 0 for the same literal / opcode / both sublists, else 1.
+fn relabel_cost(a: &UntypedAst, b: &UntypedAst) -> usize {
+    let same = match (a, b) {
+        (UntypedAst::Sublist(_), UntypedAst::Sublist(_)) => true,
+        _ => a == b,
+    };
+    if same { 0 } else { 1 }
+}
+
+/// Fill `tree_dist` for all subtree pairs sharing the leftmost leaves of keyroots `i` and `j`.
+fn forest_distance(
+    t1: &PostorderTree,
+    t2: &PostorderTree,
+    i: usize,
+    j: usize,
+    tree_dist: &mut [Vec<usize>],
+) {
+    let (li, lj) = (t1.leftmost[i], t2.leftmost[j]);
+    let (rows, cols) = (i - li + 2, j - lj + 2);
+
+    // forest[p][q]: distance between forests t1[li..li+p] and t2[lj..lj+q]
+    let mut forest = vec![vec![0usize; cols]; rows];
+    for p in 1..rows {
+        forest[p][0] = forest[p - 1][0] + 1;
+    }
+    for q in 1..cols {
+        forest[0][q] = forest[0][q - 1] + 1;
+    }
+
+    for p in 1..rows {
+        for q in 1..cols {
+            let (x, y) = (li + p - 1, lj + q - 1);
+            let delete = forest[p - 1][q] + 1;
+            let insert = forest[p][q - 1] + 1;
+
+            if t1.leftmost[x] == li && t2.leftmost[y] == lj {
+                // Both forests are whole trees
+                let relabel = forest[p - 1][q - 1] + relabel_cost(t1.nodes[x], t2.nodes[y]);
+                forest[p][q] = delete.min(insert).min(relabel);
+                tree_dist[x][y] = forest[p][q];
+            } else {
+                let (pp, qq) = (t1.leftmost[x] - li, t2.leftmost[y] - lj);
+                let subtree = forest[pp][qq] + tree_dist[x][y];
+                forest[p][q] = delete.min(insert).min(subtree);
+            }
+        }
+    }
+}
+
 /// Calculate novelty score for an individual relative to population
 pub fn calculate_novelty_score(individual: &UntypedAst, population: &[Individual]) -> f64 {
+    calculate_novelty_score_with(individual, population, DistanceMetric::Structural)
+}
+
+/// [`calculate_novelty_score`] using the given distance metric.
+pub fn calculate_novelty_score_with(
+    individual: &UntypedAst,
+    population: &[Individual],
+    metric: DistanceMetric,
+) -> f64 {
     if population.len() < 2 {
         return 1.0; // High novelty if population is small
     }
@@ -98,10 +407,10 @@ pub fn calculate_novelty_score(individual: &UntypedAst, population: &[Individual
     
     let mut distances: Vec<f64> = population
         .iter()
-        .map(|other| structural_distance(individual, &other.ast))
+        .map(|other| metric.distance(individual, &other.ast))
         .collect();
     
-    distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    distances.sort_by(f64::total_cmp);
     
     // Average distance to k nearest neighbors
     let avg_distance: f64 = distances.iter().take(k).sum::<f64>() / k as f64;
@@ -110,11 +419,134 @@ pub fn calculate_novelty_score(individual: &UntypedAst, population: &[Individual
     avg_distance
 }
 
-/// Advanced elitism that preserves diversity
+/// Behavior entry for a probe the program failed on (revert or empty stack).
+/// Finite so it survives a JSON round trip in checkpoints.
+pub const FAILED_BEHAVIOR: f64 = f64::MAX;
+
+/// Characterize a program by what it computes: its output on each probe
+/// (inputs pushed as in [`evaluate_ast_on_inputs`]), or [`FAILED_BEHAVIOR`].
+pub fn behavior_vector(runner: &mut EvmRunner, ast: &UntypedAst, probes: &[Vec<i128>]) -> Vec<f64> {
+    probes
+        .iter()
+        .map(|inputs| match evaluate_ast_on_inputs(runner, ast, inputs) {
+            Some(v) => v as f64,
+            None => FAILED_BEHAVIOR,
+        })
+        .collect()
+}
+
+/// Compute and cache `behavior` for every individual that doesn't have one yet.
+/// All individuals must be characterized with the same `probes`.
+pub fn cache_behaviors(population: &mut [Individual], runner: &mut EvmRunner, probes: &[Vec<i128>]) {
+    for individual in population.iter_mut().filter(|ind| ind.behavior.is_none()) {
+        individual.behavior = Some(behavior_vector(runner, &individual.ast, probes));
+    }
+}
+
+/// Distance in behavior space: the mean over probes of `d / (1 + d)` where `d`
+/// is the absolute output difference. Bounded to [0, 1], so a single wild
+/// output (or a failure) can't dominate.
+pub fn behavioral_distance(a: &[f64], b: &[f64]) -> f64 {
+    if a.is_empty() {
+        return 0.0;
+    }
+    let total: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| {
+            let diff = (x - y).abs();
+            diff / (1.0 + diff)
+        })
+        .sum();
+    total / a.len() as f64
+}
+
+/// Behavioral novelty: average behavioral distance to the k nearest neighbors
+/// (same k as [`calculate_novelty_score`]). Cached behaviors are used where
+/// present; missing ones are computed on the fly but not stored, so call
+/// [`cache_behaviors`] first when scoring a whole population.
+pub fn calculate_behavioral_novelty(
+    individual: &Individual,
+    population: &[Individual],
+    runner: &mut EvmRunner,
+    probes: &[Vec<i128>],
+) -> f64 {
+    if population.len() < 2 {
+        return 1.0; // High novelty if population is small
+    }
+
+    let own = cached_or_computed_behavior(individual, runner, probes);
+
+    let k = (population.len() / 4).max(5).min(population.len() - 1);
+    let mut distances: Vec<f64> = population
+        .iter()
+        .map(|other| behavioral_distance(&own, &cached_or_computed_behavior(other, runner, probes)))
+        .collect();
+    distances.sort_by(f64::total_cmp);
+
+    distances.iter().take(k).sum::<f64>() / k as f64
+}
+
+fn cached_or_computed_behavior<'a>(
+    individual: &'a Individual,
+    runner: &mut EvmRunner,
+    probes: &[Vec<i128>],
+) -> Cow<'a, [f64]> {
+    match &individual.behavior {
+        Some(b) => Cow::Borrowed(b),
+        None => Cow::Owned(behavior_vector(runner, &individual.ast, probes)),
+    }
+}
+
+/// Advanced elitism that preserves diversity.
+///
+/// Invariant: as long as `population` is non-empty, the first elite is the
+/// fittest individual, unchanged, whatever the diversity bonuses and whatever
+/// the sign of the fitness values (e.g. after fitness sharing).
+///
+/// ```
+/// use offchain::gp::population_management::{diverse_elitism, Individual};
+/// use offchain::push3;
+///
+/// let population = vec![
+///     Individual::new(push3!((1 2 +)), -5.0),
+///     Individual::new(push3!((1 2 -)), -1.0),
+///     Individual::new(push3!((DUP DUP * 7 + 9 -)), -2.0),
+/// ];
+///
+/// let elites = diverse_elitism(&population, 2, 0.1);
+/// assert_eq!(elites[0].fitness, -1.0);
+/// assert_eq!(elites[0].ast, population[1].ast);
+/// ```
 pub fn diverse_elitism(
     population: &[Individual], 
     elite_count: usize,
     min_distance: f64,
+) -> Vec<Individual> {
+    diverse_elitism_with(population, elite_count, min_distance, Objective::Maximize)
+}
+
+/// [`diverse_elitism`] for the given objective: with `Minimize` the first
+/// elite is the individual with the lowest fitness.
+///
+/// ```
+/// use offchain::gp::population_management::{diverse_elitism_with, Individual, Objective};
+/// use offchain::push3;
+///
+/// // Fitness is an MSE
+/// let population = vec![
+///     Individual::new(push3!((1 2 +)), 12.0),
+///     Individual::new(push3!((DUP *)), 0.25),
+///     Individual::new(push3!((DUP DUP * 7 + 9 -)), 3.0),
+/// ];
+/// let elites = diverse_elitism_with(&population, 2, 0.1, Objective::Minimize);
+/// assert_eq!(elites[0].ast, population[1].ast);
+/// ```
+pub fn diverse_elitism_with(
+    population: &[Individual],
+    elite_count: usize,
+    min_distance: f64,
+    objective: Objective,
 ) -> Vec<Individual> {
     if population.is_empty() {
         return Vec::new();
@@ -124,7 +556,7 @@ pub fn diverse_elitism(
     let mut remaining: Vec<Individual> = population.to_vec();
     
     // Sort by fitness (best first)
-    remaining.sort_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap());
+    sort_population_with(&mut remaining, objective);
     
     // Always take the best individual
     elites.push(remaining.remove(0));
@@ -141,14 +573,16 @@ pub fn diverse_elitism(
                 .map(|elite| structural_distance(&candidate.ast, &elite.ast))
                 .fold(f64::INFINITY, f64::min);
             
-            // Score combines fitness and diversity
+            // Score combines fitness and diversity (abs() so a negative
+            // score is still raised, not lowered, by being diverse)
+            let score = objective.score(candidate.fitness);
             let diversity_bonus = if min_dist_to_elites >= min_distance { 
-                candidate.fitness * 0.3  // 30% bonus for being diverse
+                score.abs() * 0.3  // 30% bonus for being diverse
             } else { 
                 0.0 
             };
             
-            let total_score = candidate.fitness + diversity_bonus;
+            let total_score = score + diversity_bonus;
             
             if total_score > best_score {
                 best_score = total_score;
@@ -162,15 +596,45 @@ pub fn diverse_elitism(
     elites
 }
 
-/// Fitness sharing to maintain diversity
+/// Fitness sharing to maintain diversity: each fitness is divided by its niche
+/// count (how crowded its neighbourhood is, at least 1).
+///
+/// Fitness must be non-negative for this to behave: dividing a negative
+/// fitness by the niche count raises it, rewarding crowding instead of
+/// penalizing it. Non-finite fitness (NaN, ±inf) is left as is.
+///
+/// ```
+/// use offchain::gp::population_management::{apply_fitness_sharing, Individual};
+/// use offchain::push3;
+///
+/// let edge_cases = [0.0, -5.0, f64::MAX, f64::MIN_POSITIVE, 1e-320, 3.0];
+/// let mut population: Vec<Individual> =
+///     edge_cases.iter().map(|&f| Individual::new(push3!((1 2 +)), f)).collect();
+/// population.push(Individual::new(push3!((1 2 +)), f64::INFINITY));
+///
+/// apply_fitness_sharing(&mut population, 0.5);
+/// assert!(population[..6].iter().all(|i| i.fitness.is_finite()));
+/// // Seven identical programs: a niche count of 7
+/// assert_eq!(population[5].fitness, 3.0 / 7.0);
+/// assert_eq!(population[6].fitness, f64::INFINITY);
+/// ```
 pub fn apply_fitness_sharing(population: &mut [Individual], sigma: f64) {
+    apply_fitness_sharing_with(population, sigma, DistanceMetric::Structural)
+}
+
+/// [`apply_fitness_sharing`] using the given distance metric; `sigma` is in
+/// that metric's units.
+pub fn apply_fitness_sharing_with(population: &mut [Individual], sigma: f64, metric: DistanceMetric) {
     let n = population.len();
     
     for i in 0..n {
+        if !population[i].fitness.is_finite() {
+            continue;
+        }
         let mut niche_count = 0.0;
         
         for j in 0..n {
-            let distance = structural_distance(&population[i].ast, &population[j].ast);
+            let distance = metric.distance(&population[i].ast, &population[j].ast);
             
             // Sharing function: 1 - (distance/sigma) if distance < sigma, else 0
             let sharing = if distance < sigma {
@@ -182,10 +646,10 @@ pub fn apply_fitness_sharing(population: &mut [Individual], sigma: f64) {
             niche_count += sharing;
         }
         
-        // Adjust fitness by niche count
-        if niche_count > 1.0 {
-            population[i].fitness /= niche_count;
-        }
+        // Adjust fitness by niche count; an individual is always in its own
+        // niche, so anything below 1 (or NaN, from a bad sigma) is rounding
+        let niche_count: f64 = if niche_count.is_finite() { niche_count.max(1.0) } else { 1.0 };
+        population[i].fitness /= niche_count;
     }
 }
 
@@ -196,38 +660,361 @@ pub fn age_population(population: &mut [Individual]) {
     }
 }
 
-/// Select parents using tournament selection with diversity consideration
+/// Split the population into ALPS-style age layers.
+///
+/// `layer_boundaries` must be ascending; layer `i` holds individuals with
+/// `layer_boundaries[i-1] <= age < layer_boundaries[i]`, and one extra last layer
+/// holds everyone at or above the final boundary. So `&[3, 10]` gives three layers:
+/// ages `0..3`, `3..10` and `10..`.
+pub fn age_layers(population: &[Individual], layer_boundaries: &[u32]) -> Vec<Vec<Individual>> {
+    let mut layers: Vec<Vec<Individual>> = vec![Vec::new(); layer_boundaries.len() + 1];
+
+    for individual in population {
+        let layer = layer_boundaries
+            .iter()
+            .position(|&boundary| individual.age < boundary)
+            .unwrap_or(layer_boundaries.len());
+        layers[layer].push(individual.clone());
+    }
+
+    layers
+}
+
+/// Replace every individual older than `max_age` with a fresh random program
+/// (up to `max_points` points), scored with `fitness_fn` and starting at age 0.
+/// Younger individuals are left untouched. Returns how many were replaced.
+pub fn replace_oldest(
+    population: &mut [Individual],
+    max_age: u32,
+    max_points: usize,
+    rng: &mut impl Rng,
+    mut fitness_fn: impl FnMut(&UntypedAst) -> f64,
+) -> usize {
+    let mut replaced = 0;
+
+    for individual in population.iter_mut().filter(|ind| ind.age > max_age) {
+        let fresh_ast = ranmdom_code_fixed(rng, max_points);
+        let fresh_fitness = fitness_fn(&fresh_ast);
+        *individual = Individual::new(fresh_ast, fresh_fitness);
+        replaced += 1;
+    }
+
+    replaced
+}
+
+/// Replace the worst `fraction` of the population (at least one individual
+/// if `fraction > 0`) with fresh random programs of up to `max_points` points,
+/// turned into individuals by `evaluate` (e.g. `|r, ast| Individual::new(ast, score(r, &ast))`),
+/// which is handed `runner` (usually an `EvmRunner`).
+/// The population is left sorted best first with the newcomers at the end.
+/// Returns how many were replaced.
+pub fn restart_worst_fraction<R>(
+    population: &mut [Individual],
+    fraction: f64,
+    max_points: usize,
+    runner: &mut R,
+    evaluate: impl FnMut(&mut R, UntypedAst) -> Individual,
+    rng: &mut impl Rng,
+) -> usize {
+    if population.is_empty() || fraction <= 0.0 {
+        return 0;
+    }
+    let count = ((population.len() as f64 * fraction).ceil() as usize).min(population.len());
+    let keep = population.len() - count;
+    hard_reset(population, keep, max_points, runner, evaluate, rng)
+}
+
+/// Make sure `population` still holds something at least as fit as `best`:
+/// if it doesn't, its least fit individual is replaced by a copy of `best`.
+/// Returns whether a replacement was made. Used by the engine to keep the best
+/// individual across a generation even when diversity maintenance or a restart
+/// removed the elite copy.
+///
+/// ```
+/// use offchain::gp::population_management::{preserve_best, Individual};
+/// use offchain::push3;
+///
+/// let champion = Individual::new(push3!((1 2 +)), 10.0);
+/// let mut population = vec![
+///     Individual::new(push3!((3 4 +)), 4.0),
+///     Individual::new(push3!((5 6 +)), 1.0),
+/// ];
+///
+/// assert!(preserve_best(&mut population, &champion));
+/// assert_eq!(population[1].fitness, 10.0);
+/// assert!(!preserve_best(&mut population, &champion));
+/// ```
+pub fn preserve_best(population: &mut Vec<Individual>, best: &Individual) -> bool {
+    if population.iter().any(|individual| individual.fitness >= best.fitness) {
+        return false;
+    }
+    let worst = population
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| compare_individuals(a, b, Objective::Maximize))
+        .map(|(i, _)| i);
+    match worst {
+        Some(i) => population[i] = best.clone(),
+        None => population.push(best.clone()),
+    }
+    true
+}
+
+/// Keep only the `keep_elites` fittest individuals and replace everyone else
+/// with fresh random programs of up to `max_points` points, turned into
+/// individuals by `evaluate`. The population is left sorted best first
+/// (elites, then newcomers). Returns how many were replaced.
+pub fn hard_reset<R>(
+    population: &mut [Individual],
+    keep_elites: usize,
+    max_points: usize,
+    runner: &mut R,
+    mut evaluate: impl FnMut(&mut R, UntypedAst) -> Individual,
+    rng: &mut impl Rng,
+) -> usize {
+    sort_population(population);
+
+    let keep = keep_elites.min(population.len());
+    for individual in &mut population[keep..] {
+        let fresh_ast = ranmdom_code_fixed(rng, max_points);
+        *individual = evaluate(runner, fresh_ast);
+    }
+
+    population.len() - keep
+}
+
+/// Plain tournament selection: pick `tournament_size` distinct individuals at
+/// random (everyone, if it's at least the population size) and return the
+/// fittest. Novelty is ignored.
+pub fn tournament_selection<'a>(
+    population: &'a [Individual],
+    tournament_size: usize,
+    rng: &mut impl Rng,
+) -> &'a Individual {
+    tournament_selection_with(population, tournament_size, Objective::Maximize, rng)
+}
+
+/// [`tournament_selection`] for the given objective.
+///
+/// ```
+/// use rand::{rngs::StdRng, SeedableRng};
+/// use offchain::gp::population_management::{tournament_selection_with, Individual, Objective};
+/// use offchain::push3;
+///
+/// // Fitness is an error here: lower is better
+/// let population = vec![
+///     Individual::new(push3!((1 2 +)), 40.0),
+///     Individual::new(push3!((DUP *)), 0.5),
+///     Individual::new(push3!((3 -)), 7.0),
+/// ];
+/// let mut rng = StdRng::seed_from_u64(1);
+/// // A tournament as large as the population sees everyone
+/// let winner = tournament_selection_with(&population, 64, Objective::Minimize, &mut rng);
+/// assert_eq!(winner.fitness, 0.5);
+/// let winner = tournament_selection_with(&population, 64, Objective::Maximize, &mut rng);
+/// assert_eq!(winner.fitness, 40.0);
+///
+/// // A NaN fitness (e.g. from fitness sharing) never wins, whichever the objective
+/// let with_nan = vec![Individual::new(push3!((DUP)), f64::NAN), Individual::new(push3!((4)), 1.0)];
+/// for objective in [Objective::Minimize, Objective::Maximize] {
+///     assert_eq!(tournament_selection_with(&with_nan, 64, objective, &mut rng).fitness, 1.0);
+/// }
+/// ```
+pub fn tournament_selection_with<'a>(
+    population: &'a [Individual],
+    tournament_size: usize,
+    objective: Objective,
+    rng: &mut impl Rng,
+) -> &'a Individual {
+    let tournament = draw_tournament(population, tournament_size, rng);
+
+    tournament
+        .into_iter()
+        .min_by(|a, b| compare_individuals(a, b, objective))
+        .unwrap()
+}
+
+/// `size` distinct individuals (at least one, at most all of them), in random order.
+fn draw_tournament<'a>(population: &'a [Individual], size: usize, rng: &mut impl Rng) -> Vec<&'a Individual> {
+    let size = size.clamp(1, population.len());
+    rand::seq::index::sample(rng, population.len(), size)
+        .into_vec()
+        .into_iter()
+        .map(|i| &population[i])
+        .collect()
+}
+
+/// Select parents using tournament selection with diversity consideration.
+/// With `diversity_weight == 0.0` this is exactly [`tournament_selection`].
 pub fn diverse_tournament_selection<'a>(
     population: &'a [Individual],
     tournament_size: usize,
     diversity_weight: f64,
     rng: &mut impl Rng,
 ) -> &'a Individual {
-    let tournament: Vec<&Individual> = (0..tournament_size)
-        .map(|_| &population[rng.gen_range(0..population.len())])
-        .collect();
+    diverse_tournament_selection_with(population, tournament_size, diversity_weight, Objective::Maximize, rng)
+}
+
+/// [`diverse_tournament_selection`] for the given objective. Novelty always
+/// counts in a candidate's favour, whichever way fitness points.
+pub fn diverse_tournament_selection_with<'a>(
+    population: &'a [Individual],
+    tournament_size: usize,
+    diversity_weight: f64,
+    objective: Objective,
+    rng: &mut impl Rng,
+) -> &'a Individual {
+    if diversity_weight == 0.0 {
+        return tournament_selection_with(population, tournament_size, objective, rng);
+    }
+
+    let tournament = draw_tournament(population, tournament_size, rng);
     
-    // Find winner based on combined fitness and novelty
+    // Find winner based on combined fitness and novelty; a NaN score never wins
+    let score = |individual: &Individual| {
+        let score = objective.score(individual.fitness) + diversity_weight * individual.novelty_score;
+        if score.is_nan() { f64::NEG_INFINITY } else { score }
+    };
     tournament
-        .iter()
-        .max_by(|a, b| {
-            let score_a = a.fitness + diversity_weight * a.novelty_score;
-            let score_b = b.fitness + diversity_weight * b.novelty_score;
-            score_a.partial_cmp(&score_b).unwrap()
-        })
+        .into_iter()
+        .max_by(|a, b| score(a).total_cmp(&score(b)))
         .unwrap()
 }
 
+/// Count how often each opcode occurs across all programs in the population
+/// (every occurrence, at any depth). Opcodes that never occur are absent.
+pub fn opcode_histogram(population: &[Individual]) -> HashMap<OpCode, usize> {
+    let mut histogram = HashMap::new();
+    for individual in population {
+        for (node, _) in individual.ast.iter_preorder() {
+            if let UntypedAst::Instruction(op) = node {
+                *histogram.entry(op.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+    histogram
+}
+
+/// Print a histogram from [`opcode_histogram`], most frequent first, with
+/// each opcode's share of all instructions and a bar scaled to the largest count.
+pub fn print_histogram(histogram: &HashMap<OpCode, usize>) {
+    const BAR_WIDTH: usize = 40;
+
+    let total: usize = histogram.values().sum();
+    let max = histogram.values().copied().max().unwrap_or(0);
+    if total == 0 {
+        println!("(no instructions)");
+        return;
+    }
+
+    let mut entries: Vec<(&OpCode, &usize)> = histogram.iter().collect();
+    // Ties broken by opcode so the output is stable
+    entries.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+    for (op, &n) in entries {
+        let bar = "#".repeat((n * BAR_WIDTH).div_ceil(max));
+        println!(
+            "{:<14} {:>7} {:>5.1}% {}",
+            format!("{:?}", op),
+            n,
+            100.0 * n as f64 / total as f64,
+            bar
+        );
+    }
+}
+
 /// Calculate population statistics
+///
+/// ```
+/// use offchain::gp::population_management::{calculate_population_stats, Individual};
+/// use offchain::push3;
+///
+/// let population = |fitnesses: &[f64]| -> Vec<Individual> {
+///     fitnesses.iter().map(|&f| Individual::new(push3!((1 2 +)), f)).collect()
+/// };
+///
+/// let odd = calculate_population_stats(&population(&[9.0, 1.0, 4.0, 7.0, 2.0]));
+/// assert_eq!((odd.min_fitness, odd.median_fitness, odd.max_fitness), (1.0, 4.0, 9.0));
+///
+/// let even = calculate_population_stats(&population(&[9.0, 1.0, 4.0, 7.0]));
+/// assert_eq!((even.min_fitness, even.median_fitness, even.max_fitness), (1.0, 5.5, 9.0));
+/// ```
 pub fn calculate_population_stats(population: &[Individual]) -> PopulationStats {
+    population_stats_with(population, average_pairwise_distance(population))
+}
+
+/// Like [`calculate_population_stats`], but the diversity score is estimated
+/// from `max_pairs` randomly drawn pairs once the population has more pairs
+/// than that. Small populations still get the exact value.
+pub fn calculate_population_stats_sampled(
+    population: &[Individual],
+    max_pairs: usize,
+    rng: &mut impl Rng,
+) -> PopulationStats {
+    let n = population.len();
+    let total_pairs = n * n.saturating_sub(1) / 2;
+    let diversity_score = if total_pairs <= max_pairs {
+        average_pairwise_distance(population)
+    } else {
+        sampled_pairwise_distance(population, max_pairs, rng)
+    };
+    population_stats_with(population, diversity_score)
+}
+
+/// Exact diversity score: average `structural_distance` over all pairs.
+fn average_pairwise_distance(population: &[Individual]) -> f64 {
+    let mut total_distance = 0.0;
+    let mut pair_count = 0;
+
+    for i in 0..population.len() {
+        for j in (i + 1)..population.len() {
+            total_distance += structural_distance(&population[i].ast, &population[j].ast);
+            pair_count += 1;
+        }
+    }
+
+    if pair_count > 0 {
+        total_distance / pair_count as f64
+    } else {
+        0.0
+    }
+}
+
+/// Diversity estimate from `samples` pairs of distinct individuals drawn
+/// uniformly with replacement.
+fn sampled_pairwise_distance(population: &[Individual], samples: usize, rng: &mut impl Rng) -> f64 {
+    let n = population.len();
+    if n < 2 || samples == 0 {
+        return 0.0;
+    }
+
+    let mut total_distance = 0.0;
+    for _ in 0..samples {
+        let i = rng.gen_range(0..n);
+        let mut j = rng.gen_range(0..n - 1);
+        if j >= i {
+            j += 1;
+        }
+        total_distance += structural_distance(&population[i].ast, &population[j].ast);
+    }
+    total_distance / samples as f64
+}
+
+/// Fitness and size statistics, plus an already computed diversity score.
+fn population_stats_with(population: &[Individual], diversity_score: f64) -> PopulationStats {
     if population.is_empty() {
         return PopulationStats {
             avg_fitness: 0.0,
             fitness_std: 0.0,
+            min_fitness: 0.0,
+            median_fitness: 0.0,
+            max_fitness: 0.0,
             avg_size: 0.0,
             size_std: 0.0,
             diversity_score: 0.0,
             stagnation_count: 0,
+            success_rate: 0.0,
+            avg_error: 0.0,
         };
     }
     
@@ -240,6 +1027,14 @@ pub fn calculate_population_stats(population: &[Individual]) -> PopulationStats
         .map(|f| (f - avg_fitness).powi(2))
         .sum::<f64>() / n;
     let fitness_std = fitness_variance.sqrt();
+    let mut sorted = fitnesses.clone();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    let median_fitness = if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    };
     
     // Size statistics
     let sizes: Vec<f64> = population.iter().map(|ind| ind.size as f64).collect();
@@ -248,46 +1043,239 @@ pub fn calculate_population_stats(population: &[Individual]) -> PopulationStats
         .map(|s| (s - avg_size).powi(2))
         .sum::<f64>() / n;
     let size_std = size_variance.sqrt();
-    
-    // Diversity score (average pairwise distance)
-    let mut total_distance = 0.0;
-    let mut pair_count = 0;
-    
-    for i in 0..population.len() {
-        for j in (i + 1)..population.len() {
-            total_distance += structural_distance(&population[i].ast, &population[j].ast);
-            pair_count += 1;
-        }
-    }
-    
-    let diversity_score = if pair_count > 0 {
-        total_distance / pair_count as f64
-    } else {
+
+    // Success / error statistics
+    let succeeded: Vec<f64> = population.iter()
+        .filter(|ind| !ind.reverted)
+        .map(|ind| ind.mean_error)
+        .collect();
+    let success_rate = succeeded.len() as f64 / n;
+    let avg_error = if succeeded.is_empty() {
         0.0
+    } else {
+        succeeded.iter().sum::<f64>() / succeeded.len() as f64
     };
-    
+
     PopulationStats {
         avg_fitness,
         fitness_std,
+        min_fitness: sorted[0],
+        median_fitness,
+        max_fitness: sorted[sorted.len() - 1],
         avg_size,
         size_std,
         diversity_score,
         stagnation_count: 0, // This should be tracked externally
+        success_rate,
+        avg_error,
+    }
+}
+
+/// One program kept by a [`HallOfFame`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HallOfFameEntry {
+    pub individual: Individual,
+    /// `ast_fingerprint` of the program; equal for structural duplicates.
+    pub fingerprint: String,
+}
+
+/// The `capacity` fittest distinct programs seen so far, best first. Programs
+/// with the same canonical form (same [`ast_fingerprint`]) are stored once.
+///
+/// ```
+/// use offchain::gp::population_management::{HallOfFame, Individual};
+/// use offchain::push3;
+///
+/// let mut hof = HallOfFame::new(2);
+/// assert!(hof.consider(&Individual::new(push3!((1 2 +)), 5.0)));
+/// assert!(hof.consider(&Individual::new(push3!((1 2 -)), 3.0)));
+///
+/// // Full: only something better than the worst entry gets in
+/// assert!(!hof.consider(&Individual::new(push3!((1 DUP *)), 2.0)));
+/// assert!(hof.consider(&Individual::new(push3!((4 DUP *)), 4.0)));
+/// assert_eq!(hof.entries.len(), 2);
+/// let fitness: Vec<f64> = hof.to_vec().iter().map(|i| i.fitness).collect();
+/// assert_eq!(fitness, [5.0, 4.0]);
+///
+/// // `(2 1 +)` is a structural duplicate of `(1 2 +)`: only its better fitness is kept
+/// assert!(!hof.consider(&Individual::new(push3!((2 1 +)), 4.5)));
+/// assert!(hof.consider(&Individual::new(push3!((2 1 +)), 9.0)));
+/// assert_eq!(hof.entries.len(), 2);
+/// assert_eq!(hof.best().unwrap().fitness, 9.0);
+/// assert_eq!(hof.to_vec()[1].fitness, 4.0);
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HallOfFame {
+    pub capacity: usize,
+    /// Sorted best first, at most `capacity` long, fingerprints all distinct.
+    pub entries: Vec<HallOfFameEntry>,
+}
+
+impl HallOfFame {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, entries: Vec::with_capacity(capacity) }
+    }
+
+    /// Offer `individual`. It is stored if it beats the worst entry (or there
+    /// is room) and no structural duplicate is already stored; a stored
+    /// duplicate is replaced instead if `individual` is fitter. Returns whether
+    /// the hall of fame changed.
+    pub fn consider(&mut self, individual: &Individual) -> bool {
+        let full = self.entries.len() >= self.capacity;
+        if self.capacity == 0
+            || (full && self.entries.last().is_some_and(|worst| individual.fitness <= worst.individual.fitness))
+        {
+            return false;
+        }
+
+        // Only hash programs that could get in
+        let fingerprint = ast_fingerprint(&individual.ast);
+        if let Some(i) = self.entries.iter().position(|e| e.fingerprint == fingerprint) {
+            if individual.fitness <= self.entries[i].individual.fitness {
+                return false;
+            }
+            self.entries.remove(i);
+        } else if full {
+            self.entries.pop();
+        }
+
+        let at = self
+            .entries
+            .iter()
+            .position(|e| individual.fitness > e.individual.fitness)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(at, HallOfFameEntry { individual: individual.clone(), fingerprint });
+        true
+    }
+
+    /// The fittest stored individual.
+    pub fn best(&self) -> Option<&Individual> {
+        self.entries.first().map(|e| &e.individual)
+    }
+
+    /// The stored individuals, best first.
+    pub fn to_vec(&self) -> Vec<Individual> {
+        self.entries.iter().map(|e| e.individual.clone()).collect()
     }
 }
 
-/// Maintain population diversity by removing very similar individuals
+/// Remove exact structural duplicates, keeping the first occurrence of each
+/// program (on a population sorted best first, the fittest copy). Returns how
+/// many were removed. Cheap enough to run before the distance-based passes.
+///
+/// ```
+/// use offchain::compiler::ast::{OpCode, UntypedAst};
+/// use offchain::gp::population_management::{dedup_identical, Individual};
+///
+/// let plus = UntypedAst::Sublist(vec![UntypedAst::IntLiteral(1), UntypedAst::Instruction(OpCode::Plus)]);
+/// let minus = UntypedAst::Sublist(vec![UntypedAst::IntLiteral(1), UntypedAst::Instruction(OpCode::Minus)]);
+/// let mut population = vec![
+///     Individual::new(plus.clone(), 3.0),
+///     Individual::new(minus, 2.0),
+///     Individual::new(plus, 1.0),
+/// ];
+///
+/// assert_eq!(dedup_identical(&mut population), 1);
+/// assert_eq!(population.len(), 2);
+/// assert_eq!(population[0].fitness, 3.0);
+/// ```
+pub fn dedup_identical(population: &mut Vec<Individual>) -> usize {
+    let before = population.len();
+    let mut seen: HashSet<UntypedAst> = HashSet::with_capacity(before);
+    population.retain(|individual| seen.insert(individual.ast.clone()));
+    before - population.len()
+}
+
+/// Maintain population diversity by removing very similar individuals: for
+/// every pair closer than `min_distance`, the less fit one is removed (a random
+/// one on a tie).
+///
+/// Instead of comparing all pairs, individuals are bucketed by [`shape_key`]
+/// and only compared within their bucket. Two programs with different keys
+/// are always at least `min_distance` apart, so the same pairs are found as
+/// by [`enforce_minimum_diversity_all_pairs`].
 pub fn enforce_minimum_diversity(
     population: &mut Vec<Individual>,
     min_distance: f64,
     rng: &mut impl Rng,
 ) {
-    let mut to_remove = Vec::new();
-    
-    for i in 0..population.len() {
-        for j in (i + 1)..population.len() {
+    // BTreeMap, not HashMap, so buckets (and rng draws) come in a fixed order
+    let mut buckets: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (i, individual) in population.iter().enumerate() {
+        buckets
+            .entry(shape_key(&individual.ast, min_distance))
+            .or_default()
+            .push(i);
+    }
+
+    let mut remove = vec![false; population.len()];
+    for bucket in buckets.values() {
+        mark_near_duplicates(population, bucket, min_distance, rng, &mut remove);
+    }
+    retain_unmarked(population, &remove);
+}
+
+/// The original all-pairs version of [`enforce_minimum_diversity`]. Quadratic
+/// in the population size; kept as a reference (see `bin/diversity_bench.rs`).
+pub fn enforce_minimum_diversity_all_pairs(
+    population: &mut Vec<Individual>,
+    min_distance: f64,
+    rng: &mut impl Rng,
+) {
+    let all: Vec<usize> = (0..population.len()).collect();
+    let mut remove = vec![false; population.len()];
+    mark_near_duplicates(population, &all, min_distance, rng, &mut remove);
+    retain_unmarked(population, &remove);
+}
+
+/// A cheap hash of the parts of `ast` that `structural_distance` penalizes by
+/// at least `min_distance` when they differ: node kinds and opcodes down the
+/// leftmost spine of the canonical form, and whether sublists are empty, as
+/// long as the weight at that depth stays above `min_distance`. Programs with
+/// different keys are never near-duplicates.
+pub fn shape_key(ast: &UntypedAst, min_distance: f64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hash_shape(&canonicalize(ast), 1.0, min_distance, &mut hasher);
+    hasher.finish()
+}
+
+fn hash_shape(ast: &UntypedAst, weight: f64, min_distance: f64, hasher: &mut DefaultHasher) {
+    // Mismatches below this depth cost less than `min_distance`.
+    if weight < min_distance {
+        return;
+    }
+    match ast {
+        UntypedAst::IntLiteral(_) => 0u8.hash(hasher),
+        UntypedAst::Instruction(op) => {
+            1u8.hash(hasher);
+            std::mem::discriminant(op).hash(hasher);
+        }
+        UntypedAst::Sublist(children) => {
+            2u8.hash(hasher);
+            // Empty vs. n children costs weight * n / (1 + n) >= weight / 2
+            if weight * 0.5 >= min_distance {
+                children.is_empty().hash(hasher);
+                if let Some(first) = children.first() {
+                    hash_shape(first, weight * 0.8, min_distance, hasher);
+                }
+            }
+        }
+    }
+}
+
+/// Compare all pairs within `indices` and mark the worse of each
+/// near-duplicate pair in `remove`.
+fn mark_near_duplicates(
+    population: &[Individual],
+    indices: &[usize],
+    min_distance: f64,
+    rng: &mut impl Rng,
+    remove: &mut [bool],
+) {
+    for (a, &i) in indices.iter().enumerate() {
+        for &j in &indices[a + 1..] {
             let distance = structural_distance(&population[i].ast, &population[j].ast);
-            
+
             if distance < min_distance {
                 // Remove the worse individual, or random if tied
                 let remove_idx = if population[i].fitness > population[j].fitness {
@@ -298,19 +1286,190 @@ pub fn enforce_minimum_diversity(
                     // Tied fitness - remove random one
                     if rng.gen::<bool>() { i } else { j }
                 };
-                
-                if !to_remove.contains(&remove_idx) {
-                    to_remove.push(remove_idx);
+                remove[remove_idx] = true;
+            }
+        }
+    }
+}
+
+fn retain_unmarked(population: &mut Vec<Individual>, remove: &[bool]) {
+    let mut idx = 0;
+    population.retain(|_| {
+        let keep = !remove[idx];
+        idx += 1;
+        keep
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::rng::seeded_rng;
+
+    /// `count` random programs, each followed by a slightly less fit
+    /// near-duplicate: the same program with a `NOOP` appended.
+    fn population_with_near_duplicates(count: usize, seed: u64) -> Vec<Individual> {
+        let mut rng = seeded_rng(seed);
+        let mut population = Vec::new();
+        for i in 0..count {
+            let children = match ranmdom_code_fixed(&mut rng, 12) {
+                UntypedAst::Sublist(children) => children,
+                atom => vec![atom],
+            };
+            let mut padded = children.clone();
+            padded.push(UntypedAst::Instruction(OpCode::Noop));
+            population.push(Individual::new(UntypedAst::Sublist(children), (2 * i + 1) as f64));
+            population.push(Individual::new(UntypedAst::Sublist(padded), (2 * i) as f64));
+        }
+        population
+    }
+
+    #[test]
+    fn bucketed_diversity_matches_all_pairs() {
+        let min_distance = 0.3;
+        for seed in 0..5 {
+            let population = population_with_near_duplicates(30, seed);
+
+            let mut bucketed = population.clone();
+            enforce_minimum_diversity(&mut bucketed, min_distance, &mut seeded_rng(seed));
+            let mut all_pairs = population.clone();
+            enforce_minimum_diversity_all_pairs(&mut all_pairs, min_distance, &mut seeded_rng(seed));
+
+            assert!(bucketed.len() < population.len(), "no near-duplicate removed");
+            let kept = |p: &[Individual]| p.iter().map(|i| (i.ast.clone(), i.fitness)).collect::<Vec<_>>();
+            assert_eq!(kept(&bucketed), kept(&all_pairs));
+            for (i, a) in bucketed.iter().enumerate() {
+                for b in &bucketed[i + 1..] {
+                    assert!(structural_distance(&a.ast, &b.ast) >= min_distance);
                 }
             }
         }
     }
-    
-    // Remove duplicates, sort in reverse order to maintain indices
-    to_remove.sort_unstable();
-    to_remove.dedup();
-    
-    for &idx in to_remove.iter().rev() {
-        population.remove(idx);
+
+    /// Eight programs with fitness 0..8, in no particular order.
+    fn ranked_population() -> Vec<Individual> {
+        [3, 7, 0, 5, 1, 6, 2, 4]
+            .iter()
+            .map(|&fitness| Individual::new(UntypedAst::IntLiteral(fitness), fitness as f64))
+            .collect()
+    }
+
+    /// Newcomers are scored -1, so they can't be mistaken for survivors.
+    fn newcomer(_: &mut (), ast: UntypedAst) -> Individual {
+        Individual::new(ast, -1.0)
+    }
+
+    fn fitnesses(population: &[Individual]) -> Vec<f64> {
+        population.iter().map(|individual| individual.fitness).collect()
+    }
+
+    #[test]
+    fn tournament_size_sets_selection_pressure() {
+        let population = ranked_population();
+        let mut rng = seeded_rng(5);
+
+        // Everyone takes part: the best always wins
+        for _ in 0..100 {
+            assert_eq!(tournament_selection(&population, population.len(), &mut rng).fitness, 7.0);
+        }
+
+        // A lone entrant is a uniform draw
+        let draws = 8000;
+        let mut wins = [0usize; 8];
+        for _ in 0..draws {
+            wins[tournament_selection(&population, 1, &mut rng).fitness as usize] += 1;
+        }
+        for (fitness, &count) in wins.iter().enumerate() {
+            assert!((800..1200).contains(&count), "fitness {} won {} of {} draws", fitness, count, draws);
+        }
+    }
+
+    #[test]
+    fn zero_diversity_weight_is_plain_tournament() {
+        // Novelty favours the least fit, so any weight on it would change the winners
+        let mut population = ranked_population();
+        for individual in &mut population {
+            individual.novelty_score = 10.0 - individual.fitness;
+        }
+
+        let mut plain_rng = seeded_rng(1547);
+        let mut diverse_rng = seeded_rng(1547);
+        for _ in 0..200 {
+            let plain = tournament_selection(&population, 3, &mut plain_rng);
+            let diverse = diverse_tournament_selection(&population, 3, 0.0, &mut diverse_rng);
+            assert!(std::ptr::eq(plain, diverse), "plain picked {}, diverse {}", plain.fitness, diverse.fitness);
+        }
+    }
+
+    #[test]
+    fn replace_oldest_replaces_only_those_past_max_age() {
+        let mut population = ranked_population();
+        for (individual, age) in population.iter_mut().zip([0, 9, 2, 3, 7, 1, 8, 3]) {
+            individual.age = age;
+        }
+        let replaced = replace_oldest(&mut population, 3, 6, &mut seeded_rng(1), |_| -1.0);
+
+        assert_eq!(replaced, 3);
+        assert_eq!(population.len(), 8);
+        // In place: the survivors keep their slot, fitness and age
+        assert_eq!(fitnesses(&population), [3.0, -1.0, 0.0, 5.0, -1.0, 6.0, -1.0, 4.0]);
+        let ages: Vec<u32> = population.iter().map(|individual| individual.age).collect();
+        assert_eq!(ages, [0, 0, 2, 3, 0, 1, 0, 3]);
+    }
+
+    #[test]
+    fn restart_worst_fraction_replaces_the_worst() {
+        let mut population = ranked_population();
+        let replaced = restart_worst_fraction(&mut population, 0.25, 6, &mut (), newcomer, &mut seeded_rng(1));
+        assert_eq!(replaced, 2);
+        assert_eq!(fitnesses(&population), [7.0, 6.0, 5.0, 4.0, 3.0, 2.0, -1.0, -1.0]);
+
+        // Any positive fraction restarts at least one, and nothing is restarted at 0
+        let mut population = ranked_population();
+        assert_eq!(restart_worst_fraction(&mut population, 0.01, 6, &mut (), newcomer, &mut seeded_rng(1)), 1);
+        assert_eq!(restart_worst_fraction(&mut population, 0.0, 6, &mut (), newcomer, &mut seeded_rng(1)), 0);
+        assert_eq!(population.len(), 8);
+    }
+
+    #[test]
+    fn hard_reset_keeps_only_the_elites() {
+        let mut population = ranked_population();
+        let replaced = hard_reset(&mut population, 2, 6, &mut (), newcomer, &mut seeded_rng(1));
+        assert_eq!(replaced, 6);
+        assert_eq!(fitnesses(&population), [7.0, 6.0, -1.0, -1.0, -1.0, -1.0, -1.0, -1.0]);
+
+        // More elites than individuals keeps everyone
+        let mut population = ranked_population();
+        assert_eq!(hard_reset(&mut population, 20, 6, &mut (), newcomer, &mut seeded_rng(1)), 0);
+        assert_eq!(fitnesses(&population), [7.0, 6.0, 5.0, 4.0, 3.0, 2.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn sampled_stats_stay_close_to_exact() {
+        // 120 individuals give 7140 pairs, so 1000 is a genuine sample
+        let population = population_with_near_duplicates(60, 1556);
+        let exact = calculate_population_stats(&population);
+
+        for seed in 0..5 {
+            let sampled = calculate_population_stats_sampled(&population, 1000, &mut seeded_rng(seed));
+            // Distances lie in [0, 1], so 1000 draws put the mean within ~0.016 per standard error
+            let tolerance = 0.05;
+            assert!(
+                (sampled.diversity_score - exact.diversity_score).abs() < tolerance,
+                "seed {}: sampled diversity {} vs exact {}",
+                seed,
+                sampled.diversity_score,
+                exact.diversity_score
+            );
+            // Only the diversity score is estimated
+            assert_eq!(sampled.avg_fitness, exact.avg_fitness);
+            assert_eq!(sampled.median_fitness, exact.median_fitness);
+            assert_eq!(sampled.avg_size, exact.avg_size);
+        }
+
+        // With no more pairs than the budget, the score is exact
+        let small = &population[..20];
+        let sampled = calculate_population_stats_sampled(small, 190, &mut seeded_rng(0));
+        assert_eq!(sampled.diversity_score, calculate_population_stats(small).diversity_score);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,126 @@
+//! src/gp/stats_logger.rs
+//!
+//! Per-generation statistics as CSV, one row per generation, for graphing
+//! fitness / size / diversity trajectories. Rows are flushed as they are
+//! written, so an interrupted run still leaves a usable file.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use crate::gp::population_management::PopulationStats;
+
+/// Column names, in order. `seed` repeats the run's RNG seed on every row, so
+/// rows from several runs appended to one file can be told apart and replayed.
+pub const CSV_HEADER: &str =
+    "gen,best_fitness,avg_fitness,fitness_std,min_fitness,median_fitness,max_fitness,avg_size,size_std,diversity_score,stagnation,seed";
+
+/// Appends one CSV row per generation to a file.
+pub struct StatsLogger {
+    out: BufWriter<File>,
+    seed: u64,
+}
+
+impl StatsLogger {
+    /// Open `path` for appending, creating it if needed. The header is written
+    /// only when the file is new or empty, so a resumed run keeps adding rows
+    /// to the same table.
+    pub fn new(path: impl AsRef<Path>, seed: u64) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow!("Failed to open stats log {}: {}", path.display(), e))?;
+        let is_empty = file.metadata()?.len() == 0;
+
+        let mut logger = Self { out: BufWriter::new(file), seed };
+        if is_empty {
+            writeln!(logger.out, "{}", CSV_HEADER)?;
+            logger.out.flush()?;
+        }
+        Ok(logger)
+    }
+
+    /// Append the row for generation `gen`.
+    pub fn log_generation(
+        &mut self,
+        gen: usize,
+        best_fitness: f64,
+        stats: &PopulationStats,
+        stagnation: usize,
+    ) -> Result<()> {
+        writeln!(
+            self.out,
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            gen,
+            best_fitness,
+            stats.avg_fitness,
+            stats.fitness_std,
+            stats.min_fitness,
+            stats.median_fitness,
+            stats.max_fitness,
+            stats.avg_size,
+            stats.size_std,
+            stats.diversity_score,
+            stagnation,
+            self.seed
+        )?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(avg_fitness: f64) -> PopulationStats {
+        PopulationStats {
+            avg_fitness,
+            fitness_std: 1.0,
+            min_fitness: 0.0,
+            median_fitness: avg_fitness,
+            max_fitness: 2.0 * avg_fitness,
+            avg_size: 5.0,
+            size_std: 0.5,
+            diversity_score: 0.25,
+            stagnation_count: 0,
+            success_rate: 1.0,
+            avg_error: 0.0,
+        }
+    }
+
+    #[test]
+    fn writes_header_once_and_a_row_per_generation() {
+        let path = std::env::temp_dir().join(format!("push3_stats_{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut logger = StatsLogger::new(&path, 42).unwrap();
+        logger.log_generation(0, 3.0, &stats(1.0), 0).unwrap();
+        logger.log_generation(1, 4.0, &stats(2.0), 1).unwrap();
+        drop(logger);
+        // Reopening, as a resumed run does, must not repeat the header
+        StatsLogger::new(&path, 42).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+
+        let columns: Vec<&str> = lines[0].split(',').collect();
+        assert_eq!(columns.len(), 12);
+        assert_eq!(columns[0], "gen");
+        assert_eq!(columns[1], "best_fitness");
+        assert_eq!(columns[11], "seed");
+        for (gen, row) in lines[1..].iter().enumerate() {
+            let fields: Vec<&str> = row.split(',').collect();
+            assert_eq!(fields.len(), columns.len());
+            assert_eq!(fields[0], gen.to_string());
+            assert_eq!(fields[11], "42");
+        }
+        assert_eq!(lines[2], "1,4,2,1,0,2,4,5,0.5,0.25,1,42");
+    }
+}
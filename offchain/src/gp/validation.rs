@@ -0,0 +1,84 @@
+//! src/gp/validation.rs
+//!
+//! Differential testing: run the same program through the pure-Rust interpreter
+//! (`compiler::interp`) and the on-chain interpreter (via `EvmRunner`) and report
+//! the first place where they disagree. Divergences usually point at encoding bugs
+//! (bytecode tags, negative ints, ...) rather than at the programs themselves.
+
+use crate::compiler::ast::{Push3Ast, UntypedAst};
+use crate::compiler::interp::{eval_pure, InterpError};
+use crate::runner::revm_runner::{EvmRunner, Push3InterpreterInputs};
+
+/// Outcome of comparing one run on both interpreters.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffResult {
+    /// Both interpreters produced identical int and bool stacks.
+    Agree,
+    /// Both interpreters failed (revert / overflow).
+    BothFailed,
+    /// The pure interpreter cannot run this program (e.g. it uses `RAND`),
+    /// so there is nothing to compare.
+    Skipped(InterpError),
+    /// The int stacks differ; `index` is counted from the bottom.
+    /// `None` means that side's stack is shorter.
+    IntStackDiffers {
+        index: usize,
+        pure: Option<i128>,
+        evm: Option<i128>,
+    },
+    /// The bool stacks differ; same conventions as `IntStackDiffers`.
+    BoolStackDiffers {
+        index: usize,
+        pure: Option<bool>,
+        evm: Option<bool>,
+    },
+    /// Only the pure interpreter failed.
+    OnlyPureFailed(InterpError),
+    /// Only the EVM call failed (revert or other error).
+    OnlyEvmFailed(String),
+}
+
+impl DiffResult {
+    /// True if this result shows the two interpreters disagree.
+    pub fn is_divergence(&self) -> bool {
+        !matches!(
+            self,
+            DiffResult::Agree | DiffResult::BothFailed | DiffResult::Skipped(_)
+        )
+    }
+}
+
+/// Run `ast` with `inputs` as the initial int stack on both interpreters,
+/// and compare the final stacks.
+pub fn diff_test(ast: &UntypedAst, inputs: &[i128], runner: &mut EvmRunner) -> DiffResult {
+    let pure = eval_pure(ast, inputs, &[]);
+    if let Err(e @ InterpError::Unsupported(_)) = &pure {
+        return DiffResult::Skipped(e.clone());
+    }
+
+    let evm_inputs = Push3InterpreterInputs::for_program(ast.to_bytecode(), inputs, Vec::new());
+    let evm = runner.run_interpreter(&evm_inputs);
+
+    match (pure, evm) {
+        (Err(_), Err(_)) => DiffResult::BothFailed,
+        (Err(e), Ok(_)) => DiffResult::OnlyPureFailed(e),
+        (Ok(_), Err(e)) => DiffResult::OnlyEvmFailed(e.to_string()),
+        (Ok((pure_ints, pure_bools)), Ok(out)) => {
+            if let Some((index, pure, evm)) = first_difference(&pure_ints, &out.final_int_stack) {
+                return DiffResult::IntStackDiffers { index, pure, evm };
+            }
+            if let Some((index, pure, evm)) = first_difference(&pure_bools, &out.final_bool_stack) {
+                return DiffResult::BoolStackDiffers { index, pure, evm };
+            }
+            DiffResult::Agree
+        }
+    }
+}
+
+/// Find the first index (from the bottom) where two stacks differ.
+fn first_difference<T: Copy + PartialEq>(a: &[T], b: &[T]) -> Option<(usize, Option<T>, Option<T>)> {
+    let len = a.len().max(b.len());
+    (0..len)
+        .map(|i| (i, a.get(i).copied(), b.get(i).copied()))
+        .find(|(_, x, y)| x != y)
+}
@@ -1,41 +1,190 @@
 //! src/helpers/artifact.rs
-//! A small helper module to read a Forge artifact JSON and extract the creation code.
+//! A small helper module to read a Forge artifact JSON and extract the creation
+//! (or runtime) code.
+//!
+//! Both shapes seen in Foundry / solc output are accepted: the bytecode as
+//! `"bytecode": { "object": "0x..." }` or directly as `"bytecode": "0x..."`
+//! (and likewise for `deployedBytecode`).
 
 use anyhow::{anyhow, bail, Result};
-use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
 
-/// This mirrors the JSON structure: "bytecode": { "object": "...hex..." }
-#[derive(Debug, Deserialize)]
-struct BytecodeObject {
-    object: String,
-}
+/// Environment variable naming the interpreter artifact, overriding the
+/// conventional locations searched by [`locate_interpreter_artifact`].
+pub const INTERPRETER_ARTIFACT_ENV: &str = "PUSH3_ARTIFACT";
+
+/// Where `forge build` puts the interpreter artifact, relative to this crate's
+/// root, in the order they are tried.
+const INTERPRETER_ARTIFACT_CANDIDATES: [&str; 3] = [
+    "../onchain/out/Push3Interpreter.sol/Push3Interpreter.json",
+    "onchain/out/Push3Interpreter.sol/Push3Interpreter.json",
+    "out/Push3Interpreter.sol/Push3Interpreter.json",
+];
+
+/// Path of the `Push3Interpreter` Forge artifact, independent of the working
+/// directory: `$PUSH3_ARTIFACT` if set (it must exist), else the first of the
+/// conventional build locations under the crate root that exists.
+///
+/// ```
+/// use offchain::helpers::artifact::{locate_interpreter_artifact, INTERPRETER_ARTIFACT_ENV};
+///
+/// let path = std::env::temp_dir().join("push3_artifact_override.json");
+/// std::fs::write(&path, r#"{ "bytecode": "0x00" }"#).unwrap();
+/// std::env::set_var(INTERPRETER_ARTIFACT_ENV, &path);
+/// assert_eq!(locate_interpreter_artifact().unwrap(), path);
+///
+/// // A wrong override is an error, not a silent fallback
+/// std::env::set_var(INTERPRETER_ARTIFACT_ENV, path.with_extension("missing"));
+/// assert!(locate_interpreter_artifact().is_err());
+/// std::env::remove_var(INTERPRETER_ARTIFACT_ENV);
+/// ```
+pub fn locate_interpreter_artifact() -> Result<PathBuf> {
+    if let Some(path) = env::var_os(INTERPRETER_ARTIFACT_ENV) {
+        let path = PathBuf::from(path);
+        if !path.is_file() {
+            bail!("{} is set to {}, which does not exist", INTERPRETER_ARTIFACT_ENV, path.display());
+        }
+        return Ok(path);
+    }
 
-/// The top-level artifact with a "bytecode" field.
-#[derive(Debug, Deserialize)]
-struct MyContractArtifact {
-    bytecode: BytecodeObject,
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    INTERPRETER_ARTIFACT_CANDIDATES
+        .iter()
+        .map(|candidate| root.join(candidate))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            anyhow!(
+                "Push3Interpreter artifact not found under {} (run `forge build` in onchain/, or set {})",
+                root.display(),
+                INTERPRETER_ARTIFACT_ENV
+            )
+        })
 }
 
 /// Reads the given JSON file (a Forge artifact) and returns the raw creation code bytes.
 ///
-/// * `filename`: path to the artifact JSON (e.g. `../onchain/out/Push3Interpreter.sol/Push3Interpreter.json`)
-pub fn get_creation_code(filename: &str) -> Result<Vec<u8>> {
-    // 1) Read the JSON file
-    let creation_json = fs::read_to_string(filename)
-        .map_err(|e| anyhow!("Failed to read JSON file {}: {}", filename, e))?;
-    
-    // 2) Parse
-    let contract_artifact: MyContractArtifact = serde_json::from_str(&creation_json)
-        .map_err(|e| anyhow!("Failed to parse JSON artifact: {}", e))?;
-
-    // 3) Extract the hex code
-    let raw_hex = &contract_artifact.bytecode.object;
-    let stripped_hex = raw_hex.trim_start_matches("0x");
-
-    // 4) Convert from hex => bytes
-    match hex::decode(stripped_hex) {
-        Ok(bytes) => Ok(bytes),
-        Err(e) => bail!("Invalid hex for creation code: {e}"),
+/// * `filename`: path to the artifact JSON, usually from [`locate_interpreter_artifact`]
+pub fn get_creation_code(filename: impl AsRef<Path>) -> Result<Vec<u8>> {
+    creation_code_from_json(&read_artifact(filename.as_ref())?)
+}
+
+/// Like [`get_creation_code`], but each artifact is read and decoded only once
+/// per process; later calls (from any thread) share the same buffer, e.g. to
+/// deploy many runners.
+///
+/// ```
+/// use std::sync::Arc;
+/// use offchain::helpers::artifact::cached_creation_code;
+///
+/// let path = std::env::temp_dir().join("push3_cached_artifact.json");
+/// std::fs::write(&path, r#"{ "bytecode": { "object": "0x6001" } }"#).unwrap();
+/// let a = cached_creation_code(&path).unwrap();
+/// let b = std::thread::spawn({
+///     let path = path.clone();
+///     move || cached_creation_code(&path).unwrap()
+/// })
+/// .join()
+/// .unwrap();
+/// assert!(Arc::ptr_eq(&a, &b));
+/// assert_eq!(*a, [0x60, 0x01]);
+/// ```
+pub fn cached_creation_code(filename: impl AsRef<Path>) -> Result<Arc<Vec<u8>>> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, Arc<Vec<u8>>>>> = OnceLock::new();
+
+    let filename = filename.as_ref();
+    let mut cache = CACHE
+        .get_or_init(Default::default)
+        .lock()
+        .map_err(|_| anyhow!("creation code cache poisoned"))?;
+    if let Some(code) = cache.get(filename) {
+        return Ok(Arc::clone(code));
+    }
+    // Decoded under the lock, so concurrent first calls don't both do the work
+    let code = Arc::new(get_creation_code(filename)?);
+    cache.insert(filename.to_path_buf(), Arc::clone(&code));
+    Ok(code)
+}
+
+/// Reads the given JSON file (a Forge artifact) and returns the runtime code
+/// from its `deployedBytecode`.
+pub fn get_deployed_code(filename: impl AsRef<Path>) -> Result<Vec<u8>> {
+    deployed_code_from_json(&read_artifact(filename.as_ref())?)
+}
+
+/// The creation code of an artifact given as a JSON string.
+///
+/// ```
+/// use offchain::helpers::artifact::{creation_code_from_json, deployed_code_from_json};
+///
+/// let nested = r#"{ "bytecode": { "object": "0x6001" }, "deployedBytecode": { "object": "0x00" } }"#;
+/// let plain = r#"{ "bytecode": "6001", "deployedBytecode": "0x00" }"#;
+/// for json in [nested, plain] {
+///     assert_eq!(creation_code_from_json(json).unwrap(), [0x60, 0x01]);
+///     assert_eq!(deployed_code_from_json(json).unwrap(), [0x00]);
+/// }
+///
+/// let err = creation_code_from_json(r#"{ "abi": [], "code": "0x00" }"#).unwrap_err();
+/// assert!(err.to_string().contains("keys found: [abi, code]"));
+///
+/// // An unbuilt contract: empty bytecode is an error, not an empty program
+/// for empty in [r#"{ "bytecode": "0x" }"#, r#"{ "bytecode": { "object": "" } }"#] {
+///     let err = creation_code_from_json(empty).unwrap_err();
+///     assert!(err.to_string().contains("forge build"));
+/// }
+/// ```
+pub fn creation_code_from_json(json: &str) -> Result<Vec<u8>> {
+    code_from_json(json, "bytecode")
+}
+
+/// The runtime code of an artifact given as a JSON string; see
+/// [`creation_code_from_json`].
+pub fn deployed_code_from_json(json: &str) -> Result<Vec<u8>> {
+    code_from_json(json, "deployedBytecode")
+}
+
+fn read_artifact(filename: &Path) -> Result<String> {
+    fs::read_to_string(filename)
+        .map_err(|e| anyhow!("Failed to read JSON file {}: {}", filename.display(), e))
+}
+
+/// Decode the hex under `key`, as either `{ "object": "..." }` or a plain string.
+fn code_from_json(json: &str, key: &str) -> Result<Vec<u8>> {
+    let artifact: Value =
+        serde_json::from_str(json).map_err(|e| anyhow!("Failed to parse JSON artifact: {}", e))?;
+
+    let raw_hex = match artifact.get(key) {
+        Some(Value::String(hex)) => hex,
+        Some(Value::Object(fields)) => match fields.get("object") {
+            Some(Value::String(hex)) => hex,
+            _ => bail!("Artifact field '{}' has no string 'object'", key),
+        },
+        _ => {
+            let keys: Vec<&str> = match &artifact {
+                Value::Object(fields) => fields.keys().map(String::as_str).collect(),
+                _ => Vec::new(),
+            };
+            bail!(
+                "Artifact has neither '{key}.object' nor a '{key}' string; keys found: [{}]",
+                keys.join(", ")
+            );
+        }
+    };
+
+    // Convert from hex => bytes
+    let bytes = match hex::decode(raw_hex.trim_start_matches("0x")) {
+        Ok(bytes) => bytes,
+        Err(e) => bail!("Invalid hex for {key}: {e}"),
+    };
+    if bytes.is_empty() {
+        bail!(
+            "Artifact field '{key}' is empty; the contract was not compiled (run `forge build` in onchain/) \
+             or is abstract"
+        );
     }
+    Ok(bytes)
 }
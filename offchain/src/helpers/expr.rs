@@ -0,0 +1,230 @@
+//! src/helpers/expr.rs
+//! A tiny integer expression language for describing regression targets,
+//! e.g. `"x^3 - 2*x^2 + 3*x + 5"`.
+//!
+//! Grammar (usual precedence, `^` binds tightest and is right-associative):
+//!
+//! ```text
+//! expr  := term (('+' | '-') term)*
+//! term  := unary (('*' | '/' | '%') unary)*
+//! unary := '-' unary | power
+//! power := atom ('^' unary)?
+//! atom  := integer | variable | '(' expr ')'
+//! ```
+//!
+//! Evaluation is in checked `i128`: overflow, division by zero and negative
+//! exponents yield `None`. `/` and `%` truncate toward zero like Rust.
+
+use anyhow::{bail, Result};
+
+/// A parsed expression. Variables are stored as indices into the variable
+/// names passed to [`parse_expr`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Num(i128),
+    Var(usize),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Rem(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Evaluate with `vars[i]` as the value of variable `i`.
+    ///
+    /// ```
+    /// use offchain::helpers::expr::parse_expr;
+    ///
+    /// let f = parse_expr("x^3 - 2*x^2 + 3*x + 5", &["x"]).unwrap();
+    /// assert_eq!(f.eval(&[2]), Some(11));
+    /// assert_eq!(f.eval(&[-1]), Some(-1));
+    ///
+    /// let g = parse_expr("x*y + x", &["x", "y"]).unwrap();
+    /// assert_eq!(g.eval(&[3, 4]), Some(15));
+    ///
+    /// assert_eq!(parse_expr("1 / (x - 1)", &["x"]).unwrap().eval(&[1]), None);
+    /// assert_eq!(parse_expr("-2^2", &[]).unwrap().eval(&[]), Some(-4));
+    /// ```
+    pub fn eval(&self, vars: &[i128]) -> Option<i128> {
+        match self {
+            Expr::Num(n) => Some(*n),
+            Expr::Var(i) => vars.get(*i).copied(),
+            Expr::Neg(a) => a.eval(vars)?.checked_neg(),
+            Expr::Add(a, b) => a.eval(vars)?.checked_add(b.eval(vars)?),
+            Expr::Sub(a, b) => a.eval(vars)?.checked_sub(b.eval(vars)?),
+            Expr::Mul(a, b) => a.eval(vars)?.checked_mul(b.eval(vars)?),
+            Expr::Div(a, b) => a.eval(vars)?.checked_div(b.eval(vars)?),
+            Expr::Rem(a, b) => a.eval(vars)?.checked_rem(b.eval(vars)?),
+            Expr::Pow(a, b) => {
+                let exp = u32::try_from(b.eval(vars)?).ok()?;
+                a.eval(vars)?.checked_pow(exp)
+            }
+        }
+    }
+
+    /// True if variable `index` appears anywhere in the expression.
+    pub fn uses_var(&self, index: usize) -> bool {
+        match self {
+            Expr::Num(_) => false,
+            Expr::Var(i) => *i == index,
+            Expr::Neg(a) => a.uses_var(index),
+            Expr::Add(a, b)
+            | Expr::Sub(a, b)
+            | Expr::Mul(a, b)
+            | Expr::Div(a, b)
+            | Expr::Rem(a, b)
+            | Expr::Pow(a, b) => a.uses_var(index) || b.uses_var(index),
+        }
+    }
+}
+
+/// Parse `input`; identifiers must be one of `var_names`.
+pub fn parse_expr(input: &str, var_names: &[&str]) -> Result<Expr> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0, var_names };
+    let expr = parser.expr()?;
+    if let Some(tok) = parser.peek() {
+        bail!("Unexpected {:?} after end of expression", tok);
+    }
+    Ok(expr)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(i128),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut digits = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                digits.push(d);
+                chars.next();
+            }
+            match digits.parse() {
+                Ok(n) => tokens.push(Token::Num(n)),
+                Err(_) => bail!("Number too large: {}", digits),
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut name = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_alphanumeric() || **d == '_') {
+                name.push(d);
+                chars.next();
+            }
+            tokens.push(Token::Ident(name));
+        } else {
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' | '%' | '^' => Token::Op(c),
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                _ => bail!("Unexpected character '{}'", c),
+            });
+            chars.next();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    var_names: &'a [&'a str],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        tok
+    }
+
+    /// Consume the next token if it is the operator `op`.
+    fn eat_op(&mut self, op: char) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut lhs = self.term()?;
+        loop {
+            if self.eat_op('+') {
+                lhs = Expr::Add(Box::new(lhs), Box::new(self.term()?));
+            } else if self.eat_op('-') {
+                lhs = Expr::Sub(Box::new(lhs), Box::new(self.term()?));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+        loop {
+            if self.eat_op('*') {
+                lhs = Expr::Mul(Box::new(lhs), Box::new(self.unary()?));
+            } else if self.eat_op('/') {
+                lhs = Expr::Div(Box::new(lhs), Box::new(self.unary()?));
+            } else if self.eat_op('%') {
+                lhs = Expr::Rem(Box::new(lhs), Box::new(self.unary()?));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat_op('-') {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<Expr> {
+        let base = self.atom()?;
+        if self.eat_op('^') {
+            Ok(Expr::Pow(Box::new(base), Box::new(self.unary()?)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Expr::Num(n)),
+            Some(Token::Ident(name)) => match self.var_names.iter().position(|v| *v == name) {
+                Some(i) => Ok(Expr::Var(i)),
+                None => bail!("Unknown variable '{}' (expected one of {:?})", name, self.var_names),
+            },
+            Some(Token::LParen) => {
+                let inner = self.expr()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(inner),
+                    other => bail!("Expected ')', found {:?}", other),
+                }
+            }
+            other => bail!("Expected a number, variable or '(', found {:?}", other),
+        }
+    }
+}
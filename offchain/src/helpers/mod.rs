@@ -1 +1,3 @@
 pub mod artifact;
+pub mod expr;
+pub mod rng;
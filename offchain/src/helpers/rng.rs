@@ -0,0 +1,80 @@
+//! src/helpers/rng.rs
+//! Small helpers for reproducible randomness.
+//!
+//! All generators and operators in `gp::*` already take `rng: &mut impl Rng`,
+//! so passing a seeded `StdRng` instead of `thread_rng()` is enough to make
+//! a whole run reproducible.
+
+use anyhow::{anyhow, Result};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Build a deterministic RNG from a `u64` seed.
+///
+/// Two RNGs built from the same seed yield the same sequence, so e.g. two
+/// populations generated with `ranmdom_code_fixed` are identical:
+///
+/// ```
+/// use offchain::helpers::rng::seeded_rng;
+/// use offchain::gp::generate_spec::{ramped_half_and_half, ranmdom_code_fixed, InstructionSet};
+/// use offchain::gp::mutation::mutate_by_index;
+///
+/// let population = |seed| {
+///     let mut rng = seeded_rng(seed);
+///     (0..50).map(|_| ranmdom_code_fixed(&mut rng, 10)).collect::<Vec<_>>()
+/// };
+/// assert_eq!(population(42), population(42));
+/// assert_ne!(population(42), population(43));
+///
+/// let set = InstructionSet::default();
+/// assert_eq!(
+///     ramped_half_and_half(&mut seeded_rng(42), &set, 50, 2, 5),
+///     ramped_half_and_half(&mut seeded_rng(42), &set, 50, 2, 5)
+/// );
+///
+/// // Operators applied afterwards stay in step too
+/// let (mut rng_a, mut rng_b) = (seeded_rng(42), seeded_rng(42));
+/// let a = ranmdom_code_fixed(&mut rng_a, 10);
+/// let b = ranmdom_code_fixed(&mut rng_b, 10);
+/// assert_eq!(mutate_by_index(&a, &mut rng_a, 6), mutate_by_index(&b, &mut rng_b, 6));
+/// ```
+pub fn seeded_rng(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+/// A fresh seed from entropy, for runs that weren't given one. Log it: it is
+/// all that's needed to replay the run with [`seeded_rng`].
+pub fn entropy_seed() -> u64 {
+    rand::random()
+}
+
+/// Remove `--seed N` (or `--seed=N`) from command-line `args` and return `N`,
+/// leaving the other arguments in order for positional parsing.
+///
+/// ```
+/// use offchain::helpers::rng::take_seed_flag;
+///
+/// let mut args: Vec<String> = ["bin", "x*x", "--seed", "42", "-5"].map(String::from).to_vec();
+/// assert_eq!(take_seed_flag(&mut args).unwrap(), Some(42));
+/// assert_eq!(args, ["bin", "x*x", "-5"]);
+///
+/// let mut args: Vec<String> = ["bin", "--seed=7"].map(String::from).to_vec();
+/// assert_eq!(take_seed_flag(&mut args).unwrap(), Some(7));
+/// assert_eq!(take_seed_flag(&mut args).unwrap(), None);
+/// assert!(take_seed_flag(&mut vec!["bin".into(), "--seed".into()]).is_err());
+/// ```
+pub fn take_seed_flag(args: &mut Vec<String>) -> Result<Option<u64>> {
+    let Some(i) = args.iter().position(|arg| arg == "--seed" || arg.starts_with("--seed=")) else {
+        return Ok(None);
+    };
+    let flag = args.remove(i);
+    let value = match flag.strip_prefix("--seed=") {
+        Some(value) => value.to_string(),
+        None if i < args.len() => args.remove(i),
+        None => return Err(anyhow!("--seed needs a value")),
+    };
+    value
+        .parse()
+        .map(Some)
+        .map_err(|e| anyhow!("Invalid seed '{}': {}", value, e))
+}
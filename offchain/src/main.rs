@@ -10,6 +10,7 @@ use offchain::compiler::ast::{
 };
 
 use offchain::runner::revm_runner::{EvmRunner, Push3InterpreterOutputs}; // <--- We import our EvmRunner
+use offchain::helpers::artifact::locate_interpreter_artifact;
 
 use anyhow::{anyhow, bail, Result};
 use hex;
@@ -72,9 +73,9 @@ fn main() -> Result<()> {
     // ----------------------------------------------------------------------
     // Load the creation code (bytecode) from a JSON artifact
     // ----------------------------------------------------------------------
-    let creation_hex_filename = "../onchain/out/Push3Interpreter.sol/Push3Interpreter.json";
-    let creation_json = std::fs::read_to_string(creation_hex_filename)
-        .map_err(|e| anyhow!("Failed to read JSON file {}: {}", creation_hex_filename, e))?;
+    let creation_hex_filename = locate_interpreter_artifact()?;
+    let creation_json = std::fs::read_to_string(&creation_hex_filename)
+        .map_err(|e| anyhow!("Failed to read JSON file {}: {}", creation_hex_filename.display(), e))?;
     let contract_artifact: MyContractArtifact = serde_json::from_str(&creation_json)
         .map_err(|e| anyhow!("Failed to parse JSON artifact: {}", e))?;
 
@@ -89,7 +90,7 @@ fn main() -> Result<()> {
     println!(
         "Loaded creation code ({} bytes) from {}",
         creation_bytes.len(),
-        creation_hex_filename
+        creation_hex_filename.display()
     );
 
     // ----------------------------------------------------------------------
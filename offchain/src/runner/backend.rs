@@ -0,0 +1,57 @@
+//! src/runner/backend.rs
+//!
+//! The execution backend the GP code runs programs on. [`EvmRunner`] (the
+//! deployed `Push3Interpreter` contract under REVM) is the only implementation
+//! today; a pure-Rust interpreter or a remote RPC endpoint can be swapped in by
+//! implementing [`Push3Backend`], without touching the fitness functions.
+//!
+//! [`EvmRunner`]: crate::runner::revm_runner::EvmRunner
+
+use crate::runner::revm_runner::{Push3InterpreterInputs, Push3InterpreterOutputs, RunError};
+
+/// Something that runs `runInterpreter(...)` inputs to final stacks.
+///
+/// A failed run is a [`RunError`]: `Revert` (with `out_of_gas` set for runaway
+/// programs) when the program itself failed, `Backend` when the backend could
+/// not run it at all.
+///
+/// A mock backend returning canned outputs is enough to drive the fitness
+/// functions:
+///
+/// ```
+/// use offchain::gp::fitness::{evaluate_ast_on_x, graded_fitness, graded_fitness_from_errors};
+/// use offchain::push3;
+/// use offchain::runner::backend::Push3Backend;
+/// use offchain::runner::revm_runner::{Push3InterpreterInputs, Push3InterpreterOutputs, RunError};
+///
+/// /// Answers every run with `answer` on the int stack.
+/// struct Canned {
+///     answer: i128,
+///     calls: usize,
+/// }
+///
+/// impl Push3Backend for Canned {
+///     fn run(&mut self, _inputs: &Push3InterpreterInputs) -> Result<Push3InterpreterOutputs, RunError> {
+///         self.calls += 1;
+///         Ok(Push3InterpreterOutputs {
+///             final_code_stack: Vec::new(),
+///             final_exec_stack: Vec::new(),
+///             final_int_stack: vec![self.answer],
+///             final_bool_stack: Vec::new(),
+///             saturated_ints: Vec::new(),
+///         })
+///     }
+/// }
+///
+/// let mut backend = Canned { answer: 7, calls: 0 };
+/// let ast = push3!((DUP +));
+/// assert_eq!(evaluate_ast_on_x(&mut backend, &ast, 3), Some(7));
+///
+/// let fitness = graded_fitness(&mut backend, &ast, &[(0, 7), (1, 7), (2, 7)]);
+/// assert_eq!(backend.calls, 4);
+/// assert_eq!(fitness, graded_fitness_from_errors(&[Some(0); 3]));
+/// ```
+pub trait Push3Backend {
+    /// Run the interpreter on `inputs` and return its final stacks.
+    fn run(&mut self, inputs: &Push3InterpreterInputs) -> Result<Push3InterpreterOutputs, RunError>;
+}
@@ -6,13 +6,13 @@
 
 use anyhow::{anyhow, bail, Result};
 use ethers::abi::{encode, decode, Token, ParamType};
-use ethers::types::U256;
+use ethers::types::{I256, U256};
 use ethers::utils;
 use database::CacheDB;
 use revm::{
     context::Context,
     context::{BlockEnv, TxEnv, CfgEnv},
-    context_interface::result::{ExecutionResult, Output},
+    context_interface::result::{ExecutionResult, HaltReason, Output},
     database_interface::EmptyDB,
     handler::EthHandler,
     primitives::{Bytes, TxKind}, // 4 generics
@@ -21,26 +21,156 @@ use revm::{
 };
 
 // We import your AST definitions so we can call `ast.to_bytecode()`.
-use crate::compiler::ast::{UntypedAst, Push3Ast};
+use crate::compiler::ast::{OpCode, UntypedAst, Push3Ast};
 
 // If you have a descriptor helper (like make_sublist_descriptor), bring it in:
-use crate::compiler::push3_describtor::make_sublist_descriptor;
+use crate::compiler::push3_describtor::{get_length, get_offset, get_tag, make_sublist_descriptor, TAG_SUBLIST};
+
+use crate::runner::backend::Push3Backend;
+
+/// Typed failures of `run_interpreter`, either caught before calling the
+/// contract or reported by the EVM, and of any [`Push3Backend`].
+///
+/// They are returned inside the usual `anyhow::Error`, so callers that care can
+/// `err.downcast_ref::<RunError>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunError {
+    /// `init_exec_stack[index]` is a SUBLIST whose `offset + length` runs past the end of `code`.
+    InvalidDescriptor {
+        index: usize,
+        offset: u32,
+        length: u32,
+        code_len: usize,
+    },
+    /// The call reverted or halted. `out_of_gas` is set when it used up its
+    /// whole gas limit, which for this interpreter almost always means the
+    /// program kept pushing work onto the exec stack (a loop) rather than
+    /// hitting a stack or arithmetic error.
+    Revert {
+        gas_used: u64,
+        gas_limit: u64,
+        out_of_gas: bool,
+        /// The `Error(string)` message, the raw revert data as hex, or the halt reason.
+        reason: String,
+    },
+    /// The backend could not run the program at all (e.g. the EVM itself
+    /// failed, or returned data that does not decode), as opposed to the
+    /// program reverting.
+    Backend(String),
+}
+
+impl RunError {
+    /// True for a [`RunError::Revert`] that ran out of gas.
+    pub fn is_out_of_gas(&self) -> bool {
+        matches!(self, RunError::Revert { out_of_gas: true, .. })
+    }
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::InvalidDescriptor { index, offset, length, code_len } => write!(
+                f,
+                "init_exec_stack[{index}]: sublist [{offset}, {offset}+{length}) is out of range for {code_len} bytes of code"
+            ),
+            RunError::Revert { gas_used, gas_limit, out_of_gas: true, reason } => write!(
+                f,
+                "Call ran out of gas: gas used={gas_used} of {gas_limit} ({reason})"
+            ),
+            RunError::Revert { gas_used, gas_limit, out_of_gas: false, reason } => write!(
+                f,
+                "Call reverted: gas used={gas_used} of {gas_limit}, reason: {reason}"
+            ),
+            RunError::Backend(reason) => write!(f, "Backend failed: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+/// Human-readable revert data: the message of a Solidity `Error(string)`,
+/// otherwise the raw bytes as hex.
+fn revert_reason(output: &[u8]) -> String {
+    const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0]; // Error(string)
+    if output.len() >= 4 && output[..4] == ERROR_SELECTOR {
+        if let Ok(tokens) = decode(&[ParamType::String], &output[4..]) {
+            if let Some(Token::String(message)) = tokens.into_iter().next() {
+                return message;
+            }
+        }
+    }
+    if output.is_empty() {
+        "no revert data".to_string()
+    } else {
+        format!("0x{}", hex::encode(output))
+    }
+}
+
+/// Check that every SUBLIST descriptor in `exec_stack` points inside `code`.
+fn validate_exec_stack(code: &[u8], exec_stack: &[U256]) -> std::result::Result<(), RunError> {
+    for (index, &desc) in exec_stack.iter().enumerate() {
+        if get_tag(desc) != TAG_SUBLIST {
+            continue;
+        }
+        let (offset, length) = (get_offset(desc), get_length(desc));
+        if offset as usize + length as usize > code.len() {
+            return Err(RunError::InvalidDescriptor { index, offset, length, code_len: code.len() });
+        }
+    }
+    Ok(())
+}
+
+/// The programs [`EvmRunner::self_test`] runs, with the top int each must leave:
+/// `(3 5 +)` => 8, `(10 3 -)` => 7 and `(4 DUP *)` => 16.
+pub fn self_test_cases() -> Vec<(UntypedAst, i128)> {
+    use UntypedAst::{Instruction, IntLiteral, Sublist};
+    vec![
+        (Sublist(vec![IntLiteral(3), IntLiteral(5), Instruction(OpCode::Plus)]), 8),
+        (Sublist(vec![IntLiteral(10), IntLiteral(3), Instruction(OpCode::Minus)]), 7),
+        (Sublist(vec![IntLiteral(4), Instruction(OpCode::Dup), Instruction(OpCode::Mult)]), 16),
+    ]
+}
 
 /// The input parameters for `runInterpreter(...)`: five fields (code, codeStack, execStack, intStack, boolStack).
 pub struct Push3InterpreterInputs {
     pub code: Vec<u8>,
     pub init_code_stack: Vec<U256>,
     pub init_exec_stack: Vec<U256>,
-    pub init_int_stack: Vec<i128>,
+    /// Full-width `int256` values, last on top; see [`int_stack_from_i128`]
+    /// for the common `i128` case.
+    pub init_int_stack: Vec<I256>,
     pub init_bool_stack: Vec<bool>,
 }
 
+impl Push3InterpreterInputs {
+    /// Run `code` as one whole-program sublist on the given `i128` int stack
+    /// (last on top) and bool stack, with an empty code stack.
+    pub fn for_program(code: Vec<u8>, int_stack: &[i128], bool_stack: Vec<bool>) -> Self {
+        Self {
+            init_exec_stack: vec![make_sublist_descriptor(0, code.len() as u32)],
+            code,
+            init_code_stack: Vec::new(),
+            init_int_stack: int_stack_from_i128(int_stack),
+            init_bool_stack: bool_stack,
+        }
+    }
+}
+
+/// Widen `i128` stack values to the contract's `int256`, sign-extended.
+pub fn int_stack_from_i128(values: &[i128]) -> Vec<I256> {
+    values.iter().map(|&v| I256::from(v)).collect()
+}
+
 /// The outputs from `runInterpreter(...)`: four arrays for code/exec/int/bool stacks.
+#[derive(Debug)]
 pub struct Push3InterpreterOutputs {
     pub final_code_stack: Vec<U256>,
     pub final_exec_stack: Vec<U256>,
     pub final_int_stack: Vec<i128>,
     pub final_bool_stack: Vec<bool>,
+    /// Positions in `final_int_stack` (0 is the bottom) of `int256` values that
+    /// don't fit in `i128`: they are clamped to `i128::MIN` / `i128::MAX`.
+    pub saturated_ints: Vec<usize>,
 }
 
 /// A thin wrapper around REVM, parameterized by the 4 generics (DB, BLOCK, TX, CFG).
@@ -57,14 +187,58 @@ pub struct EvmRunner {
 
     /// The address where Push3Interpreter was deployed.
     pub interpreter_addr: revm::primitives::Address,
+
+    /// Optional per-call gas limit for `run_interpreter`.
+    /// - `None` keeps the transaction's default gas limit,
+    /// - `Some(limit)` makes overly expensive programs revert (out of gas)
+    ///   deterministically instead of running to completion.
+    pub gas_limit: Option<u64>,
+
+    /// The gas limit the EVM context had right after deployment,
+    /// restored whenever `gas_limit` is `None`.
+    default_gas_limit: u64,
+
+    /// Whether `run_interpreter` calls see each other's state changes.
+    /// - `true` (default): every call starts from the post-deployment state and
+    ///   nothing it writes is kept, so calls are independent and repeatable,
+    /// - `false`: calls are committed, so storage written by one call (and the
+    ///   caller's nonce) carries over to the next.
+    ///
+    /// Switching back to `true` restores the post-deployment snapshot before the
+    /// next call. `Push3Interpreter` itself keeps no storage today, so this only
+    /// matters for stateful interpreter builds.
+    pub reset_state: bool,
+
+    /// The database right after deployment, restored when `reset_state` is set.
+    baseline_db: CacheDB<EmptyDB>,
+
+    /// Nonce for the next call: 1 on the post-deployment state, then one more
+    /// per committed call.
+    next_nonce: u64,
 }
 
 impl EvmRunner {
-    /// Deploy a new ephemeral EVM with a "Create" transaction for the given `creation_code`.
+    /// Deploy a new ephemeral EVM with a "Create" transaction for the given
+    /// `creation_code` (a `Vec<u8>`, or a slice of the buffer shared by
+    /// `artifact::cached_creation_code`).
     ///
     /// This matches your older style of:
     ///  `MainEvm::new(Context::builder()... .with_db(...), EthHandler::default())`
-    pub fn new(creation_code: Vec<u8>) -> Result<Self> {
+    ///
+    /// Empty creation code (e.g. from an artifact that was never built) is
+    /// rejected before anything is deployed:
+    ///
+    /// ```
+    /// use offchain::runner::revm_runner::EvmRunner;
+    ///
+    /// let err = EvmRunner::new(Vec::new()).err().unwrap();
+    /// assert!(err.to_string().contains("forge build"));
+    /// ```
+    pub fn new(creation_code: impl AsRef<[u8]>) -> Result<Self> {
+        let creation_code = creation_code.as_ref();
+        if creation_code.is_empty() {
+            bail!("Interpreter creation code is empty: build the contract (`forge build` in onchain/) and load it with `get_creation_code`");
+        }
         // 1) Create the EVM using your old style: `Context::builder()...`
         //    Then pass to MainEvm::new(...).
         //    The difference is that we explicitly say MainEvm<DB,BLOCK,TX,CFG>.
@@ -73,7 +247,7 @@ impl EvmRunner {
             Context::builder()
                 .modify_tx_chained(|tx| {
                     tx.transact_to = TxKind::Create;
-                    tx.data = Bytes::from(creation_code.clone());
+                    tx.data = Bytes::from(creation_code.to_vec());
                 })
                 // b) Provide ephemeral DB
                 .with_db(CacheDB::<EmptyDB>::default()),
@@ -90,20 +264,136 @@ impl EvmRunner {
         else {
             bail!("Interpreter deployment failed or no address returned: {creation_result:#?}");
         };
+        let default_gas_limit = evm.context.tx.gas_limit;
+        let baseline_db = evm.context.journaled_state.database.clone();
 
         // 3) Return the EvmRunner
         Ok(Self {
             evm,
             interpreter_addr: deployed_addr,
+            gas_limit: None,
+            default_gas_limit,
+            reset_state: true,
+            baseline_db,
+            next_nonce: 1,
         })
     }
 
+    /// Like [`EvmRunner::new`], but with the interpreter at `desired_addr`, e.g. to
+    /// match the address a contract is deployed at on-chain.
+    ///
+    /// The interpreter is deployed as usual, then (unless it already landed
+    /// there) its account is copied to `desired_addr`. The constructor only runs
+    /// once, so anything it wrote to storage stays at the original address.
+    ///
+    /// ```ignore
+    /// let desired = Address::with_last_byte(0x42);
+    /// let runner = EvmRunner::new_at(creation_bytes, desired)?;
+    /// assert_eq!(runner.interpreter_addr, desired);
+    /// ```
+    pub fn new_at(
+        creation_code: impl AsRef<[u8]>,
+        desired_addr: revm::primitives::Address,
+    ) -> Result<Self> {
+        let runner = Self::new(creation_code)?;
+        if runner.interpreter_addr == desired_addr {
+            return Ok(runner);
+        }
+
+        let mut db = runner.baseline_db;
+        let info = db
+            .accounts
+            .get(&runner.interpreter_addr)
+            .map(|account| account.info.clone())
+            .ok_or_else(|| anyhow!("Deployed interpreter account not found at {}", runner.interpreter_addr))?;
+        db.insert_account_info(desired_addr, info);
+        Ok(Self::from_deployed(db, desired_addr, runner.default_gas_limit))
+    }
+
+    /// Run a few programs with known answers ([`self_test_cases`]) and fail if
+    /// any gives a different one, e.g. right after deployment, to catch an
+    /// interpreter build whose opcode mapping no longer matches
+    /// `DefaultOpCodeMapping` before it silently scores every program wrong.
+    ///
+    /// ```ignore
+    /// let mut runner = EvmRunner::new(creation_bytes)?;
+    /// runner.self_test()?;
+    /// ```
+    pub fn self_test(&mut self) -> Result<()> {
+        self.self_test_with(&self_test_cases())
+    }
+
+    /// Like [`EvmRunner::self_test`], on the given `(program, expected top int)`
+    /// cases. The error lists every program that failed or gave another answer.
+    pub fn self_test_with(&mut self, cases: &[(UntypedAst, i128)]) -> Result<()> {
+        let mut mismatches = Vec::new();
+        for (program, expected) in cases {
+            match self.run_ast(program) {
+                Ok(outputs) => match outputs.final_int_stack.last() {
+                    Some(top) if top == expected => {}
+                    Some(top) => mismatches.push(format!("{program} gave {top}, expected {expected}")),
+                    None => mismatches.push(format!("{program} left no int, expected {expected}")),
+                },
+                Err(e) => mismatches.push(format!("{program} failed ({e}), expected {expected}")),
+            }
+        }
+        if !mismatches.is_empty() {
+            bail!("Interpreter self-test failed:\n  {}", mismatches.join("\n  "));
+        }
+        Ok(())
+    }
+
+    /// A runner on a copy of an already-deployed state: no CREATE is executed.
+    fn from_deployed(
+        db: CacheDB<EmptyDB>,
+        interpreter_addr: revm::primitives::Address,
+        default_gas_limit: u64,
+    ) -> Self {
+        let evm = MainEvm::new(Context::builder().with_db(db.clone()), EthHandler::default());
+        Self {
+            evm,
+            interpreter_addr,
+            gas_limit: None,
+            default_gas_limit,
+            reset_state: true,
+            baseline_db: db,
+            next_nonce: 1,
+        }
+    }
+
+    /// Builder-style helper to set `reset_state`.
+    ///
+    /// ```ignore
+    /// // Let a stateful interpreter accumulate storage across calls
+    /// let mut runner = EvmRunner::new(creation_bytes)?.with_reset_state(false);
+    /// ```
+    pub fn with_reset_state(mut self, reset_state: bool) -> Self {
+        self.reset_state = reset_state;
+        self
+    }
+
+    /// Builder-style helper to cap the gas of every `run_interpreter` call.
+    ///
+    /// ```ignore
+    /// let mut runner = EvmRunner::new(creation_bytes)?.with_gas_limit(1_000_000);
+    /// ```
+    pub fn with_gas_limit(mut self, gas_limit: u64) -> Self {
+        self.gas_limit = Some(gas_limit);
+        self
+    }
+
     /// Call `runInterpreter(bytes,uint256[],uint256[],int256[])` on the deployed contract,
     /// returning the final code/exec/int stacks.
+    ///
+    /// SUBLIST descriptors in `init_exec_stack` are checked against `code.len()` first;
+    /// an out-of-range one fails with [`RunError::InvalidDescriptor`] without an EVM call.
     pub fn run_interpreter(
         &mut self,
         inputs: &Push3InterpreterInputs
     ) -> Result<Push3InterpreterOutputs> {
+        // 0) Pre-flight: descriptors must reference valid code ranges
+        validate_exec_stack(&inputs.code, &inputs.init_exec_stack)?;
+
         // 1) Build function selector
         let func_selector = &utils::id("runInterpreter(bytes,uint256[],uint256[],int256[],bool[])")[0..4];
 
@@ -124,11 +414,8 @@ impl EvmRunner {
         let init_int_stack = Token::Array(
             inputs.init_int_stack
                 .iter()
-                .map(|&val_i128| {
-                    // store i128 as a U256. If you need negative values, do a two’s complement approach.
-                    let as_u128 = val_i128 as u128;
-                    Token::Int(U256::from(as_u128))
-                })
+                // Two's complement over the full 256 bits
+                .map(|&val| Token::Int(val.into_raw()))
                 .collect()
         );
         let init_bool_stack = Token::Array(
@@ -151,21 +438,37 @@ impl EvmRunner {
         let mut call_data = Vec::from(func_selector);
         call_data.extend_from_slice(&encoded_args);
 
-        // 5) Modify the transaction to CALL the deployed interpreter
+        // 5) Back to the post-deployment state if earlier calls were committed
+        if self.reset_state && self.next_nonce != 1 {
+            self.evm.context.journaled_state.database = self.baseline_db.clone();
+            self.next_nonce = 1;
+        }
+
+        // 6) Modify the transaction to CALL the deployed interpreter
+        //    (with the configured gas limit, if any)
+        let gas_limit = self.gas_limit.unwrap_or(self.default_gas_limit);
+        let nonce = self.next_nonce;
         self.evm.context.modify_tx(|tx| {
             tx.transact_to = TxKind::Call(self.interpreter_addr);
             tx.data = Bytes::from(call_data);
-            tx.nonce = 1; // increment nonce to avoid reuse
+            tx.nonce = nonce; // the deployment used nonce 0
+            tx.gas_limit = gas_limit;
         });
 
-        // 6) Execute the call
-        let call_result = self.evm.transact()?;
-        match &call_result.result {
+        // 7) Execute the call, committing it only if state is kept between calls
+        let call_result = if self.reset_state {
+            self.evm.transact()?.result
+        } else {
+            let result = self.evm.exec_commit()?;
+            self.next_nonce += 1;
+            result
+        };
+        match &call_result {
             ExecutionResult::Success {
                 output: Output::Call(return_data),
                 ..
             } => {
-                // 7) Decode (uint256[], uint256[], int256[], bool[])
+                // 8) Decode (uint256[], uint256[], int256[], bool[])
                 let param_types = &[
                     ParamType::Array(Box::new(ParamType::Uint(256))), // finalCodeStack
                     ParamType::Array(Box::new(ParamType::Uint(256))), // finalExecStack
@@ -188,18 +491,24 @@ impl EvmRunner {
                     }).collect(),
                     _ => Vec::new(),
                 };
-                let final_int_stack = match &decoded[2] {
+                let wide_ints: Vec<I256> = match &decoded[2] {
                     Token::Array(arr) => arr.iter().filter_map(|t| {
                         if let Token::Int(u256_val) = t {
-                            // read only the lower 128 bits => ignoring sign extension
-                            let lo = u256_val.low_u128();
-                            Some(lo as i128)
+                            Some(I256::from_raw(*u256_val))
                         } else {
                             None
                         }
                     }).collect(),
                     _ => Vec::new(),
                 };
+                // int256 values outside i128 are clamped, and their positions kept
+                let mut saturated_ints = Vec::new();
+                let final_int_stack = wide_ints.into_iter().enumerate().map(|(i, v)| {
+                    i128::try_from(v).unwrap_or_else(|_| {
+                        saturated_ints.push(i);
+                        if v.is_negative() { i128::MIN } else { i128::MAX }
+                    })
+                }).collect();
                 let final_bool_stack = match &decoded[3] {
                     Token::Array(arr) => arr.iter().filter_map(|t| {
                         if let Token::Bool(b) = t { Some(*b) } else { None }
@@ -212,39 +521,196 @@ impl EvmRunner {
                     final_exec_stack,
                     final_int_stack,
                     final_bool_stack,
+                    saturated_ints,
                 })
             }
-            ExecutionResult::Revert { gas_used, output } => {
-                bail!("Call reverted: gas used={gas_used:?}, output={output:?}")
+            ExecutionResult::Revert { gas_used, output } => Err(RunError::Revert {
+                gas_used: *gas_used,
+                gas_limit,
+                out_of_gas: *gas_used >= gas_limit,
+                reason: revert_reason(output),
             }
+            .into()),
+            ExecutionResult::Halt { reason, gas_used } => Err(RunError::Revert {
+                gas_used: *gas_used,
+                gas_limit,
+                out_of_gas: matches!(reason, HaltReason::OutOfGas(_)) || *gas_used >= gas_limit,
+                reason: format!("halted: {reason:?}"),
+            }
+            .into()),
             other => {
                 bail!("Call failed: {other:?}")
             }
         }
     }
 
+    /// A convenience method to run raw Push3 bytecode as one program:
+    /// - Build a whole-program sublist descriptor in the exec stack,
+    /// - Seed the int stack with `int_stack` (last element on top),
+    /// - Call `run_interpreter`.
+    ///
+    /// ```ignore
+    /// // (3 5 +): two int literals and the PLUS instruction inside one sublist
+    /// let code = UntypedAst::Sublist(vec![
+    ///     UntypedAst::IntLiteral(3),
+    ///     UntypedAst::IntLiteral(5),
+    ///     UntypedAst::Instruction(OpCode::Plus),
+    /// ]).to_bytecode();
+    /// let outputs = runner.run_bytecode(&code, Vec::new())?;
+    /// assert_eq!(outputs.final_int_stack.last(), Some(&8));
+    /// ```
+    pub fn run_bytecode(&mut self, code: &[u8], int_stack: Vec<i128>) -> Result<Push3InterpreterOutputs> {
+        self.run_program(code.to_vec(), int_stack, Vec::new())
+    }
+
     /// A convenience method to run an `UntypedAst`:
     /// - Convert AST => push3 code,
-    /// - Build a sublist descriptor in the exec stack,
-    /// - Call `run_interpreter`.
+    /// - Run it on empty int/bool stacks.
+    ///
+    /// A program that runs to the end is `Ok`, even if it leaves every stack
+    /// empty (`(5 POP)` gives an empty `final_int_stack`); `Err` means the call
+    /// itself failed, usually a [`RunError::Revert`]. `EvalOutcome::from_run`
+    /// tells these cases apart.
     pub fn run_ast(&mut self, ast: &UntypedAst) -> Result<Push3InterpreterOutputs> {
-        // 1) Convert AST => push3 bytecode
-        let code_bytes = ast.to_bytecode();
-        let code_len = code_bytes.len() as u32;
+        self.run_ast_with_stacks(ast, Vec::new(), Vec::new())
+    }
 
-        // 2) Build a sublist descriptor
-        let descriptor = make_sublist_descriptor(0, code_len);
+    /// Like `run_ast`, but seeds the int and bool stacks (last element on top),
+    /// e.g. to give `IfThen` / `IfElse` programs a condition.
+    ///
+    /// ```ignore
+    /// // (IfElse 1 2): true takes the first branch
+    /// let ast = UntypedAst::Sublist(vec![
+    ///     UntypedAst::Instruction(OpCode::IfElse),
+    ///     UntypedAst::IntLiteral(1),
+    ///     UntypedAst::IntLiteral(2),
+    /// ]);
+    /// let outputs = runner.run_ast_with_stacks(&ast, Vec::new(), vec![true])?;
+    /// assert_eq!(outputs.final_int_stack, vec![1]);
+    /// ```
+    pub fn run_ast_with_stacks(
+        &mut self,
+        ast: &UntypedAst,
+        int_stack: Vec<i128>,
+        bool_stack: Vec<bool>,
+    ) -> Result<Push3InterpreterOutputs> {
+        self.run_program(ast.to_bytecode(), int_stack, bool_stack)
+    }
 
-        // 3) Put code in `code` and sublist descriptor in `execStack`
-        let inputs = Push3InterpreterInputs {
-            code: code_bytes,
-            init_code_stack: Vec::new(),
-            init_exec_stack: vec![descriptor],
-            init_int_stack: Vec::new(),
-            init_bool_stack: Vec::new(),
-        };
+    /// Run `code` as a single whole-program sublist on the given stacks.
+    fn run_program(
+        &mut self,
+        code: Vec<u8>,
+        int_stack: Vec<i128>,
+        bool_stack: Vec<bool>,
+    ) -> Result<Push3InterpreterOutputs> {
+        self.run_interpreter(&Push3InterpreterInputs::for_program(code, &int_stack, bool_stack))
+    }
+}
 
-        // 4) Run interpreter
-        self.run_interpreter(&inputs)
+impl Push3Backend for EvmRunner {
+    /// [`EvmRunner::run_interpreter`], with any failure that is not already a
+    /// [`RunError`] reported as [`RunError::Backend`].
+    fn run(&mut self, inputs: &Push3InterpreterInputs) -> std::result::Result<Push3InterpreterOutputs, RunError> {
+        self.run_interpreter(inputs).map_err(|e| match e.downcast::<RunError>() {
+            Ok(run_error) => run_error,
+            Err(e) => RunError::Backend(e.to_string()),
+        })
+    }
+}
+
+/// Hands out `EvmRunner`s without redeploying the interpreter each time.
+///
+/// The interpreter is deployed once; the resulting database (the deployed code
+/// and the deployer's nonce) is kept, and every [`EvmRunnerPool::runner`] gets
+/// its own copy of it. Runners are fully independent of each other and of the
+/// pool, e.g. one per island of a parallel run.
+///
+/// ```ignore
+/// let pool = EvmRunnerPool::new(creation_bytes)?;
+/// let mut runners: Vec<EvmRunner> = (0..8).map(|_| pool.runner()).collect();
+/// ```
+pub struct EvmRunnerPool {
+    db: CacheDB<EmptyDB>,
+    interpreter_addr: revm::primitives::Address,
+    default_gas_limit: u64,
+}
+
+impl EvmRunnerPool {
+    /// Deploy the interpreter once and keep the post-deployment state.
+    pub fn new(creation_code: impl AsRef<[u8]>) -> Result<Self> {
+        Ok(Self::from_runner(&EvmRunner::new(creation_code)?))
+    }
+
+    /// Share the deployment of an existing runner: the baseline is its
+    /// post-deployment state, whatever calls it has committed since.
+    pub fn from_runner(runner: &EvmRunner) -> Self {
+        Self {
+            db: runner.baseline_db.clone(),
+            interpreter_addr: runner.interpreter_addr,
+            default_gas_limit: runner.default_gas_limit,
+        }
+    }
+
+    /// A new runner on a copy of the baseline state (no gas limit set).
+    pub fn runner(&self) -> EvmRunner {
+        EvmRunner::from_deployed(self.db.clone(), self.interpreter_addr, self.default_gas_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::artifact::{get_creation_code, locate_interpreter_artifact};
+
+    fn deployed() -> EvmRunner {
+        EvmRunner::new(get_creation_code(locate_interpreter_artifact().unwrap()).unwrap()).unwrap()
+    }
+
+    /// The nonce of the calling account in the runner's current database.
+    fn caller_nonce(runner: &EvmRunner) -> u64 {
+        let caller = runner.evm.context.tx.caller;
+        runner.evm.context.journaled_state.database.accounts.get(&caller).map_or(0, |account| account.info.nonce)
+    }
+
+    #[test]
+    fn validate_exec_stack_checks_sublist_range() {
+        let code = vec![0u8; 10];
+
+        assert_eq!(validate_exec_stack(&code, &[make_sublist_descriptor(2, 8)]), Ok(()));
+        assert_eq!(
+            validate_exec_stack(&code, &[make_sublist_descriptor(0, 10), make_sublist_descriptor(4, 7)]),
+            Err(RunError::InvalidDescriptor { index: 1, offset: 4, length: 7, code_len: 10 })
+        );
+    }
+
+    #[test]
+    fn committed_calls_advance_nonce() {
+        let mut runner = deployed().with_reset_state(false);
+        let start = caller_nonce(&runner);
+        let (program, sum) = self_test_cases().swap_remove(0);
+
+        for calls in 1..=2 {
+            assert_eq!(runner.run_ast(&program).unwrap().final_int_stack, vec![sum]);
+            assert_eq!(runner.next_nonce, 1 + calls);
+            assert_eq!(caller_nonce(&runner), start + calls);
+        }
+    }
+
+    #[test]
+    fn reset_calls_restore_baseline() {
+        let mut runner = deployed().with_reset_state(false);
+        let start = caller_nonce(&runner);
+        let (program, sum) = self_test_cases().swap_remove(0);
+        runner.run_ast(&program).unwrap();
+        assert_eq!(caller_nonce(&runner), start + 1);
+
+        // The committed call is rolled back before the next one, and nothing sticks after
+        runner.reset_state = true;
+        for _ in 0..2 {
+            assert_eq!(runner.run_ast(&program).unwrap().final_int_stack, vec![sum]);
+            assert_eq!(runner.next_nonce, 1);
+            assert_eq!(caller_nonce(&runner), start);
+        }
     }
 }
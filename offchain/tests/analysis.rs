@@ -0,0 +1,30 @@
+// tests/analysis.rs
+//
+// Analysis helpers on programs run by the deployed interpreter.
+
+mod common;
+
+use offchain::gp::analysis::{sample_rows, sample_rows_with, stack_rows, StackRow};
+use offchain::gp::fitness::AnswerPolicy;
+use offchain::push3;
+
+#[test]
+fn bool_results_are_read_with_top_bool() {
+    let mut runner = common::runner();
+    // Leaves x > 5 on the bool stack and nothing on the int stack
+    let ast = push3!((5 >));
+    let xs = [3, 5, 7];
+
+    let rows = stack_rows(&mut runner, &ast, &xs);
+    let expected: Vec<StackRow> = xs
+        .iter()
+        .map(|&x| StackRow { x, final_stacks: Some((Vec::new(), vec![x > 5])) })
+        .collect();
+    assert_eq!(rows, expected);
+
+    let samples: Vec<(i128, i128)> = xs.iter().map(|&x| (x, (x > 5) as i128)).collect();
+    assert!(sample_rows(&mut runner, &ast, &samples).iter().all(|row| row.predicted.is_none()));
+    for row in sample_rows_with(&mut runner, &ast, &samples, AnswerPolicy::TopBool) {
+        assert_eq!(row.predicted, Some(row.target), "x = {}", row.x);
+    }
+}
@@ -0,0 +1,55 @@
+// tests/bytecode.rs
+//
+// `to_bytecode` / `bytecode_to_ast` round trips on generated ASTs.
+
+use proptest::prelude::*;
+
+use offchain::compiler::ast::{bytecode_to_ast, DecodeError, OpCode, Push3Ast, UntypedAst, MAX_PARSE_DEPTH};
+
+/// Any AST up to 8 sublists deep: literals over the whole `i32` range (the
+/// extremes and -1 / 0 favoured), every opcode, and sublists of 0 to 4 children.
+fn any_ast() -> impl Strategy<Value = UntypedAst> {
+    let literal = prop_oneof![
+        3 => any::<i32>(),
+        1 => prop::sample::select(vec![i32::MIN, -1, 0, i32::MAX]),
+    ];
+    let leaf = prop_oneof![
+        literal.prop_map(UntypedAst::IntLiteral),
+        prop::sample::select(OpCode::ALL.to_vec()).prop_map(UntypedAst::Instruction),
+    ];
+    leaf.prop_recursive(8, 256, 4, |inner| {
+        prop::collection::vec(inner, 0..=4).prop_map(UntypedAst::Sublist)
+    })
+}
+
+/// `depth` sublists, each holding only the next, around `(1 -)`.
+fn nested(depth: usize) -> UntypedAst {
+    let mut ast = UntypedAst::Sublist(vec![UntypedAst::IntLiteral(1), UntypedAst::Instruction(OpCode::Minus)]);
+    for _ in 1..depth {
+        ast = UntypedAst::Sublist(vec![ast]);
+    }
+    ast
+}
+
+proptest! {
+    #[test]
+    fn any_ast_round_trips(ast in any_ast()) {
+        prop_assert_eq!(bytecode_to_ast(&ast.to_bytecode()), Ok(ast));
+    }
+
+    #[test]
+    fn nesting_within_the_limit_round_trips(depth in 1..=MAX_PARSE_DEPTH) {
+        let ast = nested(depth);
+        prop_assert_eq!(bytecode_to_ast(&ast.to_bytecode()), Ok(ast));
+    }
+}
+
+#[test]
+fn nesting_past_the_limit_is_rejected() {
+    // Every sublist header is 3 bytes, so the one too many starts right after them
+    let too_deep = nested(MAX_PARSE_DEPTH + 1).to_bytecode();
+    assert_eq!(
+        bytecode_to_ast(&too_deep),
+        Err(DecodeError::TooDeep { offset: 3 * MAX_PARSE_DEPTH })
+    );
+}
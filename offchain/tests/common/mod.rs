@@ -0,0 +1,23 @@
+// tests/common/mod.rs
+//
+// Shared setup for the integration tests that run programs on the deployed
+// interpreter. They need the Forge artifact (`forge build` in onchain/, or
+// PUSH3_ARTIFACT pointing at it).
+
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use offchain::helpers::artifact::{cached_creation_code, locate_interpreter_artifact};
+use offchain::runner::revm_runner::EvmRunner;
+
+/// The interpreter's creation code, read once per test binary.
+pub fn creation_code() -> Arc<Vec<u8>> {
+    let artifact = locate_interpreter_artifact().expect("interpreter artifact");
+    cached_creation_code(artifact).expect("interpreter creation code")
+}
+
+/// A freshly deployed interpreter.
+pub fn runner() -> EvmRunner {
+    EvmRunner::new(creation_code().as_slice()).expect("interpreter deployment")
+}
@@ -0,0 +1,210 @@
+// tests/engine.rs
+//
+// Short `Evolution` runs scored on the deployed interpreter.
+
+mod common;
+
+use std::path::PathBuf;
+
+use offchain::compiler::ast::UntypedAst;
+use offchain::gp::engine::{Checkpoint, Elitism, Evolution, EvolutionConfig, EvolutionResult, SelectionCriterion};
+use offchain::gp::fitness::{evaluate_errors, graded_fitness};
+use offchain::gp::mutation::get_subtree_size;
+use offchain::gp::population_management::{sort_population, Individual};
+use offchain::runner::revm_runner::EvmRunner;
+
+/// f(x) = 2x + 1 on a few points.
+fn samples() -> Vec<(i128, i128)> {
+    (-3..=3).map(|x| (x, 2 * x + 1)).collect()
+}
+
+fn tiny_config() -> EvolutionConfig {
+    EvolutionConfig {
+        pop_size: 8,
+        generations: 3,
+        max_points: 8,
+        max_size: 16,
+        elitism: Elitism::Top(2),
+        seed: Some(7),
+        verbose: false,
+        ..EvolutionConfig::default()
+    }
+}
+
+fn checkpoint_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("push3_engine_{}_{}.json", name, std::process::id()))
+}
+
+/// What must agree between two runs: programs, fitness and ages, in order.
+fn snapshot(population: &[Individual]) -> Vec<(UntypedAst, f64, u32)> {
+    population.iter().map(|i| (i.ast.clone(), i.fitness, i.age)).collect()
+}
+
+#[test]
+fn tiny_run_keeps_its_best() {
+    let mut runner = common::runner();
+    let samples = samples();
+    let result = Evolution::new(tiny_config())
+        .unwrap()
+        .evolve(&mut runner, |runner, ast| graded_fitness(runner, ast, &samples));
+
+    assert_eq!(result.generations_run, 3);
+    assert_eq!(result.seed, 7);
+    assert_eq!(result.population.len(), 8);
+    assert_eq!(result.best_fitness_history.len(), 3);
+    assert!(
+        result.best_fitness_history.windows(2).all(|w| w[1] >= w[0]),
+        "best fitness dropped: {:?}",
+        result.best_fitness_history
+    );
+    let best_seen = result.best_fitness_history.iter().copied().fold(f64::MIN, f64::max);
+    assert!(result.best.fitness >= best_seen);
+    assert_eq!(result.best.fitness, graded_fitness(&mut runner, &result.best.ast, &samples));
+}
+
+#[test]
+fn resumed_run_matches_uninterrupted_one() {
+    let mut runner = common::runner();
+    let samples = samples();
+    let run = |config: EvolutionConfig, resume: Option<&PathBuf>, runner: &mut _| {
+        let mut evolution = Evolution::new(config).unwrap();
+        if let Some(path) = resume {
+            evolution = evolution.resume_from(path).unwrap();
+        }
+        evolution.evolve(runner, |runner, ast| graded_fitness(runner, ast, &samples))
+    };
+    let config = |generations, name| EvolutionConfig {
+        generations,
+        checkpoint_every: Some(3),
+        checkpoint_path: checkpoint_path(name),
+        ..tiny_config()
+    };
+
+    let uninterrupted = run(config(6, "full"), None, &mut runner);
+
+    // Stop after 3 generations, where the checkpoint is written
+    let first_half = run(config(3, "half"), None, &mut runner);
+    let mut checkpoint = Checkpoint::load(checkpoint_path("half")).unwrap();
+    assert_eq!(checkpoint.generation, 3);
+    sort_population(&mut checkpoint.population);
+    assert_eq!(snapshot(&checkpoint.population), snapshot(&first_half.population));
+    assert_eq!(checkpoint.best_fitness_history, first_half.best_fitness_history);
+
+    let resumed = run(config(6, "resumed"), Some(&checkpoint_path("half")), &mut runner);
+    assert_eq!(resumed.generations_run, 6);
+    assert_eq!(resumed.best_fitness_history, uninterrupted.best_fitness_history);
+    assert_eq!(snapshot(&resumed.population), snapshot(&uninterrupted.population));
+    assert_eq!(resumed.best.ast, uninterrupted.best.ast);
+
+    for name in ["full", "half", "resumed"] {
+        let _ = std::fs::remove_file(checkpoint_path(name));
+    }
+}
+
+#[test]
+fn reported_seed_replays_the_run() {
+    let mut runner = common::runner();
+    let samples: Vec<(i128, i128)> = (-3..=3).map(|x| (x, x * x + 1)).collect();
+    let mut run = |seed| {
+        let config = EvolutionConfig { pop_size: 30, generations: 5, seed, verbose: false, ..EvolutionConfig::default() };
+        Evolution::new(config).unwrap().evolve(&mut runner, |runner, ast| graded_fitness(runner, ast, &samples))
+    };
+
+    let original = run(None);
+    let replay = run(Some(original.seed));
+    assert_eq!(replay.seed, original.seed);
+    assert_eq!(replay.best.ast, original.best.ast);
+    assert_eq!(replay.best.fitness, original.best.fitness);
+}
+
+#[test]
+fn sample_subset_saves_calls_but_reports_full_fitness() {
+    /// The run's result and how many (program, sample) evaluations it made.
+    fn run(runner: &mut EvmRunner, samples: &[(i128, i128)], sample_subset: Option<usize>) -> (EvolutionResult, usize) {
+        let config = EvolutionConfig {
+            pop_size: 40,
+            generations: 8,
+            sample_subset,
+            full_sample_every: 4,
+            hall_of_fame_size: 3,
+            seed: Some(7),
+            verbose: false,
+            ..EvolutionConfig::default()
+        };
+        let mut calls = 0;
+        let result = Evolution::new(config).unwrap().evolve_on_samples(runner, samples, |runner, ast, chosen| {
+            calls += chosen.len();
+            evaluate_errors(runner, ast, chosen)
+        });
+        (result, calls)
+    }
+
+    let mut runner = common::runner();
+    let samples: Vec<(i128, i128)> = (-20..=20).map(|x| (x, x * x - 3 * x + 2)).collect();
+    let (_, full_calls) = run(&mut runner, &samples, None);
+    let (sub, sub_calls) = run(&mut runner, &samples, Some(6));
+    assert!(sub_calls < full_calls, "{} vs {}", sub_calls, full_calls);
+
+    for individual in std::iter::once(&sub.best).chain(sub.hall_of_fame.to_vec().iter()) {
+        assert_eq!(individual.fitness, graded_fitness(&mut runner, &individual.ast, &samples), "{}", individual.ast);
+    }
+}
+
+#[test]
+fn select_by_picks_the_reported_best() {
+    // Training rewards large programs, validation small ones
+    fn run(runner: &mut EvmRunner, select_by: SelectionCriterion) -> EvolutionResult {
+        let config = EvolutionConfig {
+            pop_size: 30,
+            generations: 6,
+            select_by,
+            seed: Some(5),
+            verbose: false,
+            ..EvolutionConfig::default()
+        };
+        Evolution::new(config)
+            .unwrap()
+            .validate_with(|_, ast| -(get_subtree_size(ast) as f64))
+            .evolve(runner, |_, ast| get_subtree_size(ast) as f64)
+    }
+
+    let mut runner = common::runner();
+    let train = run(&mut runner, SelectionCriterion::Train);
+    let val = run(&mut runner, SelectionCriterion::Validation);
+    assert!(val.best.size < train.best.size, "{} vs {}", val.best.size, train.best.size);
+
+    let best_seen = val
+        .generation_stats
+        .iter()
+        .filter_map(|stats| stats.validation_fitness)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let reported = val.best_validation_fitness.expect("validation fitness");
+    assert!(reported >= best_seen, "reported {}, best seen {}", reported, best_seen);
+    assert_eq!(reported, -(val.best.size as f64));
+}
+
+#[test]
+fn simplify_best_keeps_fitness_and_size() {
+    let mut runner = common::runner();
+    let samples: Vec<(i128, i128)> = (-5..=5).map(|x| (x, 2 * x * x + 1)).collect();
+    let config = EvolutionConfig {
+        pop_size: 60,
+        generations: 10,
+        max_points: 20,
+        simplify_best: true,
+        seed: Some(11),
+        verbose: false,
+        ..EvolutionConfig::default()
+    };
+    let result = Evolution::new(config)
+        .unwrap()
+        .evolve_on_errors(&mut runner, |runner, ast| evaluate_errors(runner, ast, &samples));
+
+    let raw = result.raw_best.as_ref().expect("simplify_best reports the raw best");
+    let best = &result.best;
+    let raw_fitness = graded_fitness(&mut runner, &raw.ast, &samples);
+    let best_fitness = graded_fitness(&mut runner, &best.ast, &samples);
+    assert!(best_fitness >= raw_fitness, "{} < {}", best_fitness, raw_fitness);
+    assert!(best.size <= raw.size, "{} > {}", best.size, raw.size);
+    assert_eq!(best.fitness, best_fitness);
+}
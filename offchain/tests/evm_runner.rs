@@ -0,0 +1,184 @@
+// tests/evm_runner.rs
+//
+// `EvmRunner` against the deployed interpreter.
+
+mod common;
+
+use ethers::types::I256;
+use revm::primitives::Address;
+
+use offchain::compiler::ast::{OpCode, Push3Ast, UntypedAst};
+use offchain::compiler::push3_describtor::{code_stack_from_asts, decode_stack, exec_stack_from_asts};
+use offchain::push3;
+use offchain::runner::revm_runner::{
+    self_test_cases, EvmRunner, EvmRunnerPool, Push3InterpreterInputs, Push3InterpreterOutputs, RunError,
+};
+
+/// `(1 + (1 + (... (1 +))))`, `depth` sublists deep: adds `depth` to the top int.
+fn nested_increments(depth: usize) -> UntypedAst {
+    let mut ast = push3!((1 +));
+    for _ in 1..depth {
+        ast = UntypedAst::Sublist(vec![UntypedAst::IntLiteral(1), UntypedAst::Instruction(OpCode::Plus), ast]);
+    }
+    ast
+}
+
+#[test]
+fn gas_limit_stops_expensive_program() {
+    let ast = nested_increments(200);
+
+    // Each level is a real sublist: no bool literal read from its header
+    let outputs = common::runner().run_ast_with_stacks(&ast, vec![0], Vec::new()).unwrap();
+    assert_eq!(outputs.final_int_stack, vec![200]);
+    assert!(outputs.final_bool_stack.is_empty());
+
+    // Enough for the intrinsic cost of the call (21000 + at most 16 per byte
+    // of calldata), far too little to interpret 600 instructions
+    let calldata_bound = ast.to_bytecode().len() as u64 + 512;
+    let gas_limit = 21_000 + 16 * calldata_bound + 20_000;
+    let mut limited = common::runner().with_gas_limit(gas_limit);
+    let err = limited.run_ast_with_stacks(&ast, vec![0], Vec::new()).unwrap_err();
+    match err.downcast_ref::<RunError>() {
+        Some(RunError::Revert { out_of_gas: true, gas_limit: limit, .. }) => assert_eq!(*limit, gas_limit),
+        other => panic!("expected an out-of-gas revert, got {:?} ({})", other, err),
+    }
+}
+
+#[test]
+fn run_bytecode_adds() {
+    // (3 5 +): two int literals and the PLUS instruction inside one sublist
+    let code = push3!((3 5 +)).to_bytecode();
+    let outputs = common::runner().run_bytecode(&code, Vec::new()).unwrap();
+    assert_eq!(outputs.final_int_stack.last(), Some(&8));
+}
+
+#[test]
+fn if_else_follows_seeded_bool() {
+    let mut runner = common::runner();
+    let ast = UntypedAst::Sublist(vec![
+        UntypedAst::Instruction(OpCode::IfElse),
+        UntypedAst::IntLiteral(1),
+        UntypedAst::IntLiteral(2),
+    ]);
+
+    let outputs = runner.run_ast_with_stacks(&ast, Vec::new(), vec![true]).unwrap();
+    assert_eq!(outputs.final_int_stack, vec![1]);
+    let outputs = runner.run_ast_with_stacks(&ast, Vec::new(), vec![false]).unwrap();
+    assert_eq!(outputs.final_int_stack, vec![2]);
+}
+
+#[test]
+fn pooled_runners_match_fresh_deployment() {
+    let ast = push3!((3 5 + DUP *));
+    let stacks = |outputs: Push3InterpreterOutputs| (outputs.final_int_stack, outputs.final_bool_stack);
+    let expected = stacks(common::runner().run_ast(&ast).unwrap());
+    assert_eq!(expected, (vec![64], Vec::new()));
+
+    let pool = EvmRunnerPool::new(common::creation_code().as_slice()).unwrap();
+    let mut committing = pool.runner().with_reset_state(false);
+    let mut other = pool.runner();
+    for _ in 0..3 {
+        assert_eq!(stacks(committing.run_ast(&ast).unwrap()), expected);
+    }
+    // The calls committed by the first runner are not seen by the second
+    assert_eq!(stacks(other.run_ast(&ast).unwrap()), expected);
+    assert_eq!(stacks(pool.runner().run_ast(&ast).unwrap()), expected);
+}
+
+#[test]
+fn exec_stack_runs_programs_in_order() {
+    // (3 5 +) leaves 8, then (DUP *) squares it. In the opposite order the
+    // result would be 8.
+    let first = push3!((3 5 +));
+    let second = push3!((DUP *));
+
+    let mut code = Vec::new();
+    let init_exec_stack = exec_stack_from_asts(&[&first, &second], &mut code);
+    let inputs = Push3InterpreterInputs {
+        code,
+        init_code_stack: Vec::new(),
+        init_exec_stack,
+        init_int_stack: Vec::new(),
+        init_bool_stack: Vec::new(),
+    };
+
+    let outputs = common::runner().run_interpreter(&inputs).unwrap();
+    assert_eq!(outputs.final_int_stack, vec![64]);
+    // Sublist headers are framed as sublists, not read as bool literals
+    assert!(outputs.final_bool_stack.is_empty());
+}
+
+#[test]
+fn wide_ints_reach_the_interpreter() {
+    let mut runner = common::runner();
+    let above_max = I256::from(i128::MAX) + I256::one();
+    let below_min = I256::from(i128::MIN) - I256::one();
+    let cases = [
+        // (program, pushed value, expected top)
+        (push3!((1 -)), above_max, i128::MAX),
+        (push3!((1 +)), below_min, i128::MIN),
+        // Only right if -7 was sign-extended rather than sent as 2^128 - 7
+        (push3!((ABS)), I256::from(-7), 7),
+    ];
+
+    for (ast, value, expected) in cases {
+        let mut inputs = Push3InterpreterInputs::for_program(ast.to_bytecode(), &[], Vec::new());
+        inputs.init_int_stack = vec![value];
+        let outputs = runner.run_interpreter(&inputs).unwrap();
+        assert_eq!(outputs.final_int_stack, vec![expected], "{} on [{}]", ast, value);
+        assert!(outputs.saturated_ints.is_empty());
+    }
+
+    // Left as it is, the wide value is clamped and flagged
+    let mut inputs = Push3InterpreterInputs::for_program(push3!((NOOP)).to_bytecode(), &[], Vec::new());
+    inputs.init_int_stack = vec![I256::from(3), above_max];
+    let outputs = runner.run_interpreter(&inputs).unwrap();
+    assert_eq!(outputs.final_int_stack, vec![3, i128::MAX]);
+    assert_eq!(outputs.saturated_ints, vec![1]);
+}
+
+#[test]
+fn code_stack_comes_back_decoded() {
+    // Nothing touches the code stack, so the sublist should come back as-is
+    let program = push3!((3 5 +));
+    let kept = push3!((DUP (2 *) -7));
+
+    let mut code = Vec::new();
+    let init_exec_stack = exec_stack_from_asts(&[&program], &mut code);
+    let init_code_stack = code_stack_from_asts(&[&kept], &mut code);
+    let inputs = Push3InterpreterInputs {
+        code: code.clone(),
+        init_code_stack,
+        init_exec_stack,
+        init_int_stack: Vec::new(),
+        init_bool_stack: Vec::new(),
+    };
+
+    let outputs = common::runner().run_interpreter(&inputs).unwrap();
+    assert_eq!(decode_stack(&outputs.final_code_stack, &code).unwrap(), vec![kept]);
+    assert_eq!(outputs.final_int_stack, vec![8]);
+}
+
+#[test]
+fn new_at_deploys_at_the_pinned_address() {
+    let desired = Address::with_last_byte(0x42);
+    let mut runner = EvmRunner::new_at(common::creation_code().as_slice(), desired).unwrap();
+    assert_eq!(runner.interpreter_addr, desired);
+    assert_eq!(runner.run_ast(&push3!((3 5 +))).unwrap().final_int_stack, vec![8]);
+
+    // Runners shared from a pool keep the pinned address
+    let pooled = EvmRunnerPool::from_runner(&runner).runner();
+    assert_eq!(pooled.interpreter_addr, desired);
+}
+
+#[test]
+fn self_test_names_a_wrong_expectation() {
+    let mut runner = common::runner();
+    runner.self_test().unwrap();
+
+    // (3 5 +) is 8, not 9
+    let mut cases = self_test_cases();
+    cases.push((push3!((3 5 +)), 9));
+    let err = runner.self_test_with(&cases).unwrap_err();
+    assert!(err.to_string().contains("gave 8, expected 9"), "unexpected error: {}", err);
+}
@@ -0,0 +1,68 @@
+// tests/fitness.rs
+//
+// Fitness helpers against the deployed interpreter.
+
+mod common;
+
+use offchain::gp::fitness::{
+    evaluate_errors_with_options, graded_fitness_from_errors, safe_eval, AnswerPolicy, EvalOptions, EvalOutcome,
+};
+use offchain::push3;
+
+#[test]
+fn safe_eval_tells_outcomes_apart() {
+    let mut runner = common::runner();
+
+    assert_eq!(safe_eval(&mut runner, &push3!((3 5 +)), &[]), EvalOutcome::Value(8));
+    assert_eq!(safe_eval(&mut runner, &push3!((POP)), &[4]), EvalOutcome::EmptyStack);
+    // (2^127)^4 overflows int256, and the interpreter's arithmetic is checked
+    assert_eq!(
+        safe_eval(&mut runner, &push3!((DUP * DUP *)), &[i128::MAX]),
+        EvalOutcome::Reverted
+    );
+}
+
+#[test]
+fn empty_stack_is_a_successful_run() {
+    let mut runner = common::runner();
+
+    // POP on a one-element stack: runs fine, nothing left
+    let run = runner.run_ast(&push3!((5 POP)));
+    assert_eq!(run.as_ref().unwrap().final_int_stack, Vec::<i128>::new());
+    assert_eq!(EvalOutcome::from_run(&run, AnswerPolicy::TopInt), EvalOutcome::EmptyStack);
+
+    // 2^300 overflows int256, and checked arithmetic reverts
+    let run = runner.run_ast(&push3!((2 300 POW)));
+    assert_eq!(EvalOutcome::from_run(&run, AnswerPolicy::TopInt), EvalOutcome::Reverted);
+}
+
+#[test]
+fn replicated_input_feeds_dup_free_programs() {
+    let mut runner = common::runner();
+    // x^3 without DUP needs x three times on the stack
+    let cube = push3!((* *));
+    let samples: Vec<(i128, i128)> = (-4..=4).map(|x| (x, x * x * x)).collect();
+
+    let triple = EvalOptions { replicate_input: 3, ..EvalOptions::default() };
+    let errors = evaluate_errors_with_options(&mut runner, &cube, &samples, &triple);
+    assert!(errors.iter().all(|&e| e == Some(0)), "errors {:?}", errors);
+
+    let single = evaluate_errors_with_options(&mut runner, &cube, &samples, &EvalOptions::default());
+    assert!(single.iter().any(|&e| e != Some(0)), "errors {:?}", single);
+}
+
+#[test]
+fn static_prune_scores_like_the_run() {
+    let mut runner = common::runner();
+    let samples: Vec<(i128, i128)> = (-5..=5).map(|x| (x, x * x + 1)).collect();
+    let pruning = EvalOptions { static_prune: true, ..EvalOptions::default() };
+
+    // Provably pops its only input, so it is never sent to the EVM
+    let popped = push3!((POP));
+    assert!(pruning.provably_no_answer(&popped));
+
+    let ran = evaluate_errors_with_options(&mut runner, &popped, &samples, &EvalOptions::default());
+    let pruned = evaluate_errors_with_options(&mut runner, &popped, &samples, &pruning);
+    assert_eq!(pruned, ran);
+    assert_eq!(graded_fitness_from_errors(&pruned), graded_fitness_from_errors(&ran));
+}
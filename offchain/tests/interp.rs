@@ -0,0 +1,44 @@
+// tests/interp.rs
+//
+// The pure-Rust interpreter against the deployed one, on random programs.
+
+mod common;
+
+use rand::Rng;
+
+use offchain::compiler::ast::OpCode;
+use offchain::compiler::interp::InterpError;
+use offchain::gp::generate_spec::{random_code, InstructionSet};
+use offchain::gp::validation::{diff_test, DiffResult};
+use offchain::helpers::rng::seeded_rng;
+use offchain::push3;
+
+#[test]
+fn eval_pure_matches_evm_on_random_programs() {
+    let mut runner = common::runner();
+    // Nested sublists, empty ones included, must run the same on chain
+    for ast in [push3!((1 (2 +) DUP *)), push3!(((3) () ((DUP) +))), push3!((5 > (IF_ELSE 1 2)))] {
+        assert_eq!(diff_test(&ast, &[4], &mut runner), DiffResult::Agree, "{}", ast);
+    }
+
+    // RAND depends on block data, so the pure interpreter would skip it
+    let set = InstructionSet::without(&[OpCode::ConstRand]);
+    let mut rng = seeded_rng(1544);
+    let num_programs = 300;
+    let mut agreed = 0;
+
+    for i in 0..num_programs {
+        let ast = random_code(&mut rng, &set, 12);
+        let x: i128 = rng.gen_range(-50..=50);
+
+        match diff_test(&ast, &[x], &mut runner) {
+            DiffResult::Agree => agreed += 1,
+            DiffResult::BothFailed => {}
+            // Values between i128 and int256 only overflow off chain (see `interp`)
+            DiffResult::OnlyPureFailed(InterpError::Overflow(_)) => {}
+            other => panic!("program #{} {} diverges on x = {}: {:?}", i, ast, x, other),
+        }
+    }
+    // Most random programs run without overflowing
+    assert!(agreed > num_programs / 2, "only {} of {} programs agreed", agreed, num_programs);
+}
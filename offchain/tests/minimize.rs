@@ -0,0 +1,32 @@
+// tests/minimize.rs
+//
+// `minimize` against the deployed interpreter.
+
+mod common;
+
+use offchain::compiler::ast::UntypedAst;
+use offchain::gp::fitness::evaluate_ast_on_x;
+use offchain::gp::minimize::minimize;
+use offchain::push3;
+use offchain::runner::revm_runner::EvmRunner;
+
+/// How many of x in -3..=3 the program maps to 2x + 1.
+fn hits(runner: &mut EvmRunner, ast: &UntypedAst) -> f64 {
+    (-3..=3)
+        .filter(|&x| evaluate_ast_on_x(runner, ast, x) == Some(2 * x + 1))
+        .count() as f64
+}
+
+#[test]
+fn padded_solution_shrinks_to_its_core() {
+    let mut runner = common::runner();
+    // (DUP + 1 +) padded with NOOPs, a nested sublist and a pushed-then-popped
+    // 5 (grouped, as deleting the 5 or the POP alone changes the answer)
+    let padded = push3!((NOOP (DUP NOOP +) (5 POP) 1 NOOP +));
+    let fitness = hits(&mut runner, &padded);
+    assert_eq!(fitness, 7.0);
+
+    let minimized = minimize(&padded, &mut runner, hits, fitness);
+    assert_eq!(minimized, push3!((DUP + 1 +)));
+    assert_eq!(hits(&mut runner, &minimized), fitness);
+}